@@ -1,8 +1,16 @@
+use std::{
+    collections::HashSet,
+    io::{self, Write},
+};
+
+use serde_json::{json, Value};
+
 use crate::{
+    config::Config,
     date::Date,
     get_rand_diary, match_keyword, match_keyword_mut,
     sexp::{Expression, RandIter},
-    syntax_error::{illegal_element, Error, ParseResult},
+    syntax_error::{illegal_element, operand_mismatch, Error, ParseResult},
     unwrap_expr,
 };
 
@@ -29,9 +37,11 @@ pub struct Album {
     name: String,
     published_at: Date,
     link_to_diary: Option<Date>,
+    featured: Vec<String>,
+    tracks: Option<u32>,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum AlbumKind {
     StudioAlbum,
     LiveAlbum,
@@ -44,6 +54,36 @@ impl AlbumIndex {
     const fn new(artists: Vec<Artist>) -> Self {
         Self(artists)
     }
+
+    /// The albums listed more than once under the same artist, in any kinds,
+    /// as `(artist, album)` names, once each.
+    pub fn duplicates(&self) -> Vec<(&str, &str)> {
+        let mut result = Vec::new();
+        for artist in &self.0 {
+            let mut seen = HashSet::new();
+            for kind in AlbumKind::ALL {
+                for album in artist.albums.of_kind(kind) {
+                    let pair = (artist.name.as_str(), album.name.as_str());
+                    if !seen.insert(&album.name) && !result.contains(&pair) {
+                        result.push(pair);
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    /// Keeps only the first album of each name under an artist, with the
+    /// kinds in their default order and the albums of a kind by date.
+    pub fn dedup(&mut self) {
+        for artist in &mut self.0 {
+            let mut seen = HashSet::new();
+            for kind in AlbumKind::ALL {
+                artist.albums.of_kind_mut(kind).retain(|album| seen.insert(album.name.clone()));
+            }
+        }
+        self.0.sort();
+    }
 }
 
 impl Artist {
@@ -110,34 +150,22 @@ impl AlbumList {
             + self.compilation.len()
             + self.concert.len()
     }
-
-    pub fn studio_album(&self) -> &[Album] {
-        self.studio_album.as_slice()
-    }
-
-    pub fn live_album(&self) -> &[Album] {
-        self.live_album.as_slice()
-    }
-
-    pub fn studio_and_live(&self) -> &[Album] {
-        self.studio_and_live.as_slice()
-    }
-
-    pub fn compilation(&self) -> &[Album] {
-        self.compilation.as_slice()
-    }
-
-    pub fn live(&self) -> &[Album] {
-        self.concert.as_slice()
-    }
 }
 
 impl Album {
-    const fn new(name: String, published_at: Date, link_to_diary: Option<Date>) -> Self {
+    const fn new(
+        name: String,
+        published_at: Date,
+        link_to_diary: Option<Date>,
+        featured: Vec<String>,
+        tracks: Option<u32>,
+    ) -> Self {
         Self {
             name,
             published_at,
             link_to_diary,
+            featured,
+            tracks,
         }
     }
 
@@ -148,6 +176,14 @@ impl Album {
     pub fn link_to_diary(&self) -> &Option<Date> {
         &self.link_to_diary
     }
+
+    pub fn featured(&self) -> &[String] {
+        self.featured.as_slice()
+    }
+
+    pub const fn tracks(&self) -> Option<u32> {
+        self.tracks
+    }
 }
 
 impl PartialEq for Album {
@@ -168,6 +204,94 @@ impl Ord for Album {
     }
 }
 
+impl AlbumKind {
+    /// Every kind, in the default order of the sections of `albums.html`.
+    pub const ALL: [AlbumKind; 5] = [
+        AlbumKind::StudioAlbum,
+        AlbumKind::LiveAlbum,
+        AlbumKind::StudioAndLive,
+        AlbumKind::Compilation,
+        AlbumKind::Concert,
+    ];
+
+    /// The keyword of the kind in `albums.lisp`.
+    pub const fn keyword(self) -> &'static str {
+        match self {
+            AlbumKind::StudioAlbum => "studio",
+            AlbumKind::LiveAlbum => "live",
+            AlbumKind::StudioAndLive => "studioandlive",
+            AlbumKind::Compilation => "compilation",
+            AlbumKind::Concert => "concert",
+        }
+    }
+
+    pub fn from_keyword(keyword: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.keyword() == keyword)
+    }
+}
+
+impl AlbumList {
+    pub fn of_kind(&self, kind: AlbumKind) -> &[Album] {
+        match kind {
+            AlbumKind::StudioAlbum => &self.studio_album,
+            AlbumKind::LiveAlbum => &self.live_album,
+            AlbumKind::StudioAndLive => &self.studio_and_live,
+            AlbumKind::Compilation => &self.compilation,
+            AlbumKind::Concert => &self.concert,
+        }
+    }
+
+    fn of_kind_mut(&mut self, kind: AlbumKind) -> &mut Vec<Album> {
+        match kind {
+            AlbumKind::StudioAlbum => &mut self.studio_album,
+            AlbumKind::LiveAlbum => &mut self.live_album,
+            AlbumKind::StudioAndLive => &mut self.studio_and_live,
+            AlbumKind::Compilation => &mut self.compilation,
+            AlbumKind::Concert => &mut self.concert,
+        }
+    }
+}
+
+/// Writes the whole index as JSON for external tools, with the albums of each
+/// artist keyed by their kind as written in `albums.lisp`.
+pub fn write_albums_json<W: Write>(
+    writer: &mut W,
+    config: &Config,
+    index: &AlbumIndex,
+) -> io::Result<()> {
+    let album_json = |album: &Album| {
+        let diary = album.link_to_diary.as_ref().map(|date| {
+            json!({
+                "date": date.format_iso(),
+                "url": config.url(&config.entry_path(date.year(), date.month(), date.day())),
+            })
+        });
+        json!({
+            "name": album.name,
+            "published_at": album.published_at.format_iso(),
+            "featured": album.featured,
+            "tracks": album.tracks,
+            "diary": diary,
+        })
+    };
+    let artists = index
+        .0
+        .iter()
+        .map(|artist| {
+            let albums = AlbumKind::ALL
+                .iter()
+                .map(|&kind| {
+                    let albums = artist.albums.of_kind(kind).iter().map(album_json);
+                    (kind.keyword().to_string(), Value::Array(albums.collect()))
+                })
+                .collect::<serde_json::Map<_, _>>();
+            json!({ "name": artist.name, "albums": albums })
+        })
+        .collect::<Vec<_>>();
+    serde_json::to_writer_pretty(&mut *writer, &json!({ "artists": artists }))?;
+    writeln!(writer)
+}
+
 pub fn parse_albums(expr: Expression) -> ParseResult<AlbumIndex> {
     match expr {
         Expression::Tuple(l) => {
@@ -191,13 +315,7 @@ fn parse_artist(expr: Expression) -> ParseResult<Artist> {
                 let mut album_list = AlbumList::new();
                 for album_expr in rand {
                     let (kind, album) = parse_album(album_expr)?;
-                    (match kind {
-                        AlbumKind::StudioAlbum => &mut album_list.studio_album,
-                        AlbumKind::LiveAlbum => &mut album_list.live_album,
-                        AlbumKind::StudioAndLive => &mut album_list.studio_and_live,
-                        AlbumKind::Compilation => &mut album_list.compilation,
-                        AlbumKind::Concert => &mut album_list.concert
-                    }).push(album);
+                    album_list.of_kind_mut(kind).push(album);
                 }
                 album_list.sort();
                 Ok(Artist::new(name, album_list))
@@ -209,13 +327,35 @@ fn parse_album(expr: Expression) -> ParseResult<(AlbumKind, Album)> {
     fn handle(kind: AlbumKind, mut rand: RandIter) -> ParseResult<(AlbumKind, Album)> {
         let name = get_rand_diary!(rand, Expression::String)?;
         let published_at = get_rand_diary!(rand, Expression::Tuple).and_then(parse_date)?;
-        let link_to_diary = match get_rand_diary!(rand, Expression::Tuple) {
-            Ok(l) => parse_date(l).map(Some),
-            Err(Error::OperandMismatch) => Ok(None),
-            Err(err) => Err(err),
-        }?;
 
-        Ok((kind, Album::new(name, published_at, link_to_diary)))
+        // The rest are either the date of the diary or keyword sub-forms, in any order.
+        let mut link_to_diary = None;
+        let mut featured = Vec::new();
+        let mut tracks = None;
+        for expr in rand {
+            let l = unwrap_expr!(expr, Expression::Tuple).ok_or(Error::IllegalElement)?;
+            if let Some(Expression::Literal(_)) = l.first() {
+                match_keyword_mut! { l, |rand| {
+                    "feat" => {
+                        featured = parse_strings(rand)?;
+                        Ok(())
+                    },
+                    "tracks" => {
+                        tracks = Some(get_rand_diary!(rand, Expression::Integer)?);
+                        if rand.next().is_some() {
+                            return operand_mismatch();
+                        }
+                        Ok(())
+                    }
+                }}?;
+            } else if link_to_diary.is_none() {
+                link_to_diary = Some(parse_date(l)?);
+            } else {
+                return operand_mismatch();
+            }
+        }
+
+        Ok((kind, Album::new(name, published_at, link_to_diary, featured, tracks)))
     }
 
     let l = unwrap_expr!(expr, Expression::Tuple).ok_or(Error::IllegalElement)?;
@@ -228,11 +368,136 @@ fn parse_album(expr: Expression) -> ParseResult<(AlbumKind, Album)> {
     }}
 }
 
+fn parse_strings(rand: RandIter) -> ParseResult<Vec<String>> {
+    rand.map(|expr| unwrap_expr!(expr, Expression::String).ok_or(Error::IllegalElement))
+        .collect()
+}
+
 fn parse_date(expr: Vec<Expression>) -> ParseResult<Date> {
+    if expr.len() != 3 {
+        return Err(Error::DateComponents(expr.len()));
+    }
     let mut iter = expr.into_iter();
     let year = get_rand_diary!(iter, Expression::Integer)?;
     let month = get_rand_diary!(iter, Expression::Integer)?;
     let day = get_rand_diary!(iter, Expression::Integer)?;
-    let date = Date::new(year, month, day).unwrap();
-    Ok(date)
+    Date::new(year, month, day).ok_or(Error::InvalidDate(year, month, day))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::{
+        albums::{parse_albums, write_albums_json, AlbumIndex, AlbumKind},
+        config::Config,
+        date::Date,
+        sexp::SExpParser,
+        string_reader::StringReader,
+        syntax_error::{Error, ParseResult},
+    };
+
+    fn try_parse(txt: &str) -> ParseResult<AlbumIndex> {
+        let reader = StringReader::new(txt.as_bytes()).unwrap().unwrap();
+        let expr = SExpParser::new(reader).parse_expression().unwrap();
+        parse_albums(expr)
+    }
+
+    pub fn parse(txt: &str) -> AlbumIndex {
+        try_parse(txt).unwrap()
+    }
+
+    #[test]
+    fn parse_featured_artists() {
+        let AlbumIndex(artists) = parse(
+            r#"((artist "A"
+                (studio "Solo" (2020 1 1))
+                (studio "Collab" (2021 2 3) (feat "B" "C") (2023 5 5))))"#,
+        );
+        let albums = artists[0].albums().of_kind(AlbumKind::StudioAlbum);
+        assert_eq!(albums[0].name(), "Solo");
+        assert!(albums[0].featured().is_empty());
+        assert_eq!(albums[1].name(), "Collab");
+        assert_eq!(albums[1].featured(), ["B", "C"]);
+        assert!(albums[1].link_to_diary().is_some());
+    }
+
+    #[test]
+    fn parse_track_count() {
+        let AlbumIndex(artists) = parse(
+            r#"((artist "A"
+                (studio "Untracked" (2020 1 1) (feat "B"))
+                (studio "Tracked" (2021 2 3) (tracks 12) (2023 5 5) (feat "C"))))"#,
+        );
+        let albums = artists[0].albums().of_kind(AlbumKind::StudioAlbum);
+        assert_eq!(albums[0].tracks(), None);
+        assert_eq!(albums[1].tracks(), Some(12));
+        assert_eq!(albums[1].featured(), ["C"]);
+        assert!(albums[1].link_to_diary().is_some());
+        assert!(matches!(
+            try_parse(r#"((artist "A" (studio "Tracked" (2021 2 3) (tracks 12 13))))"#),
+            Err(Error::OperandMismatch)
+        ));
+    }
+
+    #[test]
+    fn dedup_albums() {
+        let mut index = parse(
+            r#"((artist "A"
+                (live "X" (2019 1 1))
+                (studio "X" (2021 1 1))
+                (studio "X" (2020 1 1))
+                (studio "Y" (2022 1 1)))
+                (artist "B" (studio "X" (2020 1 1))))"#,
+        );
+        assert_eq!(index.duplicates(), [("A", "X")]);
+        index.dedup();
+        assert!(index.duplicates().is_empty());
+        let albums = index.0[0].albums();
+        assert!(albums.of_kind(AlbumKind::LiveAlbum).is_empty());
+        let studio = albums.of_kind(AlbumKind::StudioAlbum);
+        assert_eq!(studio.iter().map(|album| album.name()).collect::<Vec<_>>(), ["X", "Y"]);
+        assert_eq!(studio[0].published_at, Date::new(2020, 1, 1).unwrap());
+    }
+
+    #[test]
+    fn albums_json() {
+        let index = parse(
+            r#"((artist "A"
+                (live "Live" (2019 7 1))
+                (studio "Collab" (2021 2 3) (feat "B") (tracks 9) (2023 5 5))))"#,
+        );
+        let mut buf = Vec::new();
+        write_albums_json(&mut buf, &Config::default(), &index).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        let artist = &json["artists"][0];
+        assert_eq!(artist["name"], "A");
+        assert_eq!(
+            artist["albums"]["studio"],
+            serde_json::json!([{
+                "name": "Collab",
+                "published_at": "2021-02-03",
+                "featured": ["B"],
+                "tracks": 9,
+                "diary": {"date": "2023-05-05", "url": "/2023/05#05"},
+            }])
+        );
+        assert_eq!(artist["albums"]["live"][0]["diary"], serde_json::Value::Null);
+        assert_eq!(artist["albums"]["live"][0]["tracks"], serde_json::Value::Null);
+        assert_eq!(artist["albums"]["concert"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn reject_malformed_dates() {
+        assert!(matches!(
+            try_parse(r#"((artist "A" (studio "X" (2020 1))))"#),
+            Err(Error::DateComponents(2))
+        ));
+        assert!(matches!(
+            try_parse(r#"((artist "A" (studio "X" (2020 1 1) (2023 5 5 1))))"#),
+            Err(Error::DateComponents(4))
+        ));
+        assert!(matches!(
+            try_parse(r#"((artist "A" (studio "X" (2020 2 30))))"#),
+            Err(Error::InvalidDate(2020, 2, 30))
+        ));
+    }
 }
@@ -1,64 +1,49 @@
 use std::io::{self, Write};
 
 use crate::{
-    albums::{Album, AlbumIndex},
+    albums::{Album, AlbumIndex, AlbumKind},
+    config::Config,
     html::HtmlWriter,
+    layout,
 };
 
 struct AlbumsGenerator<'a, W: Write> {
     writer: HtmlWriter<'a, W>,
+    config: &'a Config,
 }
 
 impl<'a, W: Write> AlbumsGenerator<'a, W> {
-    fn new(writer: &'a mut W) -> Self {
+    fn new(writer: &'a mut W, config: &'a Config) -> Self {
         Self {
-            writer: HtmlWriter::new(writer),
+            writer: HtmlWriter::new(writer).validating(config.validate_html()),
+            config,
         }
     }
 
     fn generate(&mut self, AlbumIndex(artists): AlbumIndex) -> io::Result<()> {
         let title = "Natuka.ge - Albums";
-        self.writer.doctype()?;
-        self.writer.start_attr("html", &[("lang", "ja")])?;
-        self.writer.start("head")?;
-        self.writer.start_attr("meta", &[("charset", "utf-8")])?;
-        self.writer.start("title")?;
-        write!(self.writer, "{}", title)?;
-        self.writer.end("title")?;
-        self.writer.end("head")?;
-        self.writer.start("body")?;
-        self.writer.start("h1")?;
-        write!(self.writer, "{}", title)?;
-        self.writer.end("h1")?;
-        self.writer.start_attr("a", &[("href", "/")])?;
-        write!(self.writer, "ホーム")?;
-        self.writer.end("a")?;
-        write!(self.writer, "へ")?;
+        layout::start_page(&mut self.writer, self.config, "/albums", title)?;
+        layout::home_link(&mut self.writer, self.config)?;
         self.writer.start("hr")?;
         self.writer.start("dl")?;
 
         for artist in artists {
             self.writer.start("dt")?;
             self.writer.start("h2")?;
-            write!(self.writer, "{}", artist.name())?;
+            self.writer.text(artist.name())?;
             self.writer.end("h2")?;
             self.writer.end("dt")?;
             self.writer.start("dd")?;
 
-            let album_list = artist.albums();
-            self.generate_albums("Studio albums", album_list.studio_album())?;
-            self.generate_albums("Live albums", album_list.live_album())?;
-            self.generate_albums("Studio and live album", album_list.studio_and_live())?;
-            self.generate_albums("Compilations", album_list.compilation())?;
-            self.generate_albums("Concerts", album_list.live())?;
+            for kind in self.config.album_order() {
+                self.generate_albums(section_title(kind), artist.albums().of_kind(kind))?;
+            }
 
             self.writer.end("dd")?;
         }
 
         self.writer.end("dl")?;
-        self.writer.end("body")?;
-        self.writer.end("html")?;
-        Ok(())
+        layout::end_page(&mut self.writer, self.config)
     }
 
     fn generate_albums(&mut self, title: &str, albums: &[Album]) -> io::Result<()> {
@@ -66,7 +51,7 @@ impl<'a, W: Write> AlbumsGenerator<'a, W> {
             return Ok(())
         }
         self.writer.start("h3")?;
-        write!(self.writer, "{}", title)?;
+        self.writer.text(title)?;
         self.writer.end("h3")?;
         self.writer.start("ul")?;
         for sa in albums {
@@ -80,32 +65,137 @@ impl<'a, W: Write> AlbumsGenerator<'a, W> {
     }
 
     fn generate_album(&mut self, album: &Album) -> io::Result<()> {
-        write!(self.writer, "{}", album.name())?;
+        self.writer.text(album.name())?;
+        if let Some(tracks) = album.tracks() {
+            let label = self.config.locale().track_count.replace("{}", &tracks.to_string());
+            self.writer.text(&label)?;
+        }
+        if !album.featured().is_empty() {
+            self.writer.raw(" (feat. ")?;
+            self.writer.text(&album.featured().join(", "))?;
+            self.writer.raw(")")?;
+        }
         if let Some(diary) = album.link_to_diary() {
-            write!(self.writer, " (")?;
+            self.writer.raw(" (")?;
             self.writer.start_attr(
                 "a",
                 &[(
                     "href",
-                    &format!("/{}/{:02}#{:02}", diary.year(), diary.month(), diary.day()),
+                    &self.config.path(&self.config.entry_path(
+                        diary.year(),
+                        diary.month(),
+                        diary.day(),
+                    )),
                 )],
             )?;
-            write!(
-                self.writer,
-                "{}/{:02}/{:02}",
-                diary.year(),
-                diary.month(),
-                diary.day()
-            )?;
+            self.writer.text(&diary.format_slash_padded())?;
             self.writer.end("a")?;
-            write!(self.writer, ")")?;
+            self.writer.raw(")")?;
         }
 
         Ok(())
     }
 }
 
-pub fn generate_albums<W: Write>(writer: &mut W, album_index: AlbumIndex) -> io::Result<()> {
-    let mut gen = AlbumsGenerator::new(writer);
+const fn section_title(kind: AlbumKind) -> &'static str {
+    match kind {
+        AlbumKind::StudioAlbum => "Studio albums",
+        AlbumKind::LiveAlbum => "Live albums",
+        AlbumKind::StudioAndLive => "Studio and live album",
+        AlbumKind::Compilation => "Compilations",
+        AlbumKind::Concert => "Concerts",
+    }
+}
+
+pub fn generate_albums<W: Write>(
+    writer: &mut W,
+    config: &Config,
+    album_index: AlbumIndex,
+) -> io::Result<()> {
+    let mut gen = AlbumsGenerator::new(writer, config);
     gen.generate(album_index)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        albums::tests::parse, albums_gen::generate_albums, config::Config,
+        test_util::config_with,
+    };
+
+    #[test]
+    fn render_featured_artists() {
+        let index = parse(r#"((artist "A" (studio "Collab" (2021 2 3) (feat "B" "C"))))"#);
+        let mut buf = Vec::new();
+        generate_albums(&mut buf, &Config::default(), index).unwrap();
+        let html = String::from_utf8(buf).unwrap();
+        assert!(html.contains("<li>Collab (feat. B, C)</li>"));
+    }
+
+    #[test]
+    fn render_sections_in_configured_order() {
+        let index = || {
+            parse(
+                r#"((artist "A"
+                    (studio "S" (2020 1 1))
+                    (live "L" (2020 1 1))
+                    (compilation "C" (2020 1 1))
+                    (concert "K" (2020 1 1))))"#,
+            )
+        };
+        let sections = |config: &Config| {
+            let mut buf = Vec::new();
+            generate_albums(&mut buf, config, index()).unwrap();
+            let html = String::from_utf8(buf).unwrap();
+            html.split("<h3>")
+                .skip(1)
+                .map(|s| s.split('<').next().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            sections(&Config::default()),
+            ["Studio albums", "Live albums", "Compilations", "Concerts"]
+        );
+        let config = config_with(&[("album_order", "concert, compilation")]);
+        assert_eq!(
+            sections(&config),
+            ["Concerts", "Compilations", "Studio albums", "Live albums"]
+        );
+        assert!(Config::from_map(HashMap::from([(
+            "album_order".to_string(),
+            "studio, bootleg".to_string()
+        )]))
+        .is_err());
+    }
+
+    #[test]
+    fn escape_names() {
+        let index = parse(r#"((artist "A & B" (studio "<Untitled>" (2020 1 1))))"#);
+        let mut buf = Vec::new();
+        generate_albums(&mut buf, &Config::default(), index).unwrap();
+        let html = String::from_utf8(buf).unwrap();
+        assert!(html.contains("<h2>A &amp; B</h2>"));
+        assert!(html.contains("<li>&lt;Untitled&gt;</li>"));
+    }
+
+    #[test]
+    fn render_track_count() {
+        let index = parse(
+            r#"((artist "A"
+                (studio "Untracked" (2020 1 1))
+                (studio "Tracked" (2021 2 3) (tracks 12))))"#,
+        );
+        let mut buf = Vec::new();
+        generate_albums(&mut buf, &Config::default(), index).unwrap();
+        let html = String::from_utf8(buf).unwrap();
+        assert!(html.contains("<li>Untracked</li>"));
+        assert!(html.contains("<li>Tracked [12 tracks]</li>"));
+
+        let index = parse(r#"((artist "A" (studio "Tracked" (2021 2 3) (tracks 12))))"#);
+        let mut buf = Vec::new();
+        generate_albums(&mut buf, &config_with(&[("track_count", "（全{}曲）")]), index).unwrap();
+        assert!(String::from_utf8(buf).unwrap().contains("<li>Tracked（全12曲）</li>"));
+    }
+}
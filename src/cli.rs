@@ -0,0 +1,197 @@
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum Error {
+    UnknownArgument(String),
+    MissingValue(String),
+    InvalidValue(String, String),
+    /// `--clean` with `--only`, which would empty `public/` to build one month.
+    CleanWithOnly,
+    /// `--tar` with `--serve` or `--changed-only`, which need the pages in `public/`.
+    TarWithPublic,
+    /// `--port` without `--serve`, which would be ignored.
+    PortWithoutServe,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Args {
+    pub validate_html: bool,
+    /// Where the build report is written as JSON.
+    pub report: Option<PathBuf>,
+    /// A file of `(entry year month day doc)` forms read instead of the directory tree.
+    pub archive: Option<PathBuf>,
+    /// How many images are converted at once.
+    pub jobs: Option<usize>,
+    /// Whether the plain text of each day is written next to the pages.
+    pub emit_txt: bool,
+    /// Whether broken entries of the image cache are reported before the build.
+    pub verify_cache: bool,
+    /// Whether the broken entries of the image cache are removed as well, so
+    /// that the build regenerates them. Implies `verify_cache`.
+    pub purge_cache: bool,
+    /// Whether only the months whose sources are newer than their pages are rebuilt.
+    /// Adding, removing or retitling an entry, or editing the config file, still
+    /// rebuilds everything; a change made only in the environment does not.
+    pub changed_only: bool,
+    /// Whether `public/` is emptied before the build, which needs a full build.
+    pub clean: bool,
+    /// Whether a printable `print.html` is written for each month.
+    pub print: bool,
+    /// The `(year, month)` of the only month page to build, skipping the rest of the site.
+    pub only: Option<(u32, u32)>,
+    /// Where converted images are cached, overriding `CACHE_DIR` and the config file.
+    pub cache_dir: Option<PathBuf>,
+    /// The tar archive the site is written into instead of `public/`.
+    pub tar: Option<PathBuf>,
+    /// Whether `public/` is served over HTTP after the build.
+    pub serve: bool,
+    pub port: Option<u16>,
+}
+
+/// Parses command line arguments, excluding the program name.
+pub fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Args, Error> {
+    let mut result = Args::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--validate-html" => result.validate_html = true,
+            "--emit-txt" => result.emit_txt = true,
+            "--serve" => result.serve = true,
+            "--verify-cache" => result.verify_cache = true,
+            "--purge-cache" => result.purge_cache = true,
+            "--changed-only" => result.changed_only = true,
+            "--clean" => result.clean = true,
+            "--print" => result.print = true,
+            "--port" => {
+                let value = args.next().ok_or(Error::MissingValue(arg.clone()))?;
+                match value.parse() {
+                    Ok(port) => result.port = Some(port),
+                    Err(_) => return Err(Error::InvalidValue(arg, value)),
+                }
+            }
+            "--report" => {
+                let file = args.next().ok_or(Error::MissingValue(arg))?;
+                result.report = Some(PathBuf::from(file));
+            }
+            "--jobs" => {
+                let value = args.next().ok_or(Error::MissingValue(arg.clone()))?;
+                match value.parse() {
+                    Ok(jobs) if jobs > 0 => result.jobs = Some(jobs),
+                    _ => return Err(Error::InvalidValue(arg, value)),
+                }
+            }
+            "--only" => {
+                let value = args.next().ok_or(Error::MissingValue(arg.clone()))?;
+                match parse_month(&value) {
+                    Some(month) => result.only = Some(month),
+                    None => return Err(Error::InvalidValue(arg, value)),
+                }
+            }
+            "--cache-dir" => {
+                let dir = args.next().ok_or(Error::MissingValue(arg))?;
+                result.cache_dir = Some(PathBuf::from(dir));
+            }
+            "--tar" => {
+                let file = args.next().ok_or(Error::MissingValue(arg))?;
+                result.tar = Some(PathBuf::from(file));
+            }
+            "--archive" => {
+                let file = args.next().ok_or(Error::MissingValue(arg))?;
+                result.archive = Some(PathBuf::from(file));
+            }
+            _ => return Err(Error::UnknownArgument(arg)),
+        }
+    }
+    if result.clean && result.only.is_some() {
+        return Err(Error::CleanWithOnly);
+    }
+    if result.tar.is_some() && (result.serve || result.changed_only) {
+        return Err(Error::TarWithPublic);
+    }
+    if result.port.is_some() && !result.serve {
+        return Err(Error::PortWithoutServe);
+    }
+    Ok(result)
+}
+
+/// Parses `YYYY/MM`.
+fn parse_month(value: &str) -> Option<(u32, u32)> {
+    let (year, month) = value.split_once('/')?;
+    let year = year.parse().ok()?;
+    let month = month.parse().ok().filter(|month| (1..=12).contains(month))?;
+    Some((year, month))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::cli::{parse_args, Error};
+
+    fn parse(args: &[&str]) -> Result<crate::cli::Args, Error> {
+        parse_args(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn parse_flags() {
+        assert!(!parse(&[]).unwrap().validate_html);
+        assert!(parse(&["--validate-html"]).unwrap().validate_html);
+        assert!(parse(&["--emit-txt"]).unwrap().emit_txt);
+        assert!(parse(&["--verify-cache"]).unwrap().verify_cache);
+        assert!(parse(&["--purge-cache"]).unwrap().purge_cache);
+        assert!(parse(&["--changed-only"]).unwrap().changed_only);
+        assert!(parse(&["--clean"]).unwrap().clean);
+        assert!(parse(&["--print"]).unwrap().print);
+        assert!(matches!(
+            parse(&["--unknown"]),
+            Err(Error::UnknownArgument(arg)) if arg == "--unknown"
+        ));
+    }
+
+    #[test]
+    fn parse_jobs() {
+        assert_eq!(parse(&["--jobs", "4"]).unwrap().jobs, Some(4));
+        assert!(matches!(parse(&["--jobs", "0"]), Err(Error::InvalidValue(_, _))));
+        assert!(matches!(parse(&["--jobs", "x"]), Err(Error::InvalidValue(_, _))));
+    }
+
+    #[test]
+    fn parse_serve() {
+        let args = parse(&["--serve", "--port", "8080"]).unwrap();
+        assert!(args.serve);
+        assert_eq!(args.port, Some(8080));
+        assert!(matches!(parse(&["--port", "http"]), Err(Error::InvalidValue(_, _))));
+        assert!(matches!(parse(&["--port", "8080"]), Err(Error::PortWithoutServe)));
+    }
+
+    #[test]
+    fn parse_only() {
+        assert_eq!(parse(&["--only", "2023/05"]).unwrap().only, Some((2023, 5)));
+        assert!(matches!(parse(&["--clean", "--only", "2023/05"]), Err(Error::CleanWithOnly)));
+        for value in ["2023", "2023/13", "2023/xx"] {
+            assert!(matches!(parse(&["--only", value]), Err(Error::InvalidValue(_, _))));
+        }
+    }
+
+    #[test]
+    fn parse_tar() {
+        assert_eq!(parse(&["--tar", "site.tar"]).unwrap().tar, Some(PathBuf::from("site.tar")));
+        assert!(matches!(parse(&["--tar"]), Err(Error::MissingValue(_))));
+        for flag in ["--serve", "--changed-only"] {
+            let result = parse(&["--tar", "site.tar", flag]);
+            assert!(matches!(result, Err(Error::TarWithPublic)));
+        }
+    }
+
+    #[test]
+    fn parse_report() {
+        assert_eq!(parse(&[]).unwrap().report, None);
+        assert_eq!(
+            parse(&["--report", "report.json"]).unwrap().report,
+            Some(PathBuf::from("report.json"))
+        );
+        assert!(matches!(
+            parse(&["--report"]),
+            Err(Error::MissingValue(arg)) if arg == "--report"
+        ));
+    }
+}
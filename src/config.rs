@@ -1,6 +1,787 @@
-use std::{io::{Read, self}, string::FromUtf8Error, collections::HashMap};
+use std::{io::{Read, self, BufReader}, string::FromUtf8Error, collections::HashMap, fs::{self, File}, path::{Path, PathBuf}, num::NonZeroUsize, thread};
 
-use crate::string_reader::StringReader;
+use log::debug;
+
+use crate::{
+    albums::AlbumKind,
+    cli::Args,
+    image::{DEFAULT_IMAGE_TEMPLATE, DEFAULT_THUMBNAIL_TEMPLATE},
+    string_reader::StringReader,
+    util::url_scheme,
+};
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    base_url: String,
+    base_path: String,
+    home_href: String,
+    image_dir: String,
+    thumbnail_name: String,
+    image_name: String,
+    strict: bool,
+    validate_html: bool,
+    archive: Option<PathBuf>,
+    jobs: usize,
+    emit_txt: bool,
+    verify_cache: bool,
+    purge_cache: bool,
+    changed_only: bool,
+    clean: bool,
+    cache_dir: PathBuf,
+    only: Option<(u32, u32)>,
+    tar: Option<PathBuf>,
+    normalize_whitespace: bool,
+    group_by_week: bool,
+    merge_images: bool,
+    decorative_alt: bool,
+    number_headings: bool,
+    autolink: bool,
+    archive_page: bool,
+    photo_pages: bool,
+    print_pages: bool,
+    highlight_search: bool,
+    slug_anchors: bool,
+    robots_txt: bool,
+    albums_json: bool,
+    page_granularity: PageGranularity,
+    line_ending: LineEnding,
+    caption_position: CaptionPosition,
+    gallery_layout: GalleryLayout,
+    album_order: Vec<AlbumKind>,
+    duplicate_albums: DuplicateAlbums,
+    footer: bool,
+    related_entries: usize,
+    collapse_length: usize,
+    feed_limit: usize,
+    feed_full_content: bool,
+    critical_css: Option<String>,
+    print_css: Option<String>,
+    missing_image: Option<String>,
+    favicon_url: Option<String>,
+    allowed_schemes: Vec<String>,
+    featured: Vec<String>,
+    disallow: Vec<String>,
+    keep: Vec<String>,
+    alternates: Vec<(String, String)>,
+    locale: Locale,
+}
+
+/// How entries are split into pages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PageGranularity {
+    /// One page per month, with an anchor per day.
+    Month,
+    /// A page per day instead of the month pages; the index links each month
+    /// to its latest day.
+    Day,
+}
+
+/// The newline written to generated files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+}
+
+/// Where the row of per-image captions goes in a gallery.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaptionPosition {
+    Below,
+    Above,
+    /// No caption row at all, even for images with captions.
+    None,
+}
+
+/// How the images of a gallery are laid out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GalleryLayout {
+    /// A table with a row of images and a row of captions.
+    Table,
+    /// A `figure` with its own `figcaption` per image.
+    Figure,
+}
+
+/// What to do with albums listed more than once under the same artist.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DuplicateAlbums {
+    Keep,
+    /// Keep them all, with a warning.
+    Warn,
+    /// Keep only the first, with a warning.
+    Dedup,
+}
+
+/// User-facing strings embedded in generated pages.
+#[derive(Clone, Debug)]
+pub struct Locale {
+    pub home_label: String,
+    pub home_suffix: String,
+    /// `{}` is replaced with the estimated minutes.
+    pub reading_time: String,
+    pub related_label: String,
+    pub read_more: String,
+    pub featured_label: String,
+    /// `{}` is replaced with the ISO week number.
+    pub week_heading: String,
+    pub footer_text: String,
+    pub year_suffix: String,
+    pub month_suffix: String,
+    /// `{}` is replaced with the `M/D` of the latest entry of a month on the index.
+    pub updated_label: String,
+    /// `{date}` and `{weekday}` are replaced in the heading of each day.
+    pub date_heading: String,
+    /// `{series}` and `{part}` are replaced above the parts of a series.
+    pub series_label: String,
+    /// `{}` is replaced with the track count after the name of an album.
+    pub track_count: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            base_url: String::new(),
+            base_path: String::new(),
+            home_href: "/".to_string(),
+            image_dir: "img".to_string(),
+            thumbnail_name: DEFAULT_THUMBNAIL_TEMPLATE.to_string(),
+            image_name: DEFAULT_IMAGE_TEMPLATE.to_string(),
+            strict: false,
+            validate_html: false,
+            archive: None,
+            emit_txt: false,
+            verify_cache: false,
+            purge_cache: false,
+            changed_only: false,
+            clean: false,
+            cache_dir: PathBuf::from("cache"),
+            only: None,
+            tar: None,
+            jobs: thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            normalize_whitespace: false,
+            group_by_week: false,
+            merge_images: false,
+            decorative_alt: false,
+            number_headings: false,
+            autolink: false,
+            archive_page: false,
+            photo_pages: false,
+            print_pages: false,
+            highlight_search: false,
+            slug_anchors: false,
+            robots_txt: false,
+            albums_json: false,
+            page_granularity: PageGranularity::Month,
+            line_ending: LineEnding::Lf,
+            caption_position: CaptionPosition::Below,
+            gallery_layout: GalleryLayout::Table,
+            duplicate_albums: DuplicateAlbums::Warn,
+            album_order: Vec::new(),
+            footer: false,
+            related_entries: 0,
+            collapse_length: 0,
+            feed_limit: 30,
+            feed_full_content: false,
+            critical_css: None,
+            print_css: None,
+            missing_image: None,
+            favicon_url: None,
+            allowed_schemes: vec!["http".to_string(), "https".to_string(), "mailto".to_string()],
+            featured: Vec::new(),
+            disallow: Vec::new(),
+            keep: Vec::new(),
+            alternates: Vec::new(),
+            locale: Locale::default(),
+        }
+    }
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Self {
+            home_label: "ホーム".to_string(),
+            home_suffix: "へ".to_string(),
+            reading_time: "読了目安: 約{}分".to_string(),
+            related_label: "関連する投稿".to_string(),
+            read_more: "続きを読む".to_string(),
+            featured_label: "注目の投稿".to_string(),
+            week_heading: "第{}週".to_string(),
+            footer_text: "Generated with shdiary-gen".to_string(),
+            year_suffix: "年".to_string(),
+            month_suffix: "月".to_string(),
+            updated_label: "最終更新 {}".to_string(),
+            date_heading: "{date} ({weekday})".to_string(),
+            series_label: "{series} 第{part}回".to_string(),
+            track_count: " [{} tracks]".to_string(),
+        }
+    }
+}
+
+impl Config {
+    pub fn from_map(map: HashMap<String, String>) -> ParseResult<Self> {
+        let mut config = Self::default();
+        for (key, value) in map {
+            match key.as_str() {
+                "base_url" => config.base_url = value.trim_end_matches('/').to_string(),
+                "base_path" => config.base_path = value.trim_end_matches('/').to_string(),
+                "home_href" => config.home_href = value,
+                "image_dir" => config.image_dir = value.trim_matches('/').to_string(),
+                "thumbnail_name" => config.thumbnail_name = parse_name_template(&key, value)?,
+                "image_name" => config.image_name = parse_name_template(&key, value)?,
+                "strict" => config.strict = parse_bool(&key, &value)?,
+                "related_entries" => config.related_entries = parse_usize(&key, &value)?,
+                "collapse_length" => config.collapse_length = parse_usize(&key, &value)?,
+                "feed_limit" => config.feed_limit = parse_usize(&key, &value)?,
+                "feed_full_content" => config.feed_full_content = parse_bool(&key, &value)?,
+                "cache_dir" => config.cache_dir = PathBuf::from(value),
+                "critical_css" => {
+                    // Read once here rather than for every page written.
+                    let css = fs::read_to_string(value).map_err(Error::IOError)?;
+                    config.critical_css = Some(css);
+                }
+                "print_css" => config.print_css = Some(value),
+                "missing_image" => config.missing_image = Some(value),
+                "favicon_url" => {
+                    if !value.contains("{host}") {
+                        return Err(Error::ParseError(ParseError::InvalidValue(key, value)));
+                    }
+                    config.favicon_url = Some(value)
+                }
+                "allowed_schemes" => {
+                    config.allowed_schemes = parse_list(&value)
+                        .map(|s| s.to_ascii_lowercase())
+                        .collect()
+                }
+                "featured" => config.featured = parse_list(&value).collect(),
+                "disallow" => config.disallow = parse_list(&value).collect(),
+                "keep" => config.keep = parse_list(&value).collect(),
+                "alternates" => config.alternates = parse_alternates(&key, &value)?,
+                "page_granularity" => {
+                    config.page_granularity = match value.as_str() {
+                        "month" => PageGranularity::Month,
+                        "day" => PageGranularity::Day,
+                        _ => return Err(Error::ParseError(ParseError::InvalidValue(key, value))),
+                    }
+                }
+                "line_ending" => {
+                    config.line_ending = match value.as_str() {
+                        "lf" => LineEnding::Lf,
+                        "crlf" => LineEnding::Crlf,
+                        _ => return Err(Error::ParseError(ParseError::InvalidValue(key, value))),
+                    }
+                }
+                "caption_position" => {
+                    config.caption_position = match value.as_str() {
+                        "below" => CaptionPosition::Below,
+                        "above" => CaptionPosition::Above,
+                        "none" => CaptionPosition::None,
+                        _ => return Err(Error::ParseError(ParseError::InvalidValue(key, value))),
+                    }
+                }
+                "gallery_layout" => {
+                    config.gallery_layout = match value.as_str() {
+                        "table" => GalleryLayout::Table,
+                        "figure" => GalleryLayout::Figure,
+                        _ => return Err(Error::ParseError(ParseError::InvalidValue(key, value))),
+                    }
+                }
+                "duplicate_albums" => {
+                    config.duplicate_albums = match value.as_str() {
+                        "keep" => DuplicateAlbums::Keep,
+                        "warn" => DuplicateAlbums::Warn,
+                        "dedup" => DuplicateAlbums::Dedup,
+                        _ => return Err(Error::ParseError(ParseError::InvalidValue(key, value))),
+                    }
+                }
+                "album_order" => {
+                    config.album_order = parse_list(&value)
+                        .map(|kind| AlbumKind::from_keyword(&kind))
+                        .collect::<Option<_>>()
+                        .ok_or(Error::ParseError(ParseError::InvalidValue(key, value)))?
+                }
+                "footer" => config.footer = parse_bool(&key, &value)?,
+                "highlight_search" => config.highlight_search = parse_bool(&key, &value)?,
+                "slug_anchors" => config.slug_anchors = parse_bool(&key, &value)?,
+                "robots_txt" => config.robots_txt = parse_bool(&key, &value)?,
+                "albums_json" => config.albums_json = parse_bool(&key, &value)?,
+                "archive_page" => config.archive_page = parse_bool(&key, &value)?,
+                "photo_pages" => config.photo_pages = parse_bool(&key, &value)?,
+                "print_pages" => config.print_pages = parse_bool(&key, &value)?,
+                "number_headings" => config.number_headings = parse_bool(&key, &value)?,
+                "autolink" => config.autolink = parse_bool(&key, &value)?,
+                "merge_images" => config.merge_images = parse_bool(&key, &value)?,
+                "decorative_alt" => config.decorative_alt = parse_bool(&key, &value)?,
+                "group_by_week" => config.group_by_week = parse_bool(&key, &value)?,
+                "normalize_whitespace" => {
+                    config.normalize_whitespace = parse_bool(&key, &value)?
+                }
+                "home_label" => config.locale.home_label = value,
+                "home_suffix" => config.locale.home_suffix = value,
+                "reading_time" => config.locale.reading_time = value,
+                "related_label" => config.locale.related_label = value,
+                "read_more" => config.locale.read_more = value,
+                "featured_label" => config.locale.featured_label = value,
+                "week_heading" => config.locale.week_heading = value,
+                "footer_text" => config.locale.footer_text = value,
+                "year_suffix" => config.locale.year_suffix = value,
+                "month_suffix" => config.locale.month_suffix = value,
+                "updated_label" => config.locale.updated_label = value,
+                "date_heading" => config.locale.date_heading = value,
+                "series_label" => config.locale.series_label = value,
+                "track_count" => config.locale.track_count = value,
+                _ => return Err(Error::ParseError(ParseError::UnknownKeyword(key))),
+            }
+        }
+        if config.thumbnail_name == config.image_name {
+            return Err(Error::ParseError(ParseError::InvalidValue(
+                "thumbnail_name".to_string(),
+                config.thumbnail_name,
+            )));
+        }
+        Ok(config)
+    }
+
+    /// Overrides settings with command line arguments.
+    pub fn apply_args(&mut self, args: &Args) {
+        self.validate_html |= args.validate_html;
+        self.emit_txt |= args.emit_txt;
+        self.verify_cache |= args.verify_cache || args.purge_cache;
+        self.purge_cache |= args.purge_cache;
+        self.changed_only |= args.changed_only;
+        self.clean |= args.clean;
+        self.print_pages |= args.print;
+        if args.only.is_some() {
+            self.only = args.only;
+        }
+        if args.tar.is_some() {
+            self.tar = args.tar.clone();
+        }
+        if let Some(cache_dir) = &args.cache_dir {
+            self.cache_dir = cache_dir.clone();
+        }
+        if let Some(jobs) = args.jobs {
+            self.jobs = jobs;
+        }
+        if args.archive.is_some() {
+            self.archive = args.archive.clone();
+        }
+    }
+
+    /// The path the site is served under, without a trailing `/`.
+    pub fn base_path(&self) -> &str {
+        &self.base_path
+    }
+
+    /// The scheme and host the site is served from, or empty if unknown.
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Prepends `base_path` to a site-absolute path such as `/img/foo.webp`.
+    pub fn path(&self, path: &str) -> String {
+        format!("{}{}", self.base_path, path)
+    }
+
+    /// Like `path`, but also prepends `base_url` for use outside the site, e.g. in feeds.
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, self.path(path))
+    }
+
+    /// The home link target; site-absolute targets get `base_path` as well.
+    pub fn home_href(&self) -> String {
+        if self.home_href.starts_with('/') {
+            self.path(&self.home_href)
+        } else {
+            self.home_href.clone()
+        }
+    }
+
+    /// The directory name of images, both in the source tree and the output.
+    pub fn image_dir(&self) -> &str {
+        &self.image_dir
+    }
+
+    /// The file name template of thumbnails, with `{name}` and `{ext}` placeholders.
+    pub fn thumbnail_name(&self) -> &str {
+        &self.thumbnail_name
+    }
+
+    /// The file name template of copied images, with `{name}` and `{ext}` placeholders.
+    pub fn image_name(&self) -> &str {
+        &self.image_name
+    }
+
+    /// Whether questionable but parseable input is rejected.
+    pub const fn strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Whether generated pages are checked for balanced tags.
+    pub const fn validate_html(&self) -> bool {
+        self.validate_html
+    }
+
+    /// How many worker threads convert images; defaults to the number of logical CPUs.
+    pub const fn jobs(&self) -> usize {
+        self.jobs
+    }
+
+    /// Whether a plain text export is written for each day.
+    pub const fn emit_txt(&self) -> bool {
+        self.emit_txt
+    }
+
+    /// Whether broken entries of the image cache are reported before images are converted.
+    pub const fn verify_cache(&self) -> bool {
+        self.verify_cache
+    }
+
+    /// Whether the broken entries found by `verify_cache` are removed as well.
+    pub const fn purge_cache(&self) -> bool {
+        self.purge_cache
+    }
+
+    /// Whether a month is rebuilt only if one of its sources is newer than its
+    /// page, and the index and the feeds only if any month was. Any change to
+    /// the dates, titles, tags or series of the entries, or to the config file,
+    /// rebuilds the whole site.
+    pub const fn changed_only(&self) -> bool {
+        self.changed_only
+    }
+
+    /// Whether the output directory is emptied before the build.
+    pub const fn clean(&self) -> bool {
+        self.clean
+    }
+
+    /// The names of the entries of the output directory left by `clean`.
+    pub fn keep(&self) -> &[String] {
+        &self.keep
+    }
+
+    /// The `(year, month)` of the only month to build; the index and the
+    /// other site-wide files are left as they are.
+    pub const fn only(&self) -> Option<(u32, u32)> {
+        self.only
+    }
+
+    /// The tar archive the site is written into instead of `public/`.
+    pub fn tar(&self) -> Option<&Path> {
+        self.tar.as_deref()
+    }
+
+    /// Where converted images are kept between builds.
+    pub fn cache_dir(&self) -> &Path {
+        &self.cache_dir
+    }
+
+    /// The single file holding every entry, read instead of the directory tree.
+    pub fn archive(&self) -> Option<&Path> {
+        self.archive.as_deref()
+    }
+
+    /// Whether runs of spaces in raw strings are collapsed and trailing ones trimmed.
+    pub const fn normalize_whitespace(&self) -> bool {
+        self.normalize_whitespace
+    }
+
+    /// The footer line at the bottom of every page, if enabled and not empty.
+    pub fn footer(&self) -> Option<&str> {
+        Some(self.locale.footer_text.as_str()).filter(|text| self.footer && !text.is_empty())
+    }
+
+    pub const fn page_granularity(&self) -> PageGranularity {
+        self.page_granularity
+    }
+
+    /// The newline of generated files. Files copied from `source/` are kept as is.
+    pub const fn line_ending(&self) -> LineEnding {
+        self.line_ending
+    }
+
+    pub const fn caption_position(&self) -> CaptionPosition {
+        self.caption_position
+    }
+
+    pub const fn gallery_layout(&self) -> GalleryLayout {
+        self.gallery_layout
+    }
+
+    pub const fn duplicate_albums(&self) -> DuplicateAlbums {
+        self.duplicate_albums
+    }
+
+    /// The order of the kind sections of `albums.html`: the listed kinds
+    /// first, then the rest in the default order.
+    pub fn album_order(&self) -> Vec<AlbumKind> {
+        let mut order = Vec::with_capacity(AlbumKind::ALL.len());
+        for &kind in self.album_order.iter().chain(&AlbumKind::ALL) {
+            if !order.contains(&kind) {
+                order.push(kind);
+            }
+        }
+        order
+    }
+
+    /// The site-absolute path of the entry of a day, without `base_path`.
+    pub fn entry_path(&self, year: u32, month: u32, day: u32) -> String {
+        match self.page_granularity {
+            PageGranularity::Month => format!("/{:04}/{:02}#{:02}", year, month, day),
+            PageGranularity::Day => format!("/{:04}/{:02}/{:02}", year, month, day),
+        }
+    }
+
+    /// Whether adjacent image blocks are merged into one gallery.
+    pub const fn merge_images(&self) -> bool {
+        self.merge_images
+    }
+
+    /// Whether images with neither a caption nor a gallery title get `alt=""`,
+    /// marking them decorative, instead of no `alt` at all.
+    pub const fn decorative_alt(&self) -> bool {
+        self.decorative_alt
+    }
+
+    /// Whether titled entries are anchored by a slug of their title instead
+    /// of their day number in month pages.
+    pub const fn slug_anchors(&self) -> bool {
+        self.slug_anchors
+    }
+
+    /// Whether `robots.txt` is written.
+    pub const fn robots_txt(&self) -> bool {
+        self.robots_txt
+    }
+
+    /// Whether the album index is also written as `albums.json`.
+    pub const fn albums_json(&self) -> bool {
+        self.albums_json
+    }
+
+    /// Site-absolute paths which `robots.txt` asks crawlers not to visit.
+    pub fn disallow(&self) -> &[String] {
+        &self.disallow
+    }
+
+    /// Whether entry pages highlight the terms of a `?q=` query parameter.
+    pub const fn highlight_search(&self) -> bool {
+        self.highlight_search
+    }
+
+    /// Whether `archive.html` listing every entry is generated.
+    pub const fn archive_page(&self) -> bool {
+        self.archive_page
+    }
+
+    /// Whether each month with images gets a `photos.html` gallery of all of them.
+    pub const fn photo_pages(&self) -> bool {
+        self.photo_pages
+    }
+
+    /// Whether each month also gets a `print.html` without navigation, showing
+    /// images at full size.
+    pub const fn print_pages(&self) -> bool {
+        self.print_pages
+    }
+
+    /// Whether headings are prefixed with hierarchical numbers, restarting each day.
+    pub const fn number_headings(&self) -> bool {
+        self.number_headings
+    }
+
+    /// Whether bare `http` and `https` URLs in the text of entries become links.
+    pub const fn autolink(&self) -> bool {
+        self.autolink
+    }
+
+    /// Whether the days of a month page are grouped under ISO week headings.
+    pub const fn group_by_week(&self) -> bool {
+        self.group_by_week
+    }
+
+    /// How many related entries are listed under each day; 0 disables them.
+    pub const fn related_entries(&self) -> usize {
+        self.related_entries
+    }
+
+    /// Days whose plain text is longer than this many characters show only
+    /// their first paragraph until expanded; 0 disables collapsing.
+    pub const fn collapse_length(&self) -> usize {
+        self.collapse_length
+    }
+
+    /// The maximum number of the most recent entries listed in each feed.
+    pub const fn feed_limit(&self) -> usize {
+        self.feed_limit
+    }
+
+    /// Whether feed entries carry the whole HTML of the day besides its summary.
+    pub const fn feed_full_content(&self) -> bool {
+        self.feed_full_content
+    }
+
+    /// The contents of a stylesheet inlined verbatim into the head of every
+    /// page, read from a path relative to the working directory.
+    pub fn critical_css(&self) -> Option<&str> {
+        self.critical_css.as_deref()
+    }
+
+    /// The site-absolute path of a stylesheet linked from every page for print media.
+    pub fn print_css(&self) -> Option<&str> {
+        self.print_css.as_deref()
+    }
+
+    /// An image of the image directory shown in place of the images whose
+    /// sources don't exist, outside strict mode.
+    pub fn missing_image(&self) -> Option<&str> {
+        self.missing_image.as_deref()
+    }
+
+    /// The icon shown before each external link, with `{host}` replaced by
+    /// the host of the link, e.g. `https://icons.example.com/{host}.ico`.
+    pub fn favicon_url(&self, host: &str) -> Option<String> {
+        self.favicon_url
+            .as_ref()
+            .map(|template| template.replace("{host}", host))
+    }
+
+    /// The language and the root URL of each translation of the site,
+    /// linked from the same path of every page as an alternate.
+    pub fn alternates(&self) -> &[(String, String)] {
+        &self.alternates
+    }
+
+    /// Whether links to `url` may be rendered; relative URLs always may.
+    pub fn allows_url(&self, url: &str) -> bool {
+        url_scheme(url).is_none_or(|scheme| self.allowed_schemes.contains(&scheme))
+    }
+
+    /// The `YYYY/MM/DD` dates of the entries featured on the index, as written.
+    pub fn featured(&self) -> &[String] {
+        self.featured.as_slice()
+    }
+
+    pub const fn locale(&self) -> &Locale {
+        &self.locale
+    }
+}
+
+fn parse_list(value: &str) -> impl Iterator<Item = String> + '_ {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+}
+
+/// Parses `lang:url` pairs separated by commas, e.g. `en:https://en.example.com`.
+fn parse_alternates(key: &str, value: &str) -> ParseResult<Vec<(String, String)>> {
+    parse_list(value)
+        .map(|pair| match pair.split_once(':') {
+            Some((lang, url)) if !lang.is_empty() && url_scheme(url).is_some() => {
+                Ok((lang.to_string(), url.trim_end_matches('/').to_string()))
+            }
+            _ => Err(Error::ParseError(ParseError::InvalidValue(
+                key.to_string(),
+                value.to_string(),
+            ))),
+        })
+        .collect()
+}
+
+/// A file name template must contain `{name}`, or every image would get the same name,
+/// and `{ext}`, so that a thumbnail and its image can't share a file.
+fn parse_name_template(key: &str, value: String) -> ParseResult<String> {
+    if value.contains("{name}") && value.contains("{ext}") && !value.contains('/') {
+        Ok(value)
+    } else {
+        Err(Error::ParseError(ParseError::InvalidValue(key.to_string(), value)))
+    }
+}
+
+fn parse_usize(key: &str, value: &str) -> ParseResult<usize> {
+    value.parse().map_err(|_| {
+        Error::ParseError(ParseError::InvalidValue(key.to_string(), value.to_string()))
+    })
+}
+
+fn parse_bool(key: &str, value: &str) -> ParseResult<bool> {
+    match value {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(Error::ParseError(ParseError::InvalidValue(
+            key.to_string(),
+            value.to_string(),
+        ))),
+    }
+}
+
+/// Every key `from_map` takes, so that environment variables can be told
+/// apart from those of other tools without parsing their values.
+const KEYS: &[&str] = &[
+    "base_url", "base_path", "home_href", "image_dir", "thumbnail_name", "image_name", "strict",
+    "related_entries", "collapse_length", "feed_limit", "feed_full_content", "cache_dir",
+    "critical_css", "print_css", "missing_image", "favicon_url", "allowed_schemes", "featured",
+    "disallow", "keep", "alternates", "page_granularity", "line_ending", "caption_position",
+    "gallery_layout", "duplicate_albums", "album_order", "footer", "highlight_search",
+    "slug_anchors", "robots_txt", "albums_json", "archive_page", "photo_pages", "print_pages",
+    "number_headings", "autolink", "merge_images", "decorative_alt", "group_by_week",
+    "normalize_whitespace", "home_label", "home_suffix", "reading_time", "related_label",
+    "read_more", "featured_label", "week_heading", "footer_text", "year_suffix", "month_suffix",
+    "updated_label", "date_heading", "series_label", "track_count",
+];
+
+/// Environment variables named with this prefix and a key in upper case,
+/// e.g. `SHDIARY_BASE_URL`, set that key.
+pub const ENV_PREFIX: &str = "SHDIARY_";
+
+/// The environment variable read as `cache_dir` unless `SHDIARY_CACHE_DIR` is set.
+pub const CACHE_DIR_ENV: &str = "CACHE_DIR";
+
+/// Merges every source of settings. Command line arguments take precedence
+/// over environment variables, which take precedence over the config file,
+/// which takes precedence over the defaults.
+pub fn resolve_config<I: IntoIterator<Item = (String, String)>>(
+    args: &Args,
+    env: I,
+    file: Option<&Path>,
+) -> ParseResult<Config> {
+    let mut map = match file {
+        Some(path) => read_config_file(path)?,
+        None => HashMap::new(),
+    };
+    let mut env_map = HashMap::new();
+    let mut cache_dir = None;
+    for (name, value) in env {
+        if let Some(key) = name.strip_prefix(ENV_PREFIX) {
+            let key = key.to_ascii_lowercase();
+            if KEYS.contains(&key.as_str()) {
+                env_map.insert(key, value);
+            } else {
+                // Other tools may share the prefix; they are no reason to fail.
+                debug!("Ignoring {}, which is not a setting", name);
+            }
+        } else if name == CACHE_DIR_ENV {
+            cache_dir = Some(value);
+        }
+    }
+    if let Some(cache_dir) = cache_dir {
+        env_map.entry("cache_dir".to_string()).or_insert(cache_dir);
+    }
+    map.extend(env_map);
+
+    let mut config = Config::from_map(map)?;
+    config.apply_args(args);
+    Ok(config)
+}
+
+fn read_config_file(path: &Path) -> ParseResult<HashMap<String, String>> {
+    let file = File::open(path).map_err(Error::IOError)?;
+    let reader = StringReader::new(BufReader::new(file)).map_err(Error::IOError)?;
+    match reader {
+        Some(reader) => ParseCtx::new(reader).parse_root(),
+        None => Ok(HashMap::new()),
+    }
+}
 
 pub struct ParseCtx<R: Read> {
     reader: StringReader<R>,
@@ -18,6 +799,7 @@ pub enum ParseError {
     UnexpectedEOF,
     UnexpectedCharacter(u8),
     UnknownKeyword(String),
+    InvalidValue(String, String),
     EmptyKey,
 }
 
@@ -44,10 +826,11 @@ impl<R: Read> ParseCtx<R> {
             if is_eof {
                 break
             }
-            self.parse_key()?;
+            let key = self.parse_key()?;
             self.trim_space_until_value()?;
-            self.parse_value()?;
+            let value = self.parse_value()?;
             self.trim_space_until_break_line()?;
+            result.insert(key, value);
         }
 
         Ok(result)
@@ -78,6 +861,9 @@ impl<R: Read> ParseCtx<R> {
     }
 
     fn parse_value(&mut self) -> ParseResult<String> {
+        if self.chr() == Some(b'"') {
+            return self.parse_quoted_value();
+        }
         let mut result = Vec::new();
         while let Some(chr) = self.chr() {
             match chr {
@@ -98,6 +884,30 @@ impl<R: Read> ParseCtx<R> {
         }
     }
 
+    /// A value in double quotes may contain spaces; `\"` and `\\` are escapes.
+    fn parse_quoted_value(&mut self) -> ParseResult<String> {
+        let mut result = Vec::new();
+        self.seek()?;
+        while let Some(chr) = self.chr() {
+            self.seek()?;
+            match chr {
+                b'"' => return String::from_utf8(result).map_err(Error::Utf8Error),
+                b'\\' => match self.chr() {
+                    Some(escaped @ (b'"' | b'\\')) => {
+                        result.push(escaped);
+                        self.seek()?;
+                    }
+                    Some(other) => return unexpected_chr(other),
+                    None => return unexpected_eof(),
+                },
+                0x0a => return unexpected_chr(chr),
+                _ => result.push(chr),
+            }
+        }
+
+        unexpected_eof()
+    }
+
     fn trim_space(&mut self) -> ParseResult<bool> {
         while let Some(chr) = self.chr() {
             match chr {
@@ -113,6 +923,33 @@ impl<R: Read> ParseCtx<R> {
         Ok(false)
     }
 
+    fn trim_space_until_value(&mut self) -> ParseResult<()> {
+        let mut found_eq = false;
+        while let Some(chr) = self.chr() {
+            match chr {
+                0x20 | 0x09 | 0x0c | 0x0d => {
+                    self.seek()?;
+                }
+                b'=' if !found_eq => {
+                    found_eq = true;
+                    self.seek()?;
+                }
+                0x0a => {
+                    return unexpected_chr(chr)
+                }
+                _ => {
+                    return if found_eq {
+                        Ok(())
+                    } else {
+                        unexpected_chr(chr)
+                    }
+                }
+            }
+        }
+
+        unexpected_eof()
+    }
+
     fn trim_space_until_break_line(&mut self) -> ParseResult<bool> {
         while let Some(chr) = self.chr() {
             match chr {
@@ -140,3 +977,80 @@ fn unexpected_eof<T>() -> Result<T, Error> {
 fn unexpected_chr<T>(chr: u8) -> ParseResult<T> {
     Err(Error::ParseError(ParseError::UnexpectedCharacter(chr)))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, path::Path};
+
+    use crate::{
+        cli::Args,
+        config::{resolve_config, Config, Error, ParseCtx, ParseError, KEYS},
+        string_reader::StringReader,
+        test_util::TempDir,
+    };
+
+    #[test]
+    fn parse_key_values() {
+        let txt = "base_path = /diary\nfoo=bar  \n\n";
+        let reader = StringReader::new(txt.as_bytes()).unwrap().unwrap();
+        let map = ParseCtx::new(reader).parse_root().unwrap();
+        assert_eq!(map.get("base_path").map(String::as_str), Some("/diary"));
+        assert_eq!(map.get("foo").map(String::as_str), Some("bar"));
+        assert_eq!(map.len(), 2);
+    }
+
+    #[test]
+    fn parse_quoted_values() {
+        let txt = "footer_text = \"Made with \\\"care\\\"\"\nempty=\"\"\n";
+        let reader = StringReader::new(txt.as_bytes()).unwrap().unwrap();
+        let map = ParseCtx::new(reader).parse_root().unwrap();
+        assert_eq!(
+            map.get("footer_text").map(String::as_str),
+            Some("Made with \"care\"")
+        );
+        assert_eq!(map.get("empty").map(String::as_str), Some(""));
+    }
+
+    #[test]
+    fn resolve_precedence() {
+        let dir = TempDir::new();
+        let file = dir.write("shdiary.conf", b"cache_dir = from-file\n");
+        let env = || vec![("SHDIARY_CACHE_DIR".to_string(), "from-env".to_string())];
+        let args = Args {
+            cache_dir: Some("from-args".into()),
+            ..Args::default()
+        };
+        let resolve = |args: &Args, env: Vec<(String, String)>, file: Option<&Path>| {
+            resolve_config(args, env, file).unwrap().cache_dir().to_path_buf()
+        };
+
+        assert_eq!(resolve(&args, env(), Some(&file)), Path::new("from-args"));
+        assert_eq!(resolve(&Args::default(), env(), Some(&file)), Path::new("from-env"));
+        assert_eq!(resolve(&Args::default(), vec![], Some(&file)), Path::new("from-file"));
+        assert_eq!(resolve(&Args::default(), vec![], None), Path::new("cache"));
+
+        let legacy = vec![("CACHE_DIR".to_string(), "legacy".to_string())];
+        assert_eq!(resolve(&Args::default(), legacy.clone(), None), Path::new("legacy"));
+        let both = [legacy, env()].concat();
+        assert_eq!(resolve(&Args::default(), both, None), Path::new("from-env"));
+
+        let stray = vec![("SHDIARY_UNRELATED".to_string(), "x".to_string())];
+        assert_eq!(resolve(&Args::default(), [stray, env()].concat(), None), Path::new("from-env"));
+        let file = dir.write("bad.conf", b"unrelated = x\n");
+        assert!(resolve_config(&Args::default(), vec![], Some(&file)).is_err());
+    }
+
+    #[test]
+    fn known_keys_are_taken() {
+        for key in KEYS {
+            let map = HashMap::from([(key.to_string(), String::new())]);
+            assert!(
+                !matches!(
+                    Config::from_map(map),
+                    Err(Error::ParseError(ParseError::UnknownKeyword(_)))
+                ),
+                "{key} is not taken by from_map"
+            );
+        }
+    }
+}
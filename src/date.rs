@@ -1,6 +1,9 @@
 use std::fmt;
 
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+/// The number of days before each month in a common year.
+const CUMULATIVE_DAYS: [u32; 12] = [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334];
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Date {
     year: u32,
     month: u32,
@@ -21,8 +24,7 @@ impl Date {
                 }
             }
             2 => {
-                let is_leap = (year % 4 == 0 && year % 100 != 0) || year % 400 == 0;
-                if day < 1 || day > (if is_leap { 29 } else { 28 }) {
+                if day < 1 || day > (if is_leap_year(year) { 29 } else { 28 }) {
                     return None;
                 }
             }
@@ -31,6 +33,30 @@ impl Date {
         Some(Self { year, month, day })
     }
 
+    /// The inverse of `ordinal`; `None` unless `ordinal` is within the year.
+    pub fn from_ordinal(year: u32, ordinal: u32) -> Option<Self> {
+        let leap_day = |month: u32| u32::from(month > 2 && is_leap_year(year));
+        let month = (1..=12)
+            .rev()
+            .find(|&month| CUMULATIVE_DAYS[month as usize - 1] + leap_day(month) < ordinal)?;
+        Self::new(
+            year,
+            month,
+            ordinal - CUMULATIVE_DAYS[month as usize - 1] - leap_day(month),
+        )
+    }
+
+    /// Parses `YYYY/MM/DD`.
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut parts = s.split('/').map(|p| p.parse::<u32>().ok());
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(Some(year)), Some(Some(month)), Some(Some(day)), None) => {
+                Self::new(year, month, day)
+            }
+            _ => None,
+        }
+    }
+
     pub const fn year(&self) -> u32 {
         self.year
     }
@@ -43,17 +69,49 @@ impl Date {
         self.day
     }
 
-    pub const fn weekday_ja(&self) -> &str {
-        let month = if self.month <= 2 {
-            self.month + 12
+    /// The day of the week, from 1 (Monday) to 7 (Sunday).
+    pub const fn weekday(&self) -> u32 {
+        let (month, year) = if self.month <= 2 {
+            (self.month + 12, self.year - 1)
         } else {
-            self.month
+            (self.month, self.year)
         };
 
-        let c = self.year / 100;
-        let y = self.year % 100;
-        let d = ((self.day + 26 * (month + 1) / 10 + y + y / 4 + (5 * c + c / 4) + 5) % 7) + 1;
-        match d {
+        let c = year / 100;
+        let y = year % 100;
+        ((self.day + 26 * (month + 1) / 10 + y + y / 4 + (5 * c + c / 4) + 5) % 7) + 1
+    }
+
+    /// The day of the year, starting from 1.
+    pub const fn ordinal(&self) -> u32 {
+        let leap_day = if self.month > 2 && is_leap_year(self.year) { 1 } else { 0 };
+        CUMULATIVE_DAYS[self.month as usize - 1] + leap_day + self.day
+    }
+
+    /// The ISO 8601 week-numbering year and week of the date.
+    pub const fn iso_week(&self) -> (u32, u32) {
+        let week = (self.ordinal() + 10 - self.weekday()) / 7;
+        if week == 0 {
+            (self.year - 1, iso_weeks_in_year(self.year - 1))
+        } else if week > iso_weeks_in_year(self.year) {
+            (self.year + 1, 1)
+        } else {
+            (self.year, week)
+        }
+    }
+
+    /// `YYYY-MM-DD`, as in ISO 8601.
+    pub fn format_iso(&self) -> String {
+        format!("{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+
+    /// `YYYY/MM/DD`, unlike `Display` which doesn't pad the month and the day.
+    pub fn format_slash_padded(&self) -> String {
+        format!("{:04}/{:02}/{:02}", self.year, self.month, self.day)
+    }
+
+    pub const fn weekday_ja(&self) -> &str {
+        match self.weekday() {
             1 => "月",
             2 => "火",
             3 => "水",
@@ -71,3 +129,70 @@ impl fmt::Display for Date {
         write!(f, "{}/{}/{}", self.year, self.month, self.day)
     }
 }
+
+const fn is_leap_year(year: u32) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+/// A year has 53 ISO weeks when it starts on a Thursday, or on a Wednesday in leap years.
+const fn iso_weeks_in_year(year: u32) -> u32 {
+    let jan1 = match Date::new(year, 1, 1) {
+        Some(date) => date.weekday(),
+        None => unreachable!(),
+    };
+    if jan1 == 4 || (jan1 == 3 && is_leap_year(year)) {
+        53
+    } else {
+        52
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::date::Date;
+
+    fn date(year: u32, month: u32, day: u32) -> Date {
+        Date::new(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn weekdays() {
+        assert_eq!(date(2023, 5, 5).weekday_ja(), "金");
+        assert_eq!(date(2024, 1, 1).weekday_ja(), "月");
+        assert_eq!(date(2024, 2, 29).weekday_ja(), "木");
+    }
+
+    #[test]
+    fn iso_weeks() {
+        assert_eq!(date(2023, 5, 5).iso_week(), (2023, 18));
+        assert_eq!(date(2021, 1, 3).iso_week(), (2020, 53));
+        assert_eq!(date(2021, 1, 4).iso_week(), (2021, 1));
+        assert_eq!(date(2022, 1, 1).iso_week(), (2021, 52));
+        assert_eq!(date(2024, 12, 30).iso_week(), (2025, 1));
+        assert_eq!(date(2026, 12, 31).iso_week(), (2026, 53));
+        assert_eq!(date(2027, 1, 1).iso_week(), (2026, 53));
+    }
+
+    #[test]
+    fn zero_padded_formats() {
+        assert_eq!(date(2023, 5, 5).format_iso(), "2023-05-05");
+        assert_eq!(date(2023, 5, 5).format_slash_padded(), "2023/05/05");
+        assert_eq!(date(2023, 12, 31).format_iso(), "2023-12-31");
+        assert_eq!(date(2023, 1, 9).to_string(), "2023/1/9");
+    }
+
+    #[test]
+    fn from_ordinal() {
+        assert_eq!(Date::from_ordinal(2024, 60), Some(date(2024, 2, 29)));
+        assert_eq!(Date::from_ordinal(2023, 60), Some(date(2023, 3, 1)));
+        assert_eq!(Date::from_ordinal(2024, 366), Some(date(2024, 12, 31)));
+        assert_eq!(Date::from_ordinal(2024, 367), None);
+        assert_eq!(Date::from_ordinal(2023, 365), Some(date(2023, 12, 31)));
+        assert_eq!(Date::from_ordinal(2023, 366), None);
+        assert_eq!(Date::from_ordinal(2023, 0), None);
+        assert_eq!(Date::from_ordinal(2023, 1), Some(date(2023, 1, 1)));
+        for ordinal in 1..=366 {
+            assert_eq!(Date::from_ordinal(2024, ordinal).unwrap().ordinal(), ordinal);
+        }
+    }
+}
@@ -1,18 +1,49 @@
-use crate::sexp::{Expression, RandIter};
+use std::collections::BTreeSet;
+
+use crate::config::Config;
+use crate::date::Date;
+use crate::sexp::{self, count_empty_tuples, Expression, ParseError, RandIter, SExpParser};
+use crate::string_reader::StringReader;
 use crate::syntax_error::ParseResult;
-use crate::syntax_error::{illegal_element, Error};
-use crate::{get_rand, get_rand_diary, match_keyword, parse_diary_func, unwrap_expr};
+use crate::syntax_error::{illegal_element, operand_mismatch, Error};
+use crate::{
+    get_rand, get_rand_diary, match_keyword, match_keyword_mut, parse_diary_func, unwrap_expr,
+};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Document<T: Sized + Clone> {
     contents: Vec<Item<T>>,
+    meta: DocumentMeta,
+}
+
+/// The leading `(meta ...)` block of a day, which takes precedence over the
+/// metadata items scattered among the contents.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DocumentMeta {
+    pub title: Option<String>,
+    pub tags: Vec<String>,
+    pub summary: Option<String>,
+    /// Drafts are parsed but left out of the site.
+    pub draft: bool,
 }
 
 pub type SourceDoucument = Document<String>;
 
 impl<T: Sized + Clone> Document<T> {
-    pub const fn new(contents: Vec<Item<T>>) -> Self {
-        Document { contents }
+    pub fn new(contents: Vec<Item<T>>) -> Self {
+        Document {
+            contents,
+            meta: DocumentMeta::default(),
+        }
+    }
+
+    pub fn with_meta(mut self, meta: DocumentMeta) -> Self {
+        self.meta = meta;
+        self
+    }
+
+    pub const fn meta(&self) -> &DocumentMeta {
+        &self.meta
     }
 
     pub const fn contents(self: &Self) -> &Vec<Item<T>> {
@@ -22,76 +53,756 @@ impl<T: Sized + Clone> Document<T> {
     pub fn into_contents(self) -> Vec<Item<T>> {
         self.contents
     }
+
+    pub fn tags(&self) -> Vec<&str> {
+        if !self.meta.tags.is_empty() {
+            return self.meta.tags.iter().map(String::as_str).collect();
+        }
+        self.contents
+            .iter()
+            .filter_map(|item| unwrap_expr!(item, Item::Tags))
+            .flatten()
+            .map(String::as_str)
+            .collect()
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        self.meta.title.as_deref().or_else(|| {
+            self.contents
+                .iter()
+                .find_map(|item| unwrap_expr!(item, Item::Title))
+                .map(String::as_str)
+        })
+    }
+
+    pub fn lang(&self) -> Option<&str> {
+        self.contents
+            .iter()
+            .find_map(|item| unwrap_expr!(item, Item::Lang))
+            .map(String::as_str)
+    }
+
+    /// The name of the series of the day and its part number.
+    pub fn series(&self) -> Option<(&str, u32)> {
+        self.contents.iter().find_map(|item| match item {
+            Item::Series { name, part } => Some((name.as_str(), *part)),
+            _ => None,
+        })
+    }
+
+    /// The latitude, the longitude and the label of the first `(geo ...)`.
+    pub fn geo(&self) -> Option<(f64, f64, &str)> {
+        self.contents.iter().find_map(|item| match item {
+            Item::Geo { lat, lon, label } => Some((*lat, *lon, label.as_str())),
+            _ => None,
+        })
+    }
+
+    pub fn classes(&self) -> Vec<&str> {
+        self.contents
+            .iter()
+            .filter_map(|item| unwrap_expr!(item, Item::Class))
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// The summary of the `(meta ...)` block, or else the plain text of the first paragraph.
+    pub fn summary(&self) -> Option<String> {
+        if let Some(summary) = &self.meta.summary {
+            return Some(summary.clone());
+        }
+        self.contents
+            .iter()
+            .find_map(|item| unwrap_expr!(item, Item::Text))
+            .map(plain_text)
+    }
+
+    /// Every inline item of the paragraphs, including those in lists, in document order.
+    pub fn text_items(&self) -> Vec<&TextItem> {
+        let mut result = Vec::new();
+        for item in &self.contents {
+            item.push_text_items(&mut result);
+        }
+        result
+    }
+
+    /// Every image of the document, including those in lists, in document order.
+    pub fn images(&self) -> Vec<&ImageItem<T>> {
+        self.galleries().into_iter().flat_map(|images| &images.items).collect()
+    }
+
+    /// Every `(img ...)` of the document, including those in lists, in document order.
+    pub fn galleries(&self) -> Vec<&Images<T>> {
+        let mut result = Vec::new();
+        for item in &self.contents {
+            item.push_galleries(&mut result);
+        }
+        result
+    }
+
+    /// The ids of every `(anchor ...)`, including those in nested blocks.
+    pub fn anchors(&self) -> Vec<&str> {
+        let mut result = Vec::new();
+        for item in &self.contents {
+            item.push_anchors(&mut result);
+        }
+        result
+    }
+
+    pub fn web_links(&self) -> Vec<&WebLink> {
+        self.text_items()
+            .into_iter()
+            .filter_map(|item| unwrap_expr!(item, TextItem::WebLink))
+            .collect()
+    }
+
+    /// Flattens the document into plain text blocks (headers, paragraphs,
+    /// list items, captions), in document order.
+    pub fn plain_blocks(&self) -> Vec<String> {
+        let mut blocks = Vec::new();
+        for item in &self.contents {
+            item.push_plain_blocks(&mut blocks);
+        }
+        blocks
+    }
+}
+
+impl<T: Sized + Clone> Item<T> {
+    fn push_galleries<'a>(&'a self, galleries: &mut Vec<&'a Images<T>>) {
+        match self {
+            Item::Images(imgs) => galleries.push(imgs),
+            Item::List(li)
+            | Item::OrderedList(li)
+            | Item::ListEntry(li)
+            | Item::Details { body: li, .. }
+            | Item::At { body: li, .. }
+            | Item::Quote { body: li, .. } => {
+                for item in li {
+                    item.push_galleries(galleries);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn push_anchors<'a>(&'a self, anchors: &mut Vec<&'a str>) {
+        match self {
+            Item::Anchor(id) => anchors.push(id),
+            Item::List(li)
+            | Item::OrderedList(li)
+            | Item::ListEntry(li)
+            | Item::Details { body: li, .. }
+            | Item::At { body: li, .. }
+            | Item::Quote { body: li, .. } => {
+                for item in li {
+                    item.push_anchors(anchors);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn push_text_items<'a>(&'a self, items: &mut Vec<&'a TextItem>) {
+        match self {
+            Item::Text(txt) => items.extend(txt),
+            Item::List(li)
+            | Item::OrderedList(li)
+            | Item::ListEntry(li)
+            | Item::Details { body: li, .. }
+            | Item::At { body: li, .. }
+            | Item::Quote { body: li, .. } => {
+                for item in li {
+                    item.push_text_items(items);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn push_plain_blocks(&self, blocks: &mut Vec<String>) {
+        match self {
+            Item::Text(txt) => blocks.push(plain_text(txt)),
+            Item::List(li) | Item::OrderedList(li) | Item::ListEntry(li) => {
+                for item in li {
+                    item.push_plain_blocks(blocks);
+                }
+            }
+            Item::Header(_, txt) => blocks.push(txt.clone()),
+            Item::Details { summary, body } => {
+                blocks.push(summary.clone());
+                for item in body {
+                    item.push_plain_blocks(blocks);
+                }
+            }
+            Item::At { body, .. } => {
+                for item in body {
+                    item.push_plain_blocks(blocks);
+                }
+            }
+            Item::Quote { body, cite } => {
+                for item in body {
+                    item.push_plain_blocks(blocks);
+                }
+                blocks.extend(cite.clone());
+            }
+            Item::Geo { label, .. } => blocks.push(label.clone()),
+            Item::Anchor(_)
+            | Item::Tags(_)
+            | Item::Title(_)
+            | Item::Class(_)
+            | Item::Lang(_)
+            | Item::Series { .. } => {}
+            Item::Images(images) => {
+                if !images.title.is_empty() {
+                    blocks.push(images.title.clone());
+                }
+                blocks.extend(images.items.iter().filter_map(|i| i.caption.clone()));
+                blocks.extend(images.caption.clone());
+            }
+        }
+    }
 }
 
-#[derive(Clone, Debug)]
+pub fn plain_text(txt: &Text) -> String {
+    let mut result = String::new();
+    for item in txt {
+        match item {
+            TextItem::RawString(s)
+            | TextItem::Bold(s)
+            | TextItem::Italic(s)
+            | TextItem::Strike(s)
+            | TextItem::Mark(s)
+            | TextItem::Code(s)
+            | TextItem::Sup(s)
+            | TextItem::Sub(s)
+            | TextItem::Math(s) => result.push_str(s),
+            TextItem::WebLink(link) => result.push_str(&link.title),
+            TextItem::PostLink((year, month, day)) => {
+                result.push_str(&format!("{:04}/{:02}/{:02}", year, month, day))
+            }
+            TextItem::Ruby { base, .. } => result.push_str(base),
+            TextItem::Abbr { text, .. } => result.push_str(text),
+            TextItem::Break => result.push(' '),
+        }
+    }
+    result
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum Item<T: Sized + Clone> {
     Text(Text),
     List(Vec<Item<T>>),
-    Header(String),
+    /// A `List` whose entries are numbered.
+    OrderedList(Vec<Item<T>>),
+    /// An entry of a `List` made of several blocks, such as paragraphs.
+    ListEntry(Vec<Item<T>>),
+    /// A heading of level 2 to 6; the page takes 1, and days default to 3
+    /// under their date, which is at level 2.
+    Header(u32, String),
     Images(Images<T>),
+    Anchor(String),
+    Tags(Vec<String>),
+    Title(String),
+    Class(String),
+    /// A language tag overriding the language of the page for the day.
+    Lang(String),
+    /// A disclosure block whose body is hidden until the summary is clicked.
+    Details { summary: String, body: Vec<Item<T>> },
+    /// A quotation, with the source it is attributed to if any.
+    Quote { body: Vec<Item<T>>, cite: Option<String> },
+    /// A place the day is about, in degrees.
+    Geo { lat: f64, lon: f64, label: String },
+    /// A part of a day written at `time`, in hours and minutes. The parts of
+    /// a day are sorted by their times among themselves.
+    At { time: (u32, u32), body: Vec<Item<T>> },
+    /// Makes the day the `part`th, counted from 1, of the entries named `name`.
+    Series { name: String, part: u32 },
 }
 
 pub type SourceItem = Item<String>;
 
 pub type Text = Vec<TextItem>;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum TextItem {
     RawString(String),
     Bold(String),
+    /// Emphasized text.
+    Italic(String),
+    /// Text crossed out as a correction.
+    Strike(String),
+    /// Highlighted text.
+    Mark(String),
     WebLink(WebLink),
     PostLink((u32, u32, u32)),
     Code(String),
+    Sup(String),
+    Sub(String),
+    Ruby { base: String, reading: String },
+    Break,
+    /// TeX source left for a client-side renderer such as KaTeX or MathJax.
+    Math(String),
+    /// An abbreviation with its expansion shown as a tooltip.
+    Abbr { text: String, title: String },
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct WebLink {
     pub title: String,
     pub href: String,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Images<T: Sized + Clone> {
     pub title: String,
     pub items: Vec<ImageItem<T>>,
+    /// A caption of the whole gallery, given by `(caption ...)`.
+    pub caption: Option<String>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct ImageItem<T: Sized + Clone> {
     pub data: T,
     pub caption: Option<String>,
 }
 
-pub fn parse_diary_content(expr: Expression) -> ParseResult<SourceDoucument> {
+/// An error of `parse_str`, either in the S-expression or in the document it holds.
+#[derive(Debug)]
+pub enum StrError {
+    Read(sexp::Error),
+    Syntax(Error),
+}
+
+/// Parses a whole document such as `((txt "a") (img "" ("b.webp")))` with the default config.
+pub fn parse_str(s: &str) -> Result<SourceDoucument, StrError> {
+    parse_str_with(s, &Config::default())
+}
+
+pub fn parse_str_with(s: &str, config: &Config) -> Result<SourceDoucument, StrError> {
+    let reader = StringReader::new(s.as_bytes())
+        .map_err(|err| StrError::Read(sexp::Error::IOError(err)))?
+        .ok_or(StrError::Read(sexp::Error::ParseError(ParseError::UnexpectedEOF)))?;
+    let mut parser = SExpParser::new(reader);
+    let expr = parser.parse_expression().map_err(StrError::Read)?;
+    parse_diary_content(expr, config, parser.empty_tuples()).map_err(StrError::Syntax)
+}
+
+/// `empty_tuples` are the positions of the `()` in `expr` as recorded by
+/// `SExpParser`, by which an empty form is located.
+pub fn parse_diary_content(
+    expr: Expression,
+    config: &Config,
+    mut empty_tuples: &[(usize, usize)],
+) -> ParseResult<SourceDoucument> {
+    let mut list = unwrap_expr!(expr, Expression::Tuple).ok_or(Error::IllegalElement)?;
+    let meta = match list.first() {
+        Some(Expression::Tuple(t)) if is_form(t, "meta") => match list.remove(0) {
+            Expression::Tuple(t) => {
+                empty_tuples = skip_positions(empty_tuples, t.iter().map(count_empty_tuples).sum());
+                parse_meta(t)?
+            }
+            _ => unreachable!(),
+        },
+        _ => DocumentMeta::default(),
+    };
+    let mut items = parse_top_list(list, config.strict(), empty_tuples)?;
+    sort_timed_parts(&mut items);
+    if config.normalize_whitespace() {
+        normalize_items(&mut items);
+    }
+    if config.merge_images() {
+        items = merge_adjacent_images(items);
+    }
+    let doc = Document::new(items).with_meta(meta);
+    if config.strict() {
+        check_link_schemes(&doc, config)?;
+    }
+    Ok(doc)
+}
+
+fn check_link_schemes(doc: &SourceDoucument, config: &Config) -> ParseResult<()> {
+    match doc.web_links().into_iter().find(|link| !config.allows_url(&link.href)) {
+        Some(link) => Err(Error::DisallowedScheme(link.href.clone())),
+        None => Ok(()),
+    }
+}
+
+/// Parses a packed archive, a list of `(entry year month day (items...))` forms.
+/// `empty_tuples` are as for `parse_diary_content`.
+pub fn parse_archive(
+    expr: Expression,
+    config: &Config,
+    mut empty_tuples: &[(usize, usize)],
+) -> ParseResult<Vec<(Date, SourceDoucument)>> {
+    let list = unwrap_expr!(expr, Expression::Tuple).ok_or(Error::IllegalElement)?;
+    let mut seen = BTreeSet::new();
+    let mut entries = Vec::with_capacity(list.len());
+    for entry in list {
+        // The date before the document has no `()`, so the entry's are its own.
+        let doc_tuples = empty_tuples;
+        empty_tuples = skip_positions(empty_tuples, count_empty_tuples(&entry));
+        let l = unwrap_expr!(entry, Expression::Tuple).ok_or(Error::IllegalElement)?;
+        let (date, doc) = match_keyword_mut! { l, |rand| {
+            "entry" => {
+                let year = get_rand_diary!(rand, Expression::Integer)?;
+                let month = get_rand_diary!(rand, Expression::Integer)?;
+                let day = get_rand_diary!(rand, Expression::Integer)?;
+                let date = Date::new(year, month, day).ok_or(Error::InvalidDate(year, month, day))?;
+                let doc = rand.next().ok_or(Error::OperandMismatch)?;
+                if rand.next().is_some() {
+                    return operand_mismatch();
+                }
+                Ok((date, parse_diary_content(doc, config, doc_tuples)?))
+            }
+        }}?;
+        if !seen.insert(date.clone()) {
+            return Err(Error::DuplicateEntry(date));
+        }
+        entries.push((date, doc));
+    }
+    Ok(entries)
+}
+
+/// Merges each image block into the one right before it, unless it has a
+/// title of its own, so that they render as a single gallery. Blocks with a
+/// shared caption are kept apart, as the caption wouldn't fit the others.
+fn merge_adjacent_images(items: Vec<SourceItem>) -> Vec<SourceItem> {
+    let mut result: Vec<SourceItem> = Vec::with_capacity(items.len());
+    for item in items {
+        match (result.last_mut(), item) {
+            (Some(Item::Images(prev)), Item::Images(next))
+                if (next.title.is_empty() || next.title == prev.title)
+                    && prev.caption.is_none()
+                    && next.caption.is_none() =>
+            {
+                prev.items.extend(next.items);
+            }
+            (_, item) => result.push(item),
+        }
+    }
+    result
+}
+
+fn normalize_items(items: &mut [SourceItem]) {
+    for item in items {
+        match item {
+            Item::Text(txt) => {
+                let last = txt.len().saturating_sub(1);
+                for (i, text_item) in txt.iter_mut().enumerate() {
+                    if let TextItem::RawString(s) = text_item {
+                        *s = normalize_whitespace(s, i == last);
+                    }
+                }
+            }
+            Item::List(li)
+            | Item::OrderedList(li)
+            | Item::ListEntry(li)
+            | Item::Details { body: li, .. }
+            | Item::At { body: li, .. }
+            | Item::Quote { body: li, .. } => {
+                normalize_items(li)
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Collapses runs of spaces and trims trailing whitespace from each line.
+/// The end of the string is only trimmed with `trim_end`, since it may be
+/// followed by inline markup such as `(b ...)` in the same paragraph.
+fn normalize_whitespace(s: &str, trim_end: bool) -> String {
+    let line_count = s.split('\n').count();
+    s.split('\n')
+        .enumerate()
+        .map(|(i, line)| {
+            let line = if trim_end || i + 1 < line_count {
+                line.trim_end()
+            } else {
+                line
+            };
+            let mut result = String::with_capacity(line.len());
+            for c in line.chars() {
+                if !(c == ' ' && result.ends_with(' ')) {
+                    result.push(c);
+                }
+            }
+            result
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn parse_top_list(
+    list: Vec<Expression>,
+    strict: bool,
+    mut empty_tuples: &[(usize, usize)],
+) -> ParseResult<Vec<SourceItem>> {
+    list.into_iter()
+        .map(|expr| match expr {
+            Expression::Tuple(t) if t.is_empty() => {
+                let (line, column) = empty_tuples.first().copied().unwrap_or_default();
+                Err(Error::EmptyForm(line, column))
+            }
+            expr => {
+                empty_tuples = skip_positions(empty_tuples, count_empty_tuples(&expr));
+                parse_top_expr(expr, strict)
+            }
+        })
+        .collect()
+}
+
+/// `positions` without those of the first `n` empty tuples.
+fn skip_positions(positions: &[(usize, usize)], n: usize) -> &[(usize, usize)] {
+    positions.get(n..).unwrap_or_default()
+}
+
+/// In strict mode only forms are accepted, so that a misplaced string
+/// doesn't silently become a stray paragraph.
+fn parse_top_expr(expr: Expression, strict: bool) -> ParseResult<SourceItem> {
     match expr {
-        Expression::Tuple(l) => parse_top_list(l).map(Document::new),
+        Expression::Tuple(t) => {
+            match_keyword! { t, |rand| {
+                "h" | "header" => parse_header(rand),
+                "txt" | "text" => parse_text(rand),
+                "li" | "list" => parse_list(rand),
+                "ol" | "ordered" => parse_ordered_list(rand),
+                "img" | "image" => parse_image(rand),
+                "anchor" => parse_anchor(rand),
+                "tags" => parse_tags(rand),
+                "title" => parse_title(rand),
+                "class" => parse_class(rand),
+                "lang" => parse_lang(rand),
+                "series" => parse_series(rand),
+                "details" => parse_details(rand, strict),
+                "quote" => parse_quote(rand, strict),
+                "geo" => parse_geo(rand),
+                "at" => parse_at(rand, strict)
+            }}
+        }
+        Expression::String(s) if !strict => Ok(Item::Text(vec![TextItem::RawString(s)])),
+        Expression::BackQuotedString(s) if !strict => Ok(Item::Text(vec![TextItem::Code(s)])),
         _ => illegal_element(),
     }
 }
 
-fn parse_top_list(list: Vec<Expression>) -> ParseResult<Vec<SourceItem>> {
-    list.into_iter().map(parse_top_expr).collect()
+/// Puts the `At` items of `items` in the order of their times, in the
+/// places they take together. The other items stay where they are.
+fn sort_timed_parts(items: &mut [SourceItem]) {
+    let is_timed = |item: &SourceItem| matches!(item, Item::At { .. });
+    let mut timed = items.iter().filter(|item| is_timed(item)).cloned().collect::<Vec<_>>();
+    timed.sort_by_key(|item| match item {
+        Item::At { time, .. } => *time,
+        _ => unreachable!(),
+    });
+    let slots = items.iter_mut().filter(|item| is_timed(item));
+    for (slot, item) in slots.zip(timed) {
+        *slot = item;
+    }
+}
+
+/// `(h "text")` is a level 3 heading; `(h 4 "text")` gives the level
+/// explicitly, from 2 to 6.
+fn parse_header(mut rand: RandIter) -> ParseResult<SourceItem> {
+    let (level, text) = match rand.next() {
+        Some(Expression::Integer(level)) if (2..=6).contains(&level) => {
+            (level, get_rand_diary!(rand, Expression::String)?)
+        }
+        Some(Expression::Integer(_)) => return operand_mismatch(),
+        Some(Expression::String(text)) => (3, text),
+        Some(_) => return illegal_element(),
+        None => return operand_mismatch(),
+    };
+    if rand.next().is_some() {
+        return operand_mismatch();
+    }
+    Ok(Item::Header(level, text))
 }
 
-fn parse_top_expr(expr: Expression) -> ParseResult<SourceItem> {
+parse_diary_func! {
+    parse_title(|s: Expression::String| Ok(Item::Title(s))) -> SourceItem
+}
+
+parse_diary_func! {
+    parse_class(|s: Expression::String| Ok(Item::Class(s))) -> SourceItem
+}
+
+parse_diary_func! {
+    parse_lang(|tag: Expression::String| {
+        if is_language_tag(&tag) {
+            Ok(Item::Lang(tag))
+        } else {
+            Err(Error::InvalidLanguageTag(tag))
+        }
+    }) -> SourceItem
+}
+
+/// Loosely checks the shape of a BCP 47 tag, e.g. `en`, `ja-JP` or `zh-Hant-TW`.
+fn is_language_tag(tag: &str) -> bool {
+    let mut subtags = tag.split('-');
+    let primary = subtags.next().unwrap_or_default();
+    (2..=8).contains(&primary.len())
+        && primary.chars().all(|c| c.is_ascii_alphabetic())
+        && subtags.all(|s| (1..=8).contains(&s.len()) && s.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+/// `(details "summary" items...)` takes the block items of a day, but not
+/// the metadata ones, which only make sense at the top level.
+fn parse_details(mut rand: RandIter, strict: bool) -> ParseResult<SourceItem> {
+    let summary = get_rand_diary!(&mut rand, Expression::String)?;
+    let body = rand
+        .map(|expr| parse_details_item(expr, strict))
+        .collect::<ParseResult<Vec<SourceItem>>>()?;
+    Ok(Item::Details { summary, body })
+}
+
+/// `(quote items... (cite "source"))` takes the same items as `details`; the
+/// attribution is optional and rendered after them wherever it is given.
+fn parse_quote(rand: RandIter, strict: bool) -> ParseResult<SourceItem> {
+    let mut cite = None;
+    let mut body = Vec::new();
+    for expr in rand {
+        match expr {
+            Expression::Tuple(t) if is_form(&t, "cite") => {
+                if cite.is_some() {
+                    return illegal_element();
+                }
+                let mut rand = t.into_iter().skip(1);
+                cite = Some(get_rand_diary!(rand, Expression::String)?);
+                if rand.next().is_some() {
+                    return operand_mismatch();
+                }
+            }
+            expr => body.push(parse_details_item(expr, strict)?),
+        }
+    }
+    Ok(Item::Quote { body, cite })
+}
+
+/// `(meta (title "...") (tags "a" "b") (summary "...") (draft))`, where each
+/// form is optional but may be given only once.
+fn parse_meta(tuple: Vec<Expression>) -> ParseResult<DocumentMeta> {
+    let mut meta = DocumentMeta::default();
+    let mut seen = BTreeSet::new();
+    for expr in tuple.into_iter().skip(1) {
+        let t = unwrap_expr!(expr, Expression::Tuple).ok_or(Error::IllegalElement)?;
+        let name = match t.first() {
+            Some(Expression::Literal(name)) => name.clone(),
+            _ => return illegal_element(),
+        };
+        if !seen.insert(name) {
+            return illegal_element();
+        }
+        match_keyword_mut! { t, |rand| {
+            "title" => parse_title(rand).map(|item| meta.title = unwrap_expr!(item, Item::Title)),
+            "tags" => parse_tags(rand).map(|item| {
+                meta.tags = unwrap_expr!(item, Item::Tags).unwrap_or_default()
+            }),
+            "summary" => {
+                meta.summary = Some(get_rand_diary!(rand, Expression::String)?);
+                if rand.next().is_some() {
+                    return operand_mismatch();
+                }
+                Ok(())
+            },
+            "draft" => {
+                if rand.next().is_some() {
+                    return operand_mismatch();
+                }
+                meta.draft = true;
+                Ok(())
+            }
+        }}?;
+    }
+    Ok(meta)
+}
+
+fn is_form(tuple: &[Expression], name: &str) -> bool {
+    matches!(tuple.first(), Some(Expression::Literal(l)) if l == name)
+}
+
+fn parse_details_item(expr: Expression, strict: bool) -> ParseResult<SourceItem> {
     match expr {
         Expression::Tuple(t) => {
             match_keyword! { t, |rand| {
                 "h" | "header" => parse_header(rand),
                 "txt" | "text" => parse_text(rand),
                 "li" | "list" => parse_list(rand),
-                "img" | "image" => parse_image(rand)
+                "ol" | "ordered" => parse_ordered_list(rand),
+                "img" | "image" => parse_image(rand),
+                "anchor" => parse_anchor(rand),
+                "details" => parse_details(rand, strict),
+                "quote" => parse_quote(rand, strict),
+                "geo" => parse_geo(rand)
             }}
         }
-        Expression::String(s) => Ok(Item::Text(vec![TextItem::RawString(s)])),
-        Expression::BackQuotedString(s) => Ok(Item::Text(vec![TextItem::Code(s)])),
+        Expression::String(s) if !strict => Ok(Item::Text(vec![TextItem::RawString(s)])),
+        Expression::BackQuotedString(s) if !strict => Ok(Item::Text(vec![TextItem::Code(s)])),
         _ => illegal_element(),
     }
 }
 
 parse_diary_func! {
-    parse_header(|s: Expression::String| Ok(Item::Header(s))) -> SourceItem
+    parse_series(|name: Expression::String, part: Expression::Integer| {
+        if part == 0 {
+            illegal_element()
+        } else {
+            Ok(Item::Series { name, part })
+        }
+    }) -> SourceItem
+}
+
+/// `(at "HH:MM" items...)` takes the same items as `details`.
+fn parse_at(mut rand: RandIter, strict: bool) -> ParseResult<SourceItem> {
+    let time = get_rand_diary!(&mut rand, Expression::String)?;
+    let time = parse_time(&time).ok_or(Error::InvalidTime(time))?;
+    let body = rand
+        .map(|expr| parse_details_item(expr, strict))
+        .collect::<ParseResult<Vec<SourceItem>>>()?;
+    Ok(Item::At { time, body })
+}
+
+fn parse_time(s: &str) -> Option<(u32, u32)> {
+    let (hours, minutes) = s.split_once(':')?;
+    let parse = |part: &str, max: u32| {
+        (part.len() == 2 && part.bytes().all(|b| b.is_ascii_digit()))
+            .then(|| part.parse().ok())
+            .flatten()
+            .filter(|&n| n <= max)
+    };
+    Some((parse(hours, 23)?, parse(minutes, 59)?))
+}
+
+/// `(geo latitude longitude "label")`, where the coordinates may be integers.
+fn parse_geo(mut rand: RandIter) -> ParseResult<SourceItem> {
+    let lat = parse_coordinate(rand.next())?;
+    let lon = parse_coordinate(rand.next())?;
+    let label = get_rand_diary!(rand, Expression::String)?;
+    if rand.next().is_some() {
+        return operand_mismatch();
+    }
+    if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+        return Err(Error::CoordinatesOutOfRange(lat, lon));
+    }
+    Ok(Item::Geo { lat, lon, label })
+}
+
+fn parse_coordinate(expr: Option<Expression>) -> ParseResult<f64> {
+    match expr {
+        Some(Expression::Integer(n)) => Ok(f64::from(n)),
+        Some(Expression::Float(x)) => Ok(x),
+        Some(_) => illegal_element(),
+        None => operand_mismatch(),
+    }
+}
+
+parse_diary_func! {
+    parse_anchor(|id: Expression::String| Ok(Item::Anchor(id))) -> SourceItem
+}
+
+fn parse_tags(rand: RandIter) -> ParseResult<SourceItem> {
+    rand.map(|expr| unwrap_expr!(expr, Expression::String).ok_or(Error::IllegalElement))
+        .collect::<ParseResult<Vec<String>>>()
+        .map(Item::Tags)
 }
 
 fn parse_text(rand: RandIter) -> ParseResult<SourceItem> {
@@ -106,8 +817,17 @@ fn parse_text_item(expr: Expression) -> ParseResult<TextItem> {
             match_keyword! { t, |rand| {
                 "a" => parse_weblink(rand),
                 "b" => parse_bold(rand),
+                "i" => parse_italic(rand),
+                "s" => parse_strike(rand),
+                "mark" => parse_mark(rand),
                 "p" => parse_post(rand),
-                "code" => parse_code(rand)
+                "code" => parse_code(rand),
+                "sup" => parse_sup(rand),
+                "sub" => parse_sub(rand),
+                "ruby" => parse_ruby(rand),
+                "br" => parse_break(rand),
+                "math" => parse_math(rand),
+                "abbr" => parse_abbr(rand)
             }}
         }
         Expression::String(s) => Ok(TextItem::RawString(s)),
@@ -126,6 +846,18 @@ parse_diary_func! {
     parse_bold(|txt: Expression::String| Ok(TextItem::Bold(txt))) -> TextItem
 }
 
+parse_diary_func! {
+    parse_italic(|txt: Expression::String| Ok(TextItem::Italic(txt))) -> TextItem
+}
+
+parse_diary_func! {
+    parse_strike(|txt: Expression::String| Ok(TextItem::Strike(txt))) -> TextItem
+}
+
+parse_diary_func! {
+    parse_mark(|txt: Expression::String| Ok(TextItem::Mark(txt))) -> TextItem
+}
+
 parse_diary_func! {
     parse_post(|year: Expression::Integer, month: Expression::Integer, day: Expression::Integer| {
         Ok(TextItem::PostLink((year, month, day)))
@@ -136,18 +868,90 @@ parse_diary_func! {
     parse_code(|s: Expression::String| Ok(TextItem::Code(s))) -> TextItem
 }
 
+parse_diary_func! {
+    parse_sup(|s: Expression::String| Ok(TextItem::Sup(s))) -> TextItem
+}
+
+parse_diary_func! {
+    parse_sub(|s: Expression::String| Ok(TextItem::Sub(s))) -> TextItem
+}
+
+parse_diary_func! {
+    parse_abbr(|text: Expression::String, title: Expression::String| {
+        Ok(TextItem::Abbr { text, title })
+    }) -> TextItem
+}
+
+parse_diary_func! {
+    parse_ruby(|base: Expression::String, reading: Expression::String| {
+        Ok(TextItem::Ruby { base, reading })
+    }) -> TextItem
+}
+
+/// ``(math `x^2`)``; the back-quoted form saves escaping the quotes of TeX.
+fn parse_math(mut rand: RandIter) -> ParseResult<TextItem> {
+    let tex = match rand.next() {
+        Some(Expression::BackQuotedString(s) | Expression::String(s)) => s,
+        Some(_) => return illegal_element(),
+        None => return operand_mismatch(),
+    };
+    if rand.next().is_some() {
+        return operand_mismatch();
+    }
+    Ok(TextItem::Math(tex))
+}
+
+fn parse_break(mut rand: RandIter) -> ParseResult<TextItem> {
+    if rand.next().is_some() {
+        operand_mismatch()
+    } else {
+        Ok(TextItem::Break)
+    }
+}
+
 fn parse_list(rand: RandIter) -> ParseResult<SourceItem> {
     rand.map(parse_list_item)
         .collect::<ParseResult<Vec<SourceItem>>>()
         .map(SourceItem::List)
 }
 
+fn parse_ordered_list(rand: RandIter) -> ParseResult<SourceItem> {
+    rand.map(parse_list_item)
+        .collect::<ParseResult<Vec<SourceItem>>>()
+        .map(SourceItem::OrderedList)
+}
+
 fn parse_list_item(expr: Expression) -> ParseResult<SourceItem> {
     match expr {
         Expression::Tuple(t) => {
             match_keyword! (t, |rand| {
                     "txt" | "text" => parse_text(rand),
                     "li" | "list" => parse_list(rand),
+                    "ol" | "ordered" => parse_ordered_list(rand),
+                    "img" | "image" => parse_image(rand),
+                    "item" => parse_multi_block_item(rand)
+            })
+        }
+        Expression::String(s) => Ok(Item::Text(vec![TextItem::RawString(s)])),
+        _ => illegal_element(),
+    }
+}
+
+/// `(item (txt ...) (txt ...))` puts several blocks into a single entry of a
+/// list, whereas a `(li ...)` there is a nested list.
+fn parse_multi_block_item(rand: RandIter) -> ParseResult<SourceItem> {
+    rand.map(parse_list_item_block)
+        .collect::<ParseResult<Vec<SourceItem>>>()
+        .map(SourceItem::ListEntry)
+}
+
+fn parse_list_item_block(expr: Expression) -> ParseResult<SourceItem> {
+    match expr {
+        Expression::Tuple(t) => {
+            match_keyword! (t, |rand| {
+                    "txt" | "text" => parse_text(rand),
+                    "li" | "list" => parse_list(rand),
+                    "ol" | "ordered" => parse_ordered_list(rand),
                     "img" | "image" => parse_image(rand)
             })
         }
@@ -159,11 +963,30 @@ fn parse_list_item(expr: Expression) -> ParseResult<SourceItem> {
 fn parse_image(mut rand: RandIter) -> ParseResult<SourceItem> {
     let title = get_rand_diary!(&mut rand, Expression::String)?;
 
-    let items = rand
-        .map(parse_image_items)
-        .collect::<ParseResult<Vec<ImageItem<String>>>>()?;
+    let mut caption = None;
+    let mut items = Vec::new();
+    for expr in rand {
+        match expr {
+            // Image items start with a file name, so a literal head is a sub-form.
+            Expression::Tuple(t) if matches!(t.first(), Some(Expression::Literal(_))) => {
+                if caption.is_some() {
+                    return illegal_element();
+                }
+                caption = Some(match_keyword_mut! { t, |rand| {
+                    "caption" => {
+                        let txt = get_rand_diary!(rand, Expression::String)?;
+                        if rand.next().is_some() {
+                            return operand_mismatch();
+                        }
+                        Ok(txt)
+                    }
+                }}?);
+            }
+            expr => items.push(parse_image_items(expr)?),
+        }
+    }
 
-    Ok(Item::Images(Images { title, items }))
+    Ok(Item::Images(Images { title, items, caption }))
 }
 
 fn parse_image_items(expr: Expression) -> ParseResult<ImageItem<String>> {
@@ -184,3 +1007,589 @@ fn parse_image_items(expr: Expression) -> ParseResult<ImageItem<String>> {
         _ => illegal_element(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::Config,
+        date::Date,
+        diary_content::{
+            parse_archive, parse_str, parse_str_with, Document, DocumentMeta, ImageItem, Images,
+            Item, SourceDoucument, StrError, TextItem,
+        },
+        sexp::{self, ParseError, SExpParser},
+        string_reader::StringReader,
+        syntax_error::{Error, ParseResult},
+        test_util::config_with,
+    };
+
+    fn try_parse_with(txt: &str, config: &Config) -> ParseResult<SourceDoucument> {
+        parse_str_with(txt, config).map_err(|err| match err {
+            StrError::Syntax(err) => err,
+            StrError::Read(err) => panic!("Failed to read {:?}: {:?}", txt, err),
+        })
+    }
+
+    fn parse(txt: &str) -> SourceDoucument {
+        try_parse_with(txt, &Config::default()).unwrap()
+    }
+
+    fn parse_single_text(txt: &str) -> Vec<TextItem> {
+        match parse(txt).into_contents().into_iter().next() {
+            Some(Item::Text(t)) => t,
+            other => panic!("Expected a text item, but got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_sup_and_sub() {
+        assert_eq!(
+            parse_single_text(r#"((txt "x" (sup "2") "H" (sub "2") "O"))"#),
+            vec![
+                TextItem::RawString("x".to_string()),
+                TextItem::Sup("2".to_string()),
+                TextItem::RawString("H".to_string()),
+                TextItem::Sub("2".to_string()),
+                TextItem::RawString("O".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_ruby() {
+        assert_eq!(
+            parse_single_text(r#"((txt (ruby "漢字" "かんじ")))"#),
+            vec![TextItem::Ruby {
+                base: "漢字".to_string(),
+                reading: "かんじ".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn parse_break() {
+        let expected = vec![
+            TextItem::RawString("a".to_string()),
+            TextItem::Break,
+            TextItem::RawString("b".to_string()),
+        ];
+        assert_eq!(parse_single_text(r#"((txt "a" (br) "b"))"#), expected);
+        assert!(matches!(
+            parse(r#"((li (txt "a" (br) "b")))"#).contents().as_slice(),
+            [Item::List(li)] if matches!(li.as_slice(), [Item::Text(t)] if t == &expected)
+        ));
+        assert!(matches!(
+            try_parse_with(r#"((txt (br "x")))"#, &Config::default()),
+            Err(Error::OperandMismatch)
+        ));
+    }
+
+    #[test]
+    fn flatten_into_plain_blocks() {
+        let doc = parse(
+            r#"((h "Header") (txt "a" (b "b") (a "link" "https://example.com"))
+                (li "one" (li "two")) (img "title" ("x.webp" "caption")))"#,
+        );
+        assert_eq!(
+            doc.plain_blocks(),
+            vec!["Header", "ablink", "one", "two", "title", "caption"]
+        );
+    }
+
+    #[test]
+    fn parse_anchor() {
+        assert!(matches!(
+            parse(r#"((anchor "setup"))"#).contents().as_slice(),
+            [Item::Anchor(id)] if id == "setup"
+        ));
+    }
+
+    #[test]
+    fn parse_tags() {
+        let doc = parse(r#"((tags "travel" "food") (txt "x"))"#);
+        assert_eq!(doc.tags(), vec!["travel", "food"]);
+        assert!(matches!(
+            try_parse_with(r#"((tags "travel" 1))"#, &Config::default()),
+            Err(Error::IllegalElement)
+        ));
+    }
+
+    #[test]
+    fn normalize_raw_strings() {
+        let config = config_with(&[("normalize_whitespace", "true")]);
+        let txt = "((txt \"a  b   \nc \" (code \"x   y  \")) (li \"d  \"))";
+        let doc = try_parse_with(txt, &config).unwrap();
+        match doc.contents().as_slice() {
+            [Item::Text(t), Item::List(li)] => {
+                assert_eq!(
+                    t,
+                    &vec![
+                        TextItem::RawString("a b\nc ".to_string()),
+                        TextItem::Code("x   y  ".to_string()),
+                    ]
+                );
+                assert!(matches!(
+                    li.as_slice(),
+                    [Item::Text(t)] if t == &vec![TextItem::RawString("d".to_string())]
+                ));
+            }
+            other => panic!("Unexpected items: {:?}", other),
+        }
+        assert_eq!(
+            parse_single_text(txt)[0],
+            TextItem::RawString("a  b   \nc ".to_string())
+        );
+    }
+
+    #[test]
+    fn normalize_keeps_space_before_inline_markup() {
+        let config = config_with(&[("normalize_whitespace", "true")]);
+        let txt = "((txt \"This  is  \n  \" (b \"bold\") \" text  \"))";
+        let doc = try_parse_with(txt, &config).unwrap();
+        assert_eq!(
+            doc.contents(),
+            &vec![Item::Text(vec![
+                TextItem::RawString("This is\n ".to_string()),
+                TextItem::Bold("bold".to_string()),
+                TextItem::RawString(" text".to_string()),
+            ])]
+        );
+    }
+
+    #[test]
+    fn reject_disallowed_scheme_in_strict_mode() {
+        let strict = config_with(&[("strict", "true")]);
+        assert!(try_parse_with(r#"((txt (a "ok" "http://example.com")))"#, &strict).is_ok());
+        assert!(matches!(
+            try_parse_with(r#"((li (txt (a "bad" "javascript:alert(1)"))))"#, &strict),
+            Err(Error::DisallowedScheme(href)) if href == "javascript:alert(1)"
+        ));
+        assert_eq!(
+            parse(r#"((txt (a "bad" "javascript:alert(1)")))"#).contents().len(),
+            1
+        );
+    }
+
+    #[test]
+    fn bare_top_level_string_in_strict_mode() {
+        let strict = config_with(&[("strict", "true")]);
+        let txt = r#"((h "Header") "bare")"#;
+        assert!(matches!(
+            try_parse_with(txt, &strict),
+            Err(Error::IllegalElement)
+        ));
+        assert!(matches!(
+            parse(txt).contents().as_slice(),
+            [Item::Header(3, _), Item::Text(_)]
+        ));
+    }
+
+    #[test]
+    fn reject_empty_form() {
+        assert!(matches!(
+            try_parse_with(r#"((txt "a") () (txt "b"))"#, &Config::default()),
+            Err(Error::EmptyForm(1, 12))
+        ));
+        let txt = "((meta (tags \"a\"))\n  ())";
+        assert!(matches!(try_parse_with(txt, &Config::default()), Err(Error::EmptyForm(2, 3))));
+    }
+
+    #[test]
+    fn parse_classes() {
+        let doc = parse(r#"((class "trip") (txt "x") (class "food"))"#);
+        assert_eq!(doc.classes(), vec!["trip", "food"]);
+    }
+
+    #[test]
+    fn parse_with_bom() {
+        assert_eq!(
+            parse_single_text("\u{FEFF}((txt \"x\"))"),
+            vec![TextItem::RawString("x".to_string())]
+        );
+    }
+
+    #[test]
+    fn merge_image_blocks() {
+        let config = config_with(&[("merge_images", "true")]);
+        let doc = try_parse_with(
+            r#"((img "" ("a.webp")) (img "" ("b.webp" "B"))
+                (img "Trip" ("c.webp")) (img "Food" ("d.webp"))
+                (txt "x") (img "" ("e.webp")))"#,
+            &config,
+        )
+        .unwrap();
+        let galleries = doc
+            .contents()
+            .iter()
+            .filter_map(|item| match item {
+                Item::Images(images) => Some((images.title.as_str(), images.items.len())),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(galleries, [("", 2), ("Trip", 1), ("Food", 1), ("", 1)]);
+    }
+
+    #[test]
+    fn parse_shared_image_caption() {
+        let doc = parse(r#"((img "Trip" (caption "Day one") ("a.webp" "A") ("b.webp")))"#);
+        match doc.contents().as_slice() {
+            [Item::Images(images)] => {
+                assert_eq!(images.caption.as_deref(), Some("Day one"));
+                let items = images
+                    .items
+                    .iter()
+                    .map(|item| (item.data.as_str(), item.caption.as_deref()))
+                    .collect::<Vec<_>>();
+                assert_eq!(items, [("a.webp", Some("A")), ("b.webp", None)]);
+            }
+            other => panic!("Expected an image block, but got {:?}", other),
+        }
+        assert!(matches!(
+            try_parse_with(r#"((img "" (caption "a") (caption "b")))"#, &Config::default()),
+            Err(Error::IllegalElement)
+        ));
+        assert!(matches!(
+            try_parse_with(r#"((img "" (title "a") ("a.webp")))"#, &Config::default()),
+            Err(Error::UnknownOperator(_))
+        ));
+    }
+
+    #[test]
+    fn parse_header_levels() {
+        let doc = parse(r#"((h "Three") (h 4 "Four") (h 2 "Two") (h 6 "Six"))"#);
+        assert_eq!(
+            doc.contents(),
+            &[(3, "Three"), (4, "Four"), (2, "Two"), (6, "Six")]
+                .map(|(level, text)| Item::Header(level, text.to_string()))
+                .to_vec()
+        );
+        for txt in [r#"((h 1 "One"))"#, r#"((h 7 "Seven"))"#, r#"((h 0 "Zero"))"#] {
+            assert!(matches!(
+                try_parse_with(txt, &Config::default()),
+                Err(Error::OperandMismatch)
+            ));
+        }
+    }
+
+    #[test]
+    fn parse_meta_block() {
+        let doc = parse(concat!(
+            r#"((meta (title "Trip") (tags "travel" "food") (summary "S") (draft))"#,
+            r#" (title "Old") (tags "old") (txt "body"))"#
+        ));
+        assert_eq!(
+            doc.meta(),
+            &DocumentMeta {
+                title: Some("Trip".to_string()),
+                tags: vec!["travel".to_string(), "food".to_string()],
+                summary: Some("S".to_string()),
+                draft: true,
+            }
+        );
+        assert_eq!(doc.title(), Some("Trip"));
+        assert_eq!(doc.tags(), vec!["travel", "food"]);
+        assert_eq!(doc.summary().as_deref(), Some("S"));
+        assert_eq!(doc.contents().len(), 3);
+
+        let scattered = parse(r#"((title "Old") (tags "old") (txt "body"))"#);
+        assert!(!scattered.meta().draft);
+        assert_eq!(scattered.title(), Some("Old"));
+        assert_eq!(scattered.tags(), vec!["old"]);
+
+        for txt in [
+            r#"((txt "a") (meta (draft)))"#,
+            r#"((meta (draft) (draft)))"#,
+            r#"((meta (draft "x")))"#,
+        ] {
+            assert!(try_parse_with(txt, &Config::default()).is_err(), "{}", txt);
+        }
+    }
+
+    #[test]
+    fn parse_document_from_str() {
+        let doc = parse_str(r#"((txt "a " (b "b")) (img "" ("c.webp" "C")))"#).unwrap();
+        let expected = Document::new(vec![
+            Item::Text(vec![
+                TextItem::RawString("a ".to_string()),
+                TextItem::Bold("b".to_string()),
+            ]),
+            Item::Images(Images {
+                title: String::new(),
+                items: vec![ImageItem {
+                    data: "c.webp".to_string(),
+                    caption: Some("C".to_string()),
+                }],
+                caption: None,
+            }),
+        ]);
+        assert_eq!(doc, expected);
+
+        assert!(matches!(
+            parse_str(""),
+            Err(StrError::Read(sexp::Error::ParseError(ParseError::UnexpectedEOF)))
+        ));
+        assert!(matches!(parse_str("((txt"), Err(StrError::Read(_))));
+        assert!(matches!(
+            parse_str("((foo))"),
+            Err(StrError::Syntax(Error::UnknownOperator(_)))
+        ));
+    }
+
+    #[test]
+    fn parse_archive_entries() {
+        let txt = r#"((entry 2023 5 5 ((txt "a"))) (entry 2023 4 1 ((txt "b"))))"#;
+        let reader = StringReader::new(txt.as_bytes()).unwrap().unwrap();
+        let expr = SExpParser::new(reader).parse_expression().unwrap();
+        let entries = parse_archive(expr, &Config::default(), &[]).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].0, Date::new(2023, 5, 5).unwrap());
+        assert_eq!(entries[1].1.summary().as_deref(), Some("b"));
+
+        let txt = r#"((entry 2023 5 5 ()) (entry 2023 5 5 ()))"#;
+        let reader = StringReader::new(txt.as_bytes()).unwrap().unwrap();
+        let expr = SExpParser::new(reader).parse_expression().unwrap();
+        assert!(matches!(
+            parse_archive(expr, &Config::default(), &[]),
+            Err(Error::DuplicateEntry(_))
+        ));
+
+        let txt = "((entry 2023 5 5 ())\n (entry 2023 5 6 ((txt \"a\") ())))";
+        let reader = StringReader::new(txt.as_bytes()).unwrap().unwrap();
+        let mut parser = SExpParser::new(reader);
+        let expr = parser.parse_expression().unwrap();
+        assert!(matches!(
+            parse_archive(expr, &Config::default(), parser.empty_tuples()),
+            Err(Error::EmptyForm(2, 29))
+        ));
+    }
+
+    #[test]
+    fn parse_lang() {
+        assert_eq!(parse(r#"((lang "en"))"#).lang(), Some("en"));
+        assert_eq!(parse(r#"((lang "zh-Hant-TW"))"#).lang(), Some("zh-Hant-TW"));
+        for tag in ["", "e", "en_US", "en-", "\"><script>"] {
+            let txt = format!("((lang {:?}))", tag);
+            assert!(
+                matches!(
+                    try_parse_with(&txt, &Config::default()),
+                    Err(Error::InvalidLanguageTag(_))
+                ),
+                "{}",
+                tag
+            );
+        }
+    }
+
+    #[test]
+    fn parse_details() {
+        let doc = parse(r#"((details "More" (txt "Body") (li "a" "b")))"#);
+        match doc.contents().as_slice() {
+            [Item::Details { summary, body }] => {
+                assert_eq!(summary, "More");
+                assert!(matches!(body.as_slice(), [Item::Text(_), Item::List(li)] if li.len() == 2));
+            }
+            other => panic!("Expected a details item, but got {:?}", other),
+        }
+        assert!(matches!(
+            try_parse_with(r#"((details "More" (tags "a")))"#, &Config::default()),
+            Err(Error::UnknownOperator(_))
+        ));
+    }
+
+    #[test]
+    fn parse_quote() {
+        let doc = parse(r#"((quote (txt "To be") (cite "Hamlet")) (quote (txt "Anonymous")))"#);
+        match doc.contents().as_slice() {
+            [
+                Item::Quote { body: attributed, cite: Some(cite) },
+                Item::Quote { body, cite: None },
+            ] => {
+                assert_eq!(cite, "Hamlet");
+                assert!(matches!(attributed.as_slice(), [Item::Text(_)]));
+                assert!(matches!(body.as_slice(), [Item::Text(_)]));
+            }
+            other => panic!("Expected two quotes, but got {:?}", other),
+        }
+        assert!(matches!(
+            try_parse_with(r#"((quote (cite "a") (cite "b")))"#, &Config::default()),
+            Err(Error::IllegalElement)
+        ));
+        assert!(matches!(
+            try_parse_with(r#"((quote (cite "a" "b")))"#, &Config::default()),
+            Err(Error::OperandMismatch)
+        ));
+    }
+
+    #[test]
+    fn parse_math() {
+        assert_eq!(
+            parse_single_text(r#"((txt "Area " (math `\\pi r^2`)))"#),
+            vec![
+                TextItem::RawString("Area ".to_string()),
+                TextItem::Math(r"\pi r^2".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_series() {
+        let doc = parse(r#"((series "Hokkaido Trip" 2) (txt "Day two"))"#);
+        assert_eq!(doc.series(), Some(("Hokkaido Trip", 2)));
+        assert_eq!(parse(r#"((txt "x"))"#).series(), None);
+        for txt in [r#"((series "Trip" 0))"#, r#"((series "Trip"))"#, r#"((series 1 "Trip"))"#] {
+            assert!(try_parse_with(txt, &Config::default()).is_err(), "{}", txt);
+        }
+    }
+
+    #[test]
+    fn parse_geo() {
+        let doc = parse(r#"((geo 35.68 139.76 "Tokyo") (geo -33.87 151 "Sydney"))"#);
+        assert!(matches!(
+            doc.contents().as_slice(),
+            [
+                Item::Geo { lat: 35.68, lon: 139.76, label: tokyo },
+                Item::Geo { lat: -33.87, lon: 151.0, label: sydney },
+            ] if tokyo == "Tokyo" && sydney == "Sydney"
+        ));
+        assert!(matches!(
+            try_parse_with(r#"((geo 91.5 0 "Nowhere"))"#, &Config::default()),
+            Err(Error::CoordinatesOutOfRange(..))
+        ));
+    }
+
+    #[test]
+    fn parse_timed_parts() {
+        let doc = parse(r#"((txt "a") (at "14:30" (txt "b")) (txt "c") (at "09:05" "d"))"#);
+        let text = |s: &str| Item::Text(vec![TextItem::RawString(s.to_string())]);
+        assert_eq!(
+            doc.contents(),
+            &vec![
+                text("a"),
+                Item::At { time: (9, 5), body: vec![text("d")] },
+                text("c"),
+                Item::At { time: (14, 30), body: vec![text("b")] },
+            ]
+        );
+        for time in ["24:00", "12:60", "9:30", "12-30", "ab:cd", "+1:30"] {
+            let txt = format!(r#"((at "{}" (txt "x")))"#, time);
+            assert!(
+                matches!(
+                    try_parse_with(&txt, &Config::default()),
+                    Err(Error::InvalidTime(t)) if t == time
+                ),
+                "{}",
+                time
+            );
+        }
+    }
+
+    #[test]
+    fn parse_abbr() {
+        assert_eq!(
+            parse_single_text(r#"((txt (abbr "HTML" "HyperText Markup Language")))"#),
+            vec![TextItem::Abbr {
+                text: "HTML".to_string(),
+                title: "HyperText Markup Language".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn parse_italic() {
+        let doc = parse(r#"((txt (i "a")) (li (txt "b " (i "c") (b "d"))))"#);
+        assert_eq!(
+            doc.contents(),
+            &vec![
+                Item::Text(vec![TextItem::Italic("a".to_string())]),
+                Item::List(vec![Item::Text(vec![
+                    TextItem::RawString("b ".to_string()),
+                    TextItem::Italic("c".to_string()),
+                    TextItem::Bold("d".to_string()),
+                ])]),
+            ]
+        );
+        assert!(try_parse_with(r#"((txt (i 1)))"#, &Config::default()).is_err());
+    }
+
+    #[test]
+    fn parse_strike() {
+        let doc = parse(r#"((txt "before" (s "wrong") "after"))"#);
+        assert_eq!(
+            doc.contents(),
+            &vec![Item::Text(vec![
+                TextItem::RawString("before".to_string()),
+                TextItem::Strike("wrong".to_string()),
+                TextItem::RawString("after".to_string()),
+            ])]
+        );
+    }
+
+    #[test]
+    fn parse_nested_ordered_list() {
+        let doc = parse(r#"((li "a" (ol "one" (li "b") "two")) (ordered "x"))"#);
+        let text = |s: &str| Item::Text(vec![TextItem::RawString(s.to_string())]);
+        assert_eq!(
+            doc.contents(),
+            &vec![
+                Item::List(vec![
+                    text("a"),
+                    Item::OrderedList(vec![text("one"), Item::List(vec![text("b")]), text("two")]),
+                ]),
+                Item::OrderedList(vec![text("x")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_mark() {
+        let doc = parse(r#"((li (txt "a " (mark "important"))))"#);
+        match doc.contents().as_slice() {
+            [Item::List(li)] => assert!(matches!(
+                li.as_slice(),
+                [Item::Text(txt)] if txt == &vec![
+                    TextItem::RawString("a ".to_string()),
+                    TextItem::Mark("important".to_string()),
+                ]
+            )),
+            other => panic!("Expected a list, but got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_multi_paragraph_list_item() {
+        let doc = parse(r#"((li (item (txt "first") (txt "second")) "plain"))"#);
+        match doc.contents().as_slice() {
+            [Item::List(li)] => assert!(matches!(
+                li.as_slice(),
+                [Item::ListEntry(blocks), Item::Text(_)]
+                    if matches!(blocks.as_slice(), [Item::Text(_), Item::Text(_)])
+            )),
+            other => panic!("Expected a list, but got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reject_malformed_input() {
+        type Case = (&'static str, fn(&Error) -> bool);
+        let cases: [Case; 12] = [
+            ("5", |e| matches!(e, Error::IllegalElement)),
+            (r#""bare day""#, |e| matches!(e, Error::IllegalElement)),
+            ("(())", |e| matches!(e, Error::EmptyForm(1, 2))),
+            (r#"((txt "a") ("txt" "b"))"#, |e| matches!(e, Error::IllegalElement)),
+            ("((img))", |e| matches!(e, Error::OperandMismatch)),
+            (r#"((txt (a "only-one-arg")))"#, |e| matches!(e, Error::OperandMismatch)),
+            (r#"((title "a" "b"))"#, |e| matches!(e, Error::OperandMismatch)),
+            ("((txt (b 1)))", |e| matches!(e, Error::IllegalElement)),
+            (r#"((txt (br "x")))"#, |e| matches!(e, Error::OperandMismatch)),
+            (r#"((video "a.mp4"))"#, |e| matches!(e, Error::UnknownOperator(op) if op == "video")),
+            (r#"((h 7 "Seven"))"#, |e| matches!(e, Error::OperandMismatch)),
+            (r#"((li (h "In a list")))"#, |e| matches!(e, Error::UnknownOperator(op) if op == "h")),
+        ];
+        for (txt, expected) in cases {
+            match try_parse_with(txt, &Config::default()) {
+                Err(err) => assert!(expected(&err), "{}: {:?}", txt, err),
+                Ok(doc) => panic!("{} was parsed as {:?}", txt, doc.contents()),
+            }
+        }
+    }
+}
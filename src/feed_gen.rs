@@ -0,0 +1,254 @@
+use std::io::{self, Write};
+
+use crate::{
+    config::Config,
+    date::Date,
+    diary_content::Document,
+    html::HtmlWriter,
+};
+
+/// The name of the site, which is also the author of the feeds.
+const SITE_NAME: &str = "Natuka.ge";
+
+#[derive(Clone, Debug)]
+pub struct FeedEntry {
+    date: Date,
+    title: Option<String>,
+    summary: Option<String>,
+    tags: Vec<String>,
+    /// The HTML of the day, written only with `feed_full_content`.
+    content: Option<String>,
+}
+
+impl FeedEntry {
+    pub fn new<T: Sized + Clone>(date: Date, doc: &Document<T>) -> Self {
+        Self {
+            date,
+            title: doc.title().map(str::to_string),
+            summary: doc.summary(),
+            tags: doc.tags().into_iter().map(str::to_string).collect(),
+            content: None,
+        }
+    }
+
+    pub fn set_content(&mut self, html: String) {
+        self.content = Some(html);
+    }
+
+    pub const fn date(&self) -> &Date {
+        &self.date
+    }
+}
+
+struct FeedGenerator<'a, W: Write> {
+    writer: HtmlWriter<'a, W>,
+    config: &'a Config,
+}
+
+impl<'a, W: Write> FeedGenerator<'a, W> {
+    fn new(writer: &'a mut W, config: &'a Config) -> Self {
+        Self {
+            writer: HtmlWriter::new(writer).validating(config.validate_html()),
+            config,
+        }
+    }
+
+    fn generate(&mut self, title: &str, entries: &[FeedEntry]) -> io::Result<()> {
+        let mut entries = entries.iter().collect::<Vec<_>>();
+        entries.sort_by(|a, b| b.date.cmp(&a.date));
+        entries.truncate(self.config.feed_limit());
+
+        self.writer.raw(r#"<?xml version="1.0" encoding="utf-8"?>"#)?;
+        self.writer
+            .start_attr("feed", &[("xmlns", "http://www.w3.org/2005/Atom")])?;
+        self.writer.start("title")?;
+        self.writer.text(title)?;
+        self.writer.end("title")?;
+        let home = self.config.url("/");
+        self.writer.empty_attr("link", &[("href", &home)])?;
+        self.writer.start("id")?;
+        self.writer.text(&home)?;
+        self.writer.end("id")?;
+        match entries.first() {
+            Some(latest) => self.write_updated(&latest.date)?,
+            // A feed needs one even without entries, which never changed it.
+            None => self.write_updated(&Date::new(1970, 1, 1).unwrap())?,
+        }
+        self.writer.start("author")?;
+        self.writer.start("name")?;
+        self.writer.text(SITE_NAME)?;
+        self.writer.end("name")?;
+        self.writer.end("author")?;
+        for entry in entries {
+            self.write_entry(entry)?;
+        }
+        self.writer.end("feed")?;
+        self.writer.finish()
+    }
+
+    fn write_entry(&mut self, entry: &FeedEntry) -> io::Result<()> {
+        let date = &entry.date;
+        let url = self
+            .config
+            .url(&self.config.entry_path(date.year(), date.month(), date.day()));
+        self.writer.start("entry")?;
+        self.writer.start("title")?;
+        match &entry.title {
+            Some(title) => self.writer.text(title)?,
+            None => self.writer.text(&date.format_slash_padded())?,
+        }
+        self.writer.end("title")?;
+        self.writer.empty_attr("link", &[("href", &url)])?;
+        self.writer.start("id")?;
+        self.writer.text(&url)?;
+        self.writer.end("id")?;
+        self.write_updated(date)?;
+        if let Some(summary) = &entry.summary {
+            self.writer.start("summary")?;
+            self.writer.text(summary)?;
+            self.writer.end("summary")?;
+        }
+        if let Some(content) = entry.content.as_ref().filter(|_| self.config.feed_full_content()) {
+            // Links in the content are site-absolute paths.
+            let base = self.config.url("/");
+            self.writer
+                .start_attr("content", &[("type", "html"), ("xml:base", &base)])?;
+            self.writer.text(content)?;
+            self.writer.end("content")?;
+        }
+        for tag in &entry.tags {
+            self.writer.empty_attr("category", &[("term", tag)])?;
+        }
+        self.writer.end("entry")
+    }
+
+    fn write_updated(&mut self, date: &Date) -> io::Result<()> {
+        self.writer.start("updated")?;
+        self.writer.raw(format_args!("{}T00:00:00Z", date.format_iso()))?;
+        self.writer.end("updated")
+    }
+
+    fn generate_opml(&mut self, months: &[(u32, u32)]) -> io::Result<()> {
+        self.writer.raw(r#"<?xml version="1.0" encoding="utf-8"?>"#)?;
+        self.writer.start_attr("opml", &[("version", "2.0")])?;
+        self.writer.start("head")?;
+        self.writer.start("title")?;
+        self.writer.text(SITE_NAME)?;
+        self.writer.end("title")?;
+        self.writer.end("head")?;
+        self.writer.start("body")?;
+        self.write_outline(SITE_NAME, &self.config.url("/feed.xml"))?;
+        for (year, month) in months.iter().rev() {
+            self.write_outline(
+                &format!("{} - {:4}/{:02}", SITE_NAME, year, month),
+                &self.config.url(&month_feed_path(*year, *month)),
+            )?;
+        }
+        self.writer.end("body")?;
+        self.writer.end("opml")?;
+        self.writer.finish()
+    }
+
+    /// Lists a feed, which is always Atom.
+    fn write_outline(&mut self, title: &str, url: &str) -> io::Result<()> {
+        self.writer.empty_attr(
+            "outline",
+            &[("type", "atom"), ("text", title), ("title", title), ("xmlUrl", url)],
+        )
+    }
+}
+
+/// The site-absolute path of the feed of a month.
+pub fn month_feed_path(year: u32, month: u32) -> String {
+    format!("/{}/{:02}.xml", year, month)
+}
+
+/// Writes an Atom feed of `entries`. Its ids are the URLs of the entries, so
+/// `base_url` must be set for them to be absolute.
+pub fn generate_feed<W: Write>(
+    writer: &mut W,
+    config: &Config,
+    title: &str,
+    entries: &[FeedEntry],
+) -> io::Result<()> {
+    let mut gen = FeedGenerator::new(writer, config);
+    gen.generate(title, entries)
+}
+
+/// Lists the global feed and the feeds of `months` as an OPML subscription list.
+pub fn generate_opml<W: Write>(
+    writer: &mut W,
+    config: &Config,
+    months: &[(u32, u32)],
+) -> io::Result<()> {
+    let mut gen = FeedGenerator::new(writer, config);
+    gen.generate_opml(months)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::Config,
+        date::Date,
+        diary_content::{Document, Item, SourceDoucument, TextItem},
+        feed_gen::{generate_feed, generate_opml, FeedEntry},
+        test_util::config_with,
+    };
+
+    #[test]
+    fn categories_from_tags() {
+        let doc: SourceDoucument = Document::new(vec![
+            Item::Tags(vec!["travel".to_string(), "food & drink".to_string()]),
+            Item::Text(vec![TextItem::RawString("Summary".to_string())]),
+        ]);
+        let entry = FeedEntry::new(Date::new(2023, 5, 5).unwrap(), &doc);
+        let mut buf = Vec::new();
+        generate_feed(&mut buf, &Config::default(), "Natuka.ge", &[entry]).unwrap();
+        let xml = String::from_utf8(buf).unwrap();
+        assert_eq!(xml.matches("<category ").count(), 2);
+        assert!(xml.contains(r#"<category term="travel"/>"#));
+        assert!(xml.contains(r#"<category term="food &amp; drink"/>"#));
+        assert!(xml.contains("<summary>Summary</summary>"));
+    }
+
+    #[test]
+    fn empty_feed_has_required_elements() {
+        let mut buf = Vec::new();
+        let config = config_with(&[("base_url", "https://example.com")]);
+        generate_feed(&mut buf, &config, "Natuka.ge", &[]).unwrap();
+        let xml = String::from_utf8(buf).unwrap();
+        assert!(xml.contains("<id>https://example.com/</id>"));
+        assert!(xml.contains("<updated>1970-01-01T00:00:00Z</updated>"));
+        assert!(xml.contains("<author><name>Natuka.ge</name></author>"));
+    }
+
+    #[test]
+    fn opml_lists_month_feeds() {
+        let mut buf = Vec::new();
+        generate_opml(&mut buf, &Config::default(), &[(2023, 4), (2023, 5)]).unwrap();
+        let xml = String::from_utf8(buf).unwrap();
+        assert_eq!(xml.matches("<outline ").count(), 3);
+        assert_eq!(xml.matches(r#"<outline type="atom" "#).count(), 3);
+        assert!(xml.contains(r#"xmlUrl="/feed.xml""#));
+        assert!(xml.contains(r#"xmlUrl="/2023/04.xml""#));
+        assert!(xml.contains(r#"xmlUrl="/2023/05.xml""#));
+    }
+
+    #[test]
+    fn title_from_title_item() {
+        let titled: SourceDoucument = Document::new(vec![
+            Item::Title("My Day".to_string()),
+            Item::Text(vec![TextItem::RawString("Summary".to_string())]),
+        ]);
+        let untitled: SourceDoucument = Document::new(vec![]);
+        let entries = [
+            FeedEntry::new(Date::new(2023, 5, 5).unwrap(), &titled),
+            FeedEntry::new(Date::new(2023, 5, 6).unwrap(), &untitled),
+        ];
+        let mut buf = Vec::new();
+        generate_feed(&mut buf, &Config::default(), "Natuka.ge", &entries).unwrap();
+        let xml = String::from_utf8(buf).unwrap();
+        assert!(xml.contains("<title>My Day</title>"));
+        assert!(xml.contains("<title>2023/05/06</title>"));
+    }
+}
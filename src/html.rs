@@ -1,15 +1,36 @@
-use std::io::{self, Write};
+use std::{
+    fmt::Display,
+    io::{self, ErrorKind, Write},
+};
+
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source",
+    "track", "wbr",
+];
 
 pub struct HtmlWriter<'a, W: Write> {
     writer: &'a mut W,
+    /// The currently open elements, tracked only when validating.
+    open_tags: Option<Vec<String>>,
 }
 
 impl<'a, W: Write> HtmlWriter<'a, W> {
     pub fn new(writer: &'a mut W) -> Self {
-        Self { writer }
+        Self {
+            writer,
+            open_tags: None,
+        }
+    }
+
+    /// With `validate`, every `end` must match the innermost open element
+    /// and `finish` fails if any element is left open.
+    pub fn validating(mut self, validate: bool) -> Self {
+        self.open_tags = if validate { Some(Vec::new()) } else { None };
+        self
     }
 
     pub fn start<'slf, 'str>(&'slf mut self, name: &'str str) -> io::Result<()> {
+        self.push_tag(name);
         write!(self.writer, "<{}>", name)
     }
 
@@ -18,29 +39,199 @@ impl<'a, W: Write> HtmlWriter<'a, W> {
         name: &'str str,
         attr: &[(&'str str, &'str str)],
     ) -> io::Result<()> {
-        let capacity = attr.iter().fold(0, |acc, (k, v)| acc + 4 + k.len() + v.len());
-        let attributes = attr.iter().fold(String::with_capacity(capacity), |mut acc, (k, v)| {
-            acc.push_str(&format!(r#" {}="{}""#, k, v));
-            acc
-        });
-        write!(self.writer, "<{}{}>", name, &attributes)
+        self.push_tag(name);
+        write!(self.writer, "<{}", name)?;
+        self.attributes(attr)?;
+        self.raw(">")
+    }
+
+    /// Writes a self-closing element, as used in XML documents like feeds.
+    pub fn empty_attr(&mut self, name: &str, attr: &[(&str, &str)]) -> io::Result<()> {
+        write!(self.writer, "<{}", name)?;
+        self.attributes(attr)?;
+        self.raw("/>")
     }
 
     pub fn end<'slf, 'str>(&'slf mut self, name: &'str str) -> io::Result<()> {
+        if let Some(open_tags) = &mut self.open_tags {
+            match open_tags.pop() {
+                Some(open) if open == name => {}
+                Some(open) => {
+                    return Err(invalid_html(format!(
+                        "</{}> found while <{}> is open",
+                        name, open
+                    )))
+                }
+                None => return Err(invalid_html(format!("</{}> without a start tag", name))),
+            }
+        }
         write!(self.writer, "</{}>", name)
     }
 
+    /// Checks that every element has been closed.
+    pub fn finish(&mut self) -> io::Result<()> {
+        match self.open_tags.as_deref() {
+            Some([.., open]) => Err(invalid_html(format!("<{}> is never closed", open))),
+            _ => Ok(()),
+        }
+    }
+
+    fn attributes(&mut self, attr: &[(&str, &str)]) -> io::Result<()> {
+        for (key, value) in attr {
+            write!(self.writer, r#" {}=""#, key)?;
+            self.attr_value(value)?;
+            self.raw('"')?;
+        }
+        Ok(())
+    }
+
+    fn push_tag(&mut self, name: &str) {
+        if let Some(open_tags) = &mut self.open_tags {
+            if !VOID_ELEMENTS.contains(&name) {
+                open_tags.push(name.to_string());
+            }
+        }
+    }
+
+    /// Writes `txt` as element content, escaping `&`, `<` and `>`.
+    pub fn text(&mut self, txt: &str) -> io::Result<()> {
+        let mut rest = txt;
+        while let Some(pos) = rest.find(['&', '<', '>']) {
+            self.writer.write_all(&rest.as_bytes()[..pos])?;
+            let escaped = match rest.as_bytes()[pos] {
+                b'&' => "&amp;",
+                b'<' => "&lt;",
+                _ => "&gt;",
+            };
+            self.writer.write_all(escaped.as_bytes())?;
+            rest = &rest[pos + 1..];
+        }
+        self.writer.write_all(rest.as_bytes())
+    }
+
+    /// Writes `value` as the content of a double-quoted attribute, escaping
+    /// `&`, `"` and `<`. The quotes themselves are left to the caller.
+    pub fn attr_value(&mut self, value: &str) -> io::Result<()> {
+        self.writer.write_all(escape_attr(value).as_bytes())
+    }
+
+    /// Writes `html` as is, for markup, scripts and styles which are already
+    /// safe in place. Text from entries goes through `text` instead.
+    pub fn raw(&mut self, html: impl Display) -> io::Result<()> {
+        write!(self.writer, "{}", html)
+    }
+
     pub fn doctype(&mut self) -> io::Result<()> {
-        write!(self.writer, "<!DOCTYPE html>")
+        self.raw("<!DOCTYPE html>")
     }
 }
 
-impl<'a, W: Write> Write for HtmlWriter<'a, W> {
-    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.writer.write(buf)
+fn invalid_html(message: String) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message)
+}
+
+/// Escapes an attribute value which is always written double-quoted.
+fn escape_attr(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+}
+
+/// Reduces `s` to characters which are safe as an id or class token
+/// without any escaping; everything else becomes `-`.
+pub fn sanitize_token(s: &str) -> String {
+    s.chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | ':') {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::html::{sanitize_token, HtmlWriter};
+
+    #[test]
+    fn escape_text() {
+        let mut buf = Vec::new();
+        HtmlWriter::new(&mut buf).text("a < b & c > \"d\"").unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), "a &lt; b &amp; c &gt; \"d\"");
+    }
+
+    #[test]
+    fn escape_by_context() {
+        let input = r#"<a href="x">&amp;</a>"#;
+        let mut buf = Vec::new();
+        HtmlWriter::new(&mut buf).text(input).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            r#"&lt;a href="x"&gt;&amp;amp;&lt;/a&gt;"#
+        );
+
+        let mut buf = Vec::new();
+        HtmlWriter::new(&mut buf).attr_value(input).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "&lt;a href=&quot;x&quot;>&amp;amp;&lt;/a>"
+        );
+
+        let mut buf = Vec::new();
+        HtmlWriter::new(&mut buf).raw(input).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), input);
+    }
+
+    #[test]
+    fn validate_balanced_tags() {
+        let mut buf = Vec::new();
+        let mut writer = HtmlWriter::new(&mut buf).validating(true);
+        writer.start("p").unwrap();
+        writer.start_attr("a", &[("href", "/")]).unwrap();
+        writer.start("hr").unwrap();
+        writer.end("a").unwrap();
+        writer.end("p").unwrap();
+        assert!(writer.finish().is_ok());
+    }
+
+    #[test]
+    fn validate_unbalanced_tags() {
+        let mut buf = Vec::new();
+        let mut writer = HtmlWriter::new(&mut buf).validating(true);
+        writer.start("li").unwrap();
+        writer.start_attr("a", &[("href", "/")]).unwrap();
+        assert!(writer.end("li").is_err());
+
+        let mut buf = Vec::new();
+        let mut writer = HtmlWriter::new(&mut buf).validating(true);
+        writer.start("a").unwrap();
+        assert!(writer.finish().is_err());
+
+        let mut buf = Vec::new();
+        let mut writer = HtmlWriter::new(&mut buf);
+        writer.start("a").unwrap();
+        assert!(writer.finish().is_ok());
+    }
+
+    #[test]
+    fn escape_attribute_values() {
+        let mut buf = Vec::new();
+        HtmlWriter::new(&mut buf)
+            .start_attr("a", &[("href", "/?a=1&b=\"2\"")])
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            r#"<a href="/?a=1&amp;b=&quot;2&quot;">"#
+        );
     }
 
-    fn flush(&mut self) -> io::Result<()> {
-        self.writer.flush()
+    #[test]
+    fn sanitize_tokens() {
+        assert_eq!(sanitize_token("setup"), "setup");
+        assert_eq!(sanitize_token("a b\"<c>"), "a-b--c-");
+        assert_eq!(sanitize_token("日本語_1"), "日本語_1");
     }
 }
@@ -1,20 +1,32 @@
-use crate::util::{calc_hash, push_path};
+use crate::sink::Sink;
+use crate::util::{calc_hash, encode_path_segment, push_path};
+use xxhash_rust::xxh3::xxh3_64;
 use image::{image_dimensions, ImageFormat};
 use image::{io::Reader as ImageReader, ImageError};
 use log::{debug, info, warn};
-use std::fs::{self, copy, File};
+use std::fs::{self, File};
 use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
-use std::{io, path::PathBuf};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::{io, path::PathBuf, thread};
 
 #[derive(Clone, Debug)]
 pub struct ImagePath {
+    dir_url: String,
     image_name: ImageName,
     size: ImageSize,
+    missing: bool,
+    /// Whether the paths are those of another image shown in place of the
+    /// missing source.
+    substitute: bool,
 }
 
 #[derive(Clone, Debug)]
 pub struct ImageName {
     name: String,
+    thumbnail_name: String,
+    actual_name: String,
 }
 
 #[derive(Clone, Debug)]
@@ -24,6 +36,48 @@ pub struct ImageSize {
 }
 
 impl ImagePath {
+    /// Stands in for `file_name` when its source doesn't exist, so that the
+    /// page referencing it can still be built.
+    pub fn placeholder(file_name: &str) -> Self {
+        Self {
+            dir_url: String::new(),
+            image_name: ImageName {
+                name: file_name.to_string(),
+                thumbnail_name: file_name.to_string(),
+                actual_name: file_name.to_string(),
+            },
+            size: ImageSize {
+                width: 0,
+                height: 0,
+            },
+            missing: true,
+            substitute: false,
+        }
+    }
+
+    /// Stands in for `file_name` like a `placeholder`, but pointing at this
+    /// image, so that the page still shows one.
+    pub fn substitute(&self, file_name: &str) -> Self {
+        Self {
+            image_name: ImageName {
+                name: file_name.to_string(),
+                ..self.image_name.clone()
+            },
+            missing: true,
+            substitute: true,
+            ..self.clone()
+        }
+    }
+
+    /// Whether there is an image to show, which a `placeholder` doesn't have.
+    pub const fn has_image(&self) -> bool {
+        !self.missing || self.substitute
+    }
+
+    pub fn name(&self) -> &str {
+        &self.image_name.name
+    }
+
     pub const fn height(&self) -> u32 {
         self.size.height
     }
@@ -33,21 +87,29 @@ impl ImagePath {
     }
 
     pub fn thumbnail_path(&self) -> String {
-        format!("/img/{}", self.image_name.thumbnail_name())
+        format!(
+            "{}/{}",
+            self.dir_url,
+            encode_path_segment(&self.image_name.thumbnail_name())
+        )
     }
 
     pub fn actual_path(&self) -> String {
-        format!("/img/{}", self.image_name.actual_name())
+        format!(
+            "{}/{}",
+            self.dir_url,
+            encode_path_segment(&self.image_name.actual_name())
+        )
     }
 }
 
 impl ImageName {
-    fn thumbnail_name(&self) -> String {
-        format!("{}-thumb.jpeg", self.name)
+    fn thumbnail_name(&self) -> &str {
+        &self.thumbnail_name
     }
 
-    fn actual_name(&self) -> String {
-        format!("{}.webp", self.name)
+    fn actual_name(&self) -> &str {
+        &self.actual_name
     }
 
     fn hash_name(&self) -> String {
@@ -55,45 +117,268 @@ impl ImageName {
     }
 }
 
+/// Whether `convert_image` could reuse the cached thumbnail.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ImageOutcome {
+    CacheHit,
+    Converted { thumbnail_bytes: u64 },
+}
+
 type ImgResult<T> = Result<T, Error>;
 
+/// Sources renamed to avoid a name collision, with their new names.
+type Renamed = Vec<(String, String)>;
+
+/// How thumbnails are named in the cache and the output, from the name of
+/// their source without extensions.
+pub const DEFAULT_THUMBNAIL_TEMPLATE: &str = "{name}-thumb.{ext}";
+/// How copied images are named in the output.
+pub const DEFAULT_IMAGE_TEMPLATE: &str = "{name}.{ext}";
+
+const THUMBNAIL_EXT: &str = "jpeg";
+const IMAGE_EXT: &str = "webp";
+
+/// Replaces `{name}` and `{ext}` in `template`.
+fn apply_template(template: &str, name: &str, ext: &str) -> String {
+    template.replace("{ext}", ext).replace("{name}", name)
+}
+
+/// The name which `apply_template` turns into `file_name`, if any.
+fn match_template(template: &str, file_name: &str, ext: &str) -> Option<String> {
+    let template = template.replace("{ext}", ext);
+    let (prefix, suffix) = template.split_once("{name}")?;
+    let name = file_name.strip_prefix(prefix)?.strip_suffix(suffix)?;
+    (apply_template(&template, name, ext) == file_name).then(|| name.to_string())
+}
+
+/// The bounds of a thumbnail, which keeps the aspect ratio of its source.
+const THUMBNAIL_WIDTH: u32 = 300;
+const THUMBNAIL_HEIGHT: u32 = 96;
+
+/// The file name of `file_name` without directories or its last extension.
+fn base_name(file_name: &str) -> String {
+    let file_name = file_name.rsplit('/').next().unwrap_or(file_name);
+    match file_name.rsplit_once('.') {
+        Some((name, _)) if !name.is_empty() => name.to_string(),
+        _ => file_name.to_string(),
+    }
+}
+
+#[derive(Debug)]
 pub enum Error {
     IOError(io::Error),
     ImageError(ImageError),
+    /// The source of an image, relative to the image directory, doesn't exist.
+    MissingSource(String),
 }
 
 pub struct ImageConverter {
     src_dir: PathBuf,
     dst_dir: PathBuf,
     cache_dir: PathBuf,
+    dir_url: String,
+    thumbnail_template: String,
+    image_template: String,
+    /// The names of the cache entries `verify_cache` found broken, which are
+    /// regenerated even if they weren't purged.
+    broken: BTreeSet<String>,
+    /// Where thumbnails and images are copied, below `dst_dir`.
+    sink: Arc<Sink>,
 }
 
 impl ImageConverter {
-    pub fn new(src_dir: PathBuf, dst_dir: PathBuf, cache_dir: PathBuf) -> io::Result<Self> {
+    pub fn new(
+        src_dir: PathBuf,
+        dst_dir: PathBuf,
+        cache_dir: PathBuf,
+        dir_url: String,
+    ) -> io::Result<Self> {
         Self::create_dir_all(&src_dir)?;
-        Self::create_dir_all(&dst_dir)?;
         Self::create_dir_all(&cache_dir)?;
         Ok(Self {
             src_dir,
             dst_dir,
             cache_dir,
+            dir_url,
+            thumbnail_template: DEFAULT_THUMBNAIL_TEMPLATE.to_string(),
+            image_template: DEFAULT_IMAGE_TEMPLATE.to_string(),
+            broken: BTreeSet::new(),
+            sink: Arc::new(Sink::Dir),
         })
     }
 
-    pub fn convert_image(&self, file_name: String) -> ImgResult<ImagePath> {
+    /// Names thumbnails and copied images after templates with `{name}` and
+    /// `{ext}` placeholders instead of the defaults.
+    pub fn with_name_templates(mut self, thumbnail: &str, image: &str) -> Self {
+        self.thumbnail_template = thumbnail.to_string();
+        self.image_template = image.to_string();
+        self
+    }
+
+    /// Writes thumbnails and images to `sink` instead of loose files.
+    pub fn with_sink(mut self, sink: Arc<Sink>) -> Self {
+        self.sink = sink;
+        self
+    }
+
+    fn image_name(&self, name: String) -> ImageName {
+        ImageName {
+            thumbnail_name: apply_template(&self.thumbnail_template, &name, THUMBNAIL_EXT),
+            actual_name: apply_template(&self.image_template, &name, IMAGE_EXT),
+            name,
+        }
+    }
+
+    /// Converts each of `file_names` with up to `jobs` worker threads.
+    /// The results are in the order of `file_names`, whatever `jobs` is, and
+    /// come with the sources renamed to avoid a collision and their new names.
+    /// Sources with the same output are converted once, and the others are
+    /// reported as cache hits. `others` are sources which aren't converted
+    /// this time but still count for collisions, so that an image keeps its
+    /// name however few of them are built.
+    pub fn convert_images(
+        &self,
+        file_names: &[String],
+        others: &[String],
+        jobs: usize,
+    ) -> ImgResult<(Vec<(ImagePath, ImageOutcome)>, Renamed)> {
+        let (names, renamed) = self.output_names(file_names, others)?;
+        if !file_names.is_empty() {
+            self.sink.create_dir(&self.dst_dir).map_err(Error::IOError)?;
+        }
+        let mut unique: Vec<(&String, ImageName, u64)> = Vec::new();
+        let mut index_of: HashMap<String, usize> = HashMap::new();
+        let indices = file_names
+            .iter()
+            .zip(names)
+            .map(|(file_name, (name, hash))| {
+                *index_of.entry(name.name.clone()).or_insert_with(|| {
+                    unique.push((file_name, name, hash));
+                    unique.len() - 1
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let results = if jobs <= 1 {
+            unique
+                .iter()
+                .map(|(file_name, name, hash)| {
+                    self.convert_image_as(file_name, name.clone(), *hash)
+                })
+                .collect::<ImgResult<Vec<_>>>()?
+        } else {
+            let next = AtomicUsize::new(0);
+            let mut results = thread::scope(|scope| {
+                let workers = (0..jobs.min(unique.len()))
+                    .map(|_| {
+                        scope.spawn(|| {
+                            let mut results = Vec::new();
+                            loop {
+                                let i = next.fetch_add(1, Ordering::Relaxed);
+                                let Some((file_name, name, hash)) = unique.get(i) else { break };
+                                let result = self.convert_image_as(file_name, name.clone(), *hash);
+                                results.push((i, result));
+                            }
+                            results
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                workers
+                    .into_iter()
+                    .flat_map(|worker| worker.join().expect("An image worker panicked"))
+                    .collect::<Vec<_>>()
+            });
+            results.sort_by_key(|(i, _)| *i);
+            results
+                .into_iter()
+                .map(|(_, result)| result)
+                .collect::<ImgResult<Vec<_>>>()?
+        };
+
+        let mut converted = vec![false; results.len()];
+        let results = indices
+            .into_iter()
+            .map(|i| {
+                let (path, outcome) = results[i].clone();
+                if converted[i] {
+                    (path, ImageOutcome::CacheHit)
+                } else {
+                    converted[i] = true;
+                    (path, outcome)
+                }
+            })
+            .collect();
+        Ok((results, renamed))
+    }
+
+    /// Names the output of each source after its file name without
+    /// directories or its last extension. When sources with different
+    /// contents share a name, each of them gets a suffix from a hash of its
+    /// path, so none of them overwrites another and the names don't depend on
+    /// the order of `file_names`. The renamed sources are returned with their
+    /// new names. Only `file_names` are named, but `others` are taken into
+    /// account for collisions.
+    fn output_names(
+        &self,
+        file_names: &[String],
+        others: &[String],
+    ) -> ImgResult<(Vec<(ImageName, u64)>, Renamed)> {
+        let hash = |file_name: &String| {
+            calc_hash(&push_path(&self.src_dir, file_name)).map_err(|err| {
+                if err.kind() == ErrorKind::NotFound {
+                    Error::MissingSource(file_name.clone())
+                } else {
+                    Error::IOError(err)
+                }
+            })
+        };
+        let hashes = file_names.iter().map(hash).collect::<ImgResult<Vec<_>>>()?;
+        let mut contents: HashMap<String, BTreeSet<u64>> = HashMap::new();
+        for (file_name, &hash) in file_names.iter().zip(&hashes) {
+            contents.entry(base_name(file_name)).or_default().insert(hash);
+        }
+        for file_name in others {
+            if let Some(name_hashes) = contents.get_mut(&base_name(file_name)) {
+                name_hashes.insert(hash(file_name)?);
+            }
+        }
+
+        let mut renamed = Vec::new();
+        let names = file_names
+            .iter()
+            .zip(hashes)
+            .map(|(file_name, hash)| {
+                let base_name = base_name(file_name);
+                let name = if contents[&base_name].len() > 1 {
+                    let name = format!("{}-{:08x}", base_name, xxh3_64(file_name.as_bytes()) >> 32);
+                    renamed.push((file_name.clone(), name.clone()));
+                    name
+                } else {
+                    base_name
+                };
+                (self.image_name(name), hash)
+            })
+            .collect();
+        Ok((names, renamed))
+    }
+
+    fn convert_image_as(
+        &self,
+        file_name: &str,
+        image_path: ImageName,
+        hash: u64,
+    ) -> ImgResult<(ImagePath, ImageOutcome)> {
         debug!("Converting a image: \"{}\"", file_name);
-        let src = push_path(&self.src_dir, &file_name);
-        let base_name = file_name
-            .split('.')
-            .nth(0)
-            .unwrap_or(&file_name)
-            .to_string();
-        let image_path = ImageName { name: base_name };
+        let src = push_path(&self.src_dir, file_name);
 
         let thumbnail_cache_path = push_path(&self.cache_dir, &image_path.thumbnail_name());
 
         let cache_hash_path = push_path(&self.cache_dir, &image_path.hash_name());
         let cache_hash = loop {
+            if self.broken.contains(&image_path.name) {
+                info!("Broken cache entry: \"{}\"", &file_name);
+                break None;
+            }
             let mut f = match File::open(&cache_hash_path) {
                 Ok(f) => f,
                 Err(err) => {
@@ -108,62 +393,143 @@ impl ImageConverter {
             f.read(&mut buf).map_err(Error::IOError)?;
             break Some(u64::from_ne_bytes(buf));
         };
-        let hash = calc_hash(&src).map_err(Error::IOError)?;
 
-        let size = loop {
+        let (size, outcome) = loop {
             if let Some(cache_hash) = cache_hash {
-                if cache_hash == hash {
+                if cache_hash == hash && thumbnail_cache_path.is_file() {
                     debug!("Unchanged image: \"{}\"", &file_name);
-                    break Self::get_image_size(&thumbnail_cache_path)?;
+                    let size = Self::get_image_size(&thumbnail_cache_path)?;
+                    break (size, ImageOutcome::CacheHit);
+                } else if cache_hash == hash {
+                    // The thumbnail template may have changed since.
+                    info!("Missing thumbnail: \"{}\"", &file_name);
                 } else {
                     info!("Updated image: \"{}\"", &file_name);
                 }
             } else {
                 info!("New image: \"{}\"", &file_name);
             }
-            Self::save_hash(hash, &cache_hash_path)?;
             let size = Self::generate_thumbnail(&src, &thumbnail_cache_path)?;
+            Self::save_hash(hash, &size, &cache_hash_path)?;
+            let thumbnail_bytes = fs::metadata(&thumbnail_cache_path)
+                .map_err(Error::IOError)?
+                .len();
 
-            break size;
+            break (size, ImageOutcome::Converted { thumbnail_bytes });
         };
 
-        Self::copy_image(
-            &thumbnail_cache_path,
-            &push_path(&self.dst_dir, &image_path.thumbnail_name()),
-        )
-        .or_else(|err| {
-            if err.kind() == ErrorKind::NotFound {
-                warn!(
-                    "The hash file exists, but the thumbnail doesn't exist: \"{}\".",
-                    file_name
-                );
-                Self::generate_thumbnail(&src, &thumbnail_cache_path)?;
-                Self::copy_image(
-                    &thumbnail_cache_path,
-                    &push_path(&self.dst_dir, &image_path.thumbnail_name()),
-                )
-                .map_err(Error::IOError)
-            } else {
-                Err(Error::IOError(err))
-            }
-        })?;
-        Self::copy_image(&src, &push_path(&self.dst_dir, &image_path.actual_name()))
+        self.sink
+            .copy(
+                &thumbnail_cache_path,
+                &push_path(&self.dst_dir, image_path.thumbnail_name()),
+            )
+            .or_else(|err| {
+                if err.kind() == ErrorKind::NotFound {
+                    warn!(
+                        "The hash file exists, but the thumbnail doesn't exist: \"{}\".",
+                        file_name
+                    );
+                    Self::generate_thumbnail(&src, &thumbnail_cache_path)?;
+                    self.sink
+                        .copy(
+                            &thumbnail_cache_path,
+                            &push_path(&self.dst_dir, image_path.thumbnail_name()),
+                        )
+                        .map_err(Error::IOError)
+                } else {
+                    Err(Error::IOError(err))
+                }
+            })?;
+        self.sink
+            .copy(&src, &push_path(&self.dst_dir, image_path.actual_name()))
             .map_err(Error::IOError)?;
-        Ok(ImagePath {
+        let path = ImagePath {
+            dir_url: self.dir_url.clone(),
             image_name: image_path,
             size,
-        })
+            missing: false,
+            substitute: false,
+        };
+        Ok((path, outcome))
     }
 
-    fn save_hash(hash: u64, path: &PathBuf) -> ImgResult<()> {
-        let binary = hash.to_ne_bytes();
-        let mut writer = File::create(path).map_err(Error::IOError)?;
-        writer.write(&binary).map_err(Error::IOError)?;
-        Ok(())
+    pub fn source_exists(&self, file_name: &str) -> bool {
+        push_path(&self.src_dir, file_name).is_file()
+    }
+
+    /// Checks every entry of the cache, i.e. a hash file and its thumbnail,
+    /// and with `purge` removes the broken ones. The build regenerates the
+    /// broken entries either way. Returns their names.
+    pub fn verify_cache(&mut self, purge: bool) -> io::Result<Vec<String>> {
+        let mut names = BTreeSet::new();
+        for entry in fs::read_dir(&self.cache_dir)? {
+            let file_name = entry?.file_name();
+            let Some(file_name) = file_name.to_str() else { continue };
+            if let Some(name) = file_name.strip_suffix(".xxh3") {
+                names.insert(name.to_string());
+            } else if let Some(name) =
+                match_template(&self.thumbnail_template, file_name, THUMBNAIL_EXT)
+            {
+                // A thumbnail left without its hash file is broken too.
+                names.insert(name);
+            }
+        }
+
+        let mut broken = Vec::new();
+        for name in names {
+            let name = self.image_name(name);
+            if self.is_cache_entry_valid(&name) {
+                continue;
+            }
+            if purge {
+                for file_name in [&name.hash_name(), name.thumbnail_name()] {
+                    match fs::remove_file(push_path(&self.cache_dir, file_name)) {
+                        Err(err) if err.kind() != ErrorKind::NotFound => return Err(err),
+                        _ => {}
+                    }
+                }
+            }
+            self.broken.insert(name.name.clone());
+            broken.push(name.name);
+        }
+        Ok(broken)
     }
 
-    fn copy_image(original: &PathBuf, link: &PathBuf) -> io::Result<()> {
-        copy(original, link)?;
+    /// Whether the hash file holds a 64-bit hash and the thumbnail decodes to
+    /// the dimensions recorded after it. Hash files written before dimensions
+    /// were recorded only need a size `generate_thumbnail` could have made.
+    fn is_cache_entry_valid(&self, name: &ImageName) -> bool {
+        let hash_path = push_path(&self.cache_dir, &name.hash_name());
+        let thumbnail_path = push_path(&self.cache_dir, &name.thumbnail_name());
+        let Ok(hash) = fs::read(hash_path) else { return false };
+        let recorded = match hash.len() {
+            8 => None,
+            16 => {
+                let dimension = |i: usize| u32::from_ne_bytes(hash[i..i + 4].try_into().unwrap());
+                Some((dimension(8), dimension(12)))
+            }
+            _ => return false,
+        };
+        let thumbnail = File::open(thumbnail_path).ok().and_then(|f| {
+            ImageReader::with_format(BufReader::new(f), ImageFormat::Jpeg)
+                .decode()
+                .ok()
+        });
+        thumbnail.is_some_and(|img| match recorded {
+            Some(size) => size == (img.width(), img.height()),
+            None => {
+                (1..=THUMBNAIL_WIDTH).contains(&img.width())
+                    && (1..=THUMBNAIL_HEIGHT).contains(&img.height())
+            }
+        })
+    }
+
+    fn save_hash(hash: u64, size: &ImageSize, path: &PathBuf) -> ImgResult<()> {
+        let mut binary = hash.to_ne_bytes().to_vec();
+        binary.extend(size.width.to_ne_bytes());
+        binary.extend(size.height.to_ne_bytes());
+        let mut writer = File::create(path).map_err(Error::IOError)?;
+        writer.write_all(&binary).map_err(Error::IOError)?;
         Ok(())
     }
 
@@ -172,7 +538,7 @@ impl ImageConverter {
         let img = ImageReader::with_format(BufReader::new(reader), ImageFormat::WebP)
             .decode()
             .map_err(Error::ImageError)?;
-        let img = img.thumbnail(300, 96);
+        let img = img.thumbnail(THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT);
 
         let writer = File::create(dst).map_err(Error::IOError)?;
         img.write_to(&mut BufWriter::new(writer), ImageFormat::Jpeg)
@@ -189,13 +555,174 @@ impl ImageConverter {
         Ok(ImageSize { width, height })
     }
 
+    /// An existing directory is fine, but an existing file in its place is
+    /// reported as `NotADirectory` instead of failing later on.
     fn create_dir_all(path: &PathBuf) -> io::Result<()> {
         fs::create_dir_all(path).or_else(|err| {
-            if err.kind() == ErrorKind::AlreadyExists {
+            if err.kind() == ErrorKind::AlreadyExists && path.is_dir() {
                 Ok(())
+            } else if err.kind() == ErrorKind::AlreadyExists {
+                Err(io::Error::new(
+                    ErrorKind::NotADirectory,
+                    format!("\"{}\" is not a directory", path.display()),
+                ))
             } else {
                 Err(err)
             }
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+
+    use crate::{
+        image::{ImageConverter, ImageName, ImageOutcome, ImagePath, ImageSize},
+        report::BuildReport,
+        test_util::{config_with, TempDir, TINY_WEBP},
+    };
+
+    fn new_converter(dir: &TempDir) -> ImageConverter {
+        ImageConverter::new(
+            dir.join("img"),
+            dir.join("public/img"),
+            dir.join("cache"),
+            "/img".to_string(),
+        )
+        .unwrap()
+    }
+
+    fn convert(converter: &ImageConverter, file_name: &str) -> (ImagePath, ImageOutcome) {
+        converter
+            .convert_images(&[file_name.to_string()], &[], 1)
+            .unwrap()
+            .0
+            .remove(0)
+    }
+
+    #[test]
+    fn image_path_with_base_path() {
+        let config = config_with(&[("base_path", "/diary")]);
+        let path = ImagePath {
+            dir_url: config.path("/img"),
+            image_name: ImageName {
+                name: "photo".to_string(),
+                thumbnail_name: "photo-thumb.jpeg".to_string(),
+                actual_name: "photo.webp".to_string(),
+            },
+            size: ImageSize {
+                width: 1,
+                height: 1,
+            },
+            missing: false,
+            substitute: false,
+        };
+        assert_eq!(path.thumbnail_path(), "/diary/img/photo-thumb.jpeg");
+        assert_eq!(path.actual_path(), "/diary/img/photo.webp");
+    }
+
+    #[test]
+    fn report_cache_hit_and_miss() {
+        let dir = TempDir::new();
+        dir.write("img/old.webp", TINY_WEBP);
+        convert(&new_converter(&dir), "old.webp");
+
+        dir.write("img/new.webp", TINY_WEBP);
+        let converter = new_converter(&dir);
+        let mut report = BuildReport::new();
+        for name in ["old.webp", "new.webp"] {
+            let (_, outcome) = convert(&converter, name);
+            report.record_image(&outcome);
+        }
+        assert_eq!(report.images_cached, 1);
+        assert_eq!(report.images_converted, 1);
+        assert!(report.thumbnail_bytes > 0);
+        assert!(dir.join("public/img/new-thumb.jpeg").exists());
+    }
+
+    #[test]
+    fn encode_unsafe_file_names() {
+        let dir = TempDir::new();
+        dir.write("img/my photo#1.webp", TINY_WEBP);
+        let (path, _) = convert(&new_converter(&dir), "my photo#1.webp");
+        assert_eq!(path.actual_path(), "/img/my%20photo%231.webp");
+        assert_eq!(path.thumbnail_path(), "/img/my%20photo%231-thumb.jpeg");
+        assert!(dir.join("public/img/my photo#1.webp").exists());
+    }
+
+    #[test]
+    fn construct_converter_twice() {
+        let dir = TempDir::new();
+        new_converter(&dir);
+        new_converter(&dir);
+        assert!(dir.join("cache").is_dir());
+    }
+
+    #[test]
+    fn cache_dir_is_file() {
+        let dir = TempDir::new();
+        dir.write("cache", b"");
+        let err = ImageConverter::new(
+            dir.join("img"),
+            dir.join("public/img"),
+            dir.join("cache"),
+            "/img".to_string(),
+        )
+        .err()
+        .unwrap();
+        assert_eq!(err.kind(), ErrorKind::NotADirectory);
+    }
+
+    #[test]
+    fn disambiguate_colliding_names() {
+        let dir = TempDir::new();
+        dir.write("img/2022/photo.webp", TINY_WEBP);
+        let mut other = TINY_WEBP.to_vec();
+        other.push(0);
+        dir.write("img/2023/photo.webp", &other);
+        dir.write("img/2024/photo.webp", TINY_WEBP);
+
+        let names = ["2022/photo.webp", "2023/photo.webp", "2024/photo.webp"].map(String::from);
+        let converter = new_converter(&dir);
+        let (results, renamed) = converter.convert_images(&names, &[], 1).unwrap();
+        let paths = results.iter().map(|(path, _)| path.actual_path()).collect::<Vec<_>>();
+        assert!(paths.iter().all(|path| path.starts_with("/img/photo-")));
+        assert_ne!(paths[0], paths[1]);
+        assert_ne!(paths[0], paths[2]);
+        assert_eq!(renamed.len(), 3);
+        assert_eq!(renamed[1].0, "2023/photo.webp");
+        assert!(paths[1].ends_with(&format!("/{}.webp", renamed[1].1)));
+
+        let file = |path: &str| std::fs::read(dir.join(&format!("public{}", path))).unwrap();
+        assert_eq!(file(&paths[0]), TINY_WEBP);
+        assert_eq!(file(&paths[1]), other);
+
+        // The names stay the same whatever the order of the sources.
+        let reversed = names.iter().rev().cloned().collect::<Vec<_>>();
+        let (results, _) = converter.convert_images(&reversed, &[], 1).unwrap();
+        let reversed_paths = results.iter().rev().map(|(path, _)| path.actual_path());
+        assert!(reversed_paths.eq(paths));
+    }
+
+    #[test]
+    fn convert_shared_output_once() {
+        let dir = TempDir::new();
+        dir.write("img/2022/photo.webp", TINY_WEBP);
+        dir.write("img/2023/photo.webp", TINY_WEBP);
+        dir.write("img/photo.v2.webp", TINY_WEBP);
+
+        let names = ["2022/photo.webp", "2023/photo.webp", "photo.v2.webp"].map(String::from);
+        let (results, renamed) = new_converter(&dir).convert_images(&names, &[], 4).unwrap();
+        assert!(renamed.is_empty());
+        assert_eq!(results[0].0.actual_path(), "/img/photo.webp");
+        assert_eq!(results[1].0.actual_path(), "/img/photo.webp");
+        assert_eq!(results[2].0.actual_path(), "/img/photo.v2.webp");
+        let mut report = BuildReport::new();
+        for (_, outcome) in &results {
+            report.record_image(outcome);
+        }
+        assert_eq!(report.images_converted, 2);
+        assert_eq!(report.images_cached, 1);
+    }
+}
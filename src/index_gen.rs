@@ -1,69 +1,141 @@
 use std::io::{self, Write};
 
-use crate::html::HtmlWriter;
+use crate::{
+    config::{Config, PageGranularity},
+    date::Date,
+    html::HtmlWriter,
+    layout,
+};
 
 struct IndexGenerator<'a, W: Write> {
     writer: HtmlWriter<'a, W>,
+    config: &'a Config,
 }
 
 impl<'a, W: Write> IndexGenerator<'a, W> {
-    fn new(writer: &'a mut W) -> Self {
+    fn new(writer: &'a mut W, config: &'a Config) -> Self {
         Self {
-            writer: HtmlWriter::new(writer),
+            writer: HtmlWriter::new(writer).validating(config.validate_html()),
+            config,
         }
     }
 
-    fn generate<'t, T: Iterator<Item = (&'t u32, &'t Vec<bool>)>>(
+    fn generate<'t, T: Iterator<Item = (&'t u32, &'t Vec<Option<u32>>)>>(
         &'a mut self,
+        featured: &[Date],
         list: T,
     ) -> io::Result<()>
     where
         't: 'a,
     {
-        self.writer.doctype()?;
-        self.writer.start_attr("html", &[("lang", "ja")])?;
-        self.writer.start("head")?;
-        self.writer.start_attr("meta", &[("charset", "utf-8")])?;
-        self.writer.start("title")?;
-        write!(self.writer, "{}", "Natuka.ge")?;
-        self.writer.end("title")?;
-        self.writer.end("head")?;
-        self.writer.start("body")?;
-        self.writer.start("h1")?;
-        write!(self.writer, "{}", "Natuka.ge")?;
-        self.writer.end("h1")?;
-        self.writer.start_attr("a", &[("href", "/links")])?;
-        write!(self.writer, "リンク")?;
+        layout::start_page(&mut self.writer, self.config, "/", "Natuka.ge")?;
+        self.writer
+            .start_attr("a", &[("href", &self.config.path("/links"))])?;
+        self.writer.text("リンク")?;
         self.writer.end("a")?;
-        write!(self.writer, " | ")?;
-        self.writer.start_attr("a", &[("href", "/albums")])?;
-        write!(self.writer, "アルバム一覧")?;
+        self.writer.raw(" | ")?;
+        self.writer
+            .start_attr("a", &[("href", &self.config.path("/albums"))])?;
+        self.writer.text("アルバム一覧")?;
         self.writer.end("a")?;
         self.writer.start("hr")?;
+        self.write_featured(featured)?;
         self.writer.start("ul")?;
         for (year, months) in list {
             self.write_year(*year, months)?;
         }
         self.writer.end("ul")?;
-        self.writer.end("body")?;
-        self.writer.end("html")?;
-        Ok(())
+        layout::end_page(&mut self.writer, self.config)
     }
 
-    fn write_year(&mut self, year: u32, months: &Vec<bool>) -> io::Result<()> {
+    fn generate_archive(&mut self, dates: &[Date]) -> io::Result<()> {
+        layout::start_page(&mut self.writer, self.config, "/archive", "Natuka.ge - Archive")?;
+        layout::home_link(&mut self.writer, self.config)?;
+        self.writer.start("hr")?;
+        self.writer.start("ul")?;
+        for year in dates.chunk_by(|a, b| a.year() == b.year()) {
+            self.writer.start("li")?;
+            self.writer
+                .text(&format!("{}{}", year[0].year(), self.config.locale().year_suffix))?;
+            self.writer.start("ul")?;
+            for month in year.chunk_by(|a, b| a.month() == b.month()) {
+                self.writer.start("li")?;
+                self.writer
+                    .text(&format!("{}{}", month[0].month(), self.config.locale().month_suffix))?;
+                self.writer.start("ul")?;
+                for date in month {
+                    self.writer.start("li")?;
+                    self.write_entry_link(date)?;
+                    self.writer.end("li")?;
+                }
+                self.writer.end("ul")?;
+                self.writer.end("li")?;
+            }
+            self.writer.end("ul")?;
+            self.writer.end("li")?;
+        }
+        self.writer.end("ul")?;
+        layout::end_page(&mut self.writer, self.config)
+    }
+
+    fn write_entry_link(&mut self, date: &Date) -> io::Result<()> {
+        let href = self.config.path(&self.config.entry_path(
+            date.year(),
+            date.month(),
+            date.day(),
+        ));
+        self.writer.start_attr("a", &[("href", &href)])?;
+        self.writer.text(&date.format_slash_padded())?;
+        self.writer.end("a")
+    }
+
+    fn write_featured(&mut self, featured: &[Date]) -> io::Result<()> {
+        if featured.is_empty() {
+            return Ok(());
+        }
+        self.writer.start_attr("section", &[("class", "featured")])?;
+        self.writer.start("h2")?;
+        self.writer.text(&self.config.locale().featured_label)?;
+        self.writer.end("h2")?;
+        self.writer.start("ul")?;
+        for date in featured {
+            self.writer.start("li")?;
+            self.write_entry_link(date)?;
+            self.writer.end("li")?;
+        }
+        self.writer.end("ul")?;
+        self.writer.end("section")
+    }
+
+    /// `months` holds the latest day with an entry of each month, if any.
+    fn write_year(&mut self, year: u32, months: &[Option<u32>]) -> io::Result<()> {
         self.writer.start("li")?;
-        write!(self.writer, "{}年", year)?;
-        for month in months
+        self.writer
+            .text(&format!("{}{}", year, self.config.locale().year_suffix))?;
+        for (month, day) in months
             .iter()
             .enumerate()
-            .filter_map(|(month, &b)| Some(month + 1).filter(|_| b))
+            .filter_map(|(month, &day)| day.map(|day| (month + 1, day)))
             .rev()
         {
             self.writer.start("ul")?;
             self.writer.start("li")?;
+            // Without month pages, a month opens at its latest entry.
+            let page = match self.config.page_granularity() {
+                PageGranularity::Month => format!("/{}/{:02}", year, month),
+                PageGranularity::Day => self.config.entry_path(year, month as u32, day),
+            };
+            let href = self.config.path(&page);
+            self.writer.start_attr("a", &[("href", &href)])?;
             self.writer
-                .start_attr("a", &[("href", &format!("/{}/{:02}", year, month))])?;
-            write!(self.writer, "{}月", month)?;
+                .text(&format!("{}{}", month, self.config.locale().month_suffix))?;
+            self.writer.end("a")?;
+            self.writer.raw(" ")?;
+            self.writer.start_attr("small", &[("class", "updated")])?;
+            let updated = format!("{}/{}", month, day);
+            self.writer
+                .text(&self.config.locale().updated_label.replace("{}", &updated))?;
+            self.writer.end("small")?;
             self.writer.end("li")?;
             self.writer.end("ul")?;
         }
@@ -72,11 +144,92 @@ impl<'a, W: Write> IndexGenerator<'a, W> {
     }
 }
 
-pub fn generate_index<'a, W: Write, T: Iterator<Item = (&'a u32, &'a Vec<bool>)>>(
+pub fn generate_index<'a, W: Write, T: Iterator<Item = (&'a u32, &'a Vec<Option<u32>>)>>(
     writer: &'a mut W,
+    config: &'a Config,
+    featured: &[Date],
     list: T,
 ) -> io::Result<()> {
-    let mut gen = IndexGenerator::new(writer);
-    gen.generate(list)?;
+    let mut gen = IndexGenerator::new(writer, config);
+    gen.generate(featured, list)?;
     Ok(())
 }
+
+/// Lists every entry of `dates`, which must be sorted, by year and month.
+pub fn generate_archive<W: Write>(
+    writer: &mut W,
+    config: &Config,
+    dates: &[Date],
+) -> io::Result<()> {
+    let mut gen = IndexGenerator::new(writer, config);
+    gen.generate_archive(dates)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::{
+        config::Config,
+        date::Date,
+        index_gen::{generate_archive, generate_index},
+        test_util::config_with,
+    };
+
+    #[test]
+    fn render_featured_in_order() {
+        let featured = [Date::new(2023, 5, 5).unwrap(), Date::new(2022, 1, 2).unwrap()];
+        let years = BTreeMap::from([(2023, vec![None; 12])]);
+        let mut buf = Vec::new();
+        generate_index(&mut buf, &Config::default(), &featured, years.iter()).unwrap();
+        let html = String::from_utf8(buf).unwrap();
+        assert!(html.contains(concat!(
+            r#"<section class="featured"><h2>注目の投稿</h2><ul>"#,
+            r#"<li><a href="/2023/05#05">2023/05/05</a></li>"#,
+            r#"<li><a href="/2022/01#02">2022/01/02</a></li>"#,
+            "</ul></section>"
+        )));
+    }
+
+    #[test]
+    fn escape_label_suffixes() {
+        let config = config_with(&[("year_suffix", " & co"), ("month_suffix", "<m>")]);
+        let mut months = vec![None; 12];
+        months[4] = Some(3);
+        let years = BTreeMap::from([(2023, months)]);
+        let mut buf = Vec::new();
+        generate_index(&mut buf, &config, &[], years.iter()).unwrap();
+        let html = String::from_utf8(buf).unwrap();
+        assert!(html.contains(r#"<li>2023 &amp; co<ul><li><a href="/2023/05">5&lt;m&gt;</a>"#));
+    }
+
+    #[test]
+    fn render_latest_update() {
+        let mut months = vec![None; 12];
+        months[2] = Some(20);
+        let years = BTreeMap::from([(2023, months)]);
+        let mut buf = Vec::new();
+        generate_index(&mut buf, &Config::default(), &[], years.iter()).unwrap();
+        let html = String::from_utf8(buf).unwrap();
+        assert!(html.contains(concat!(
+            r#"<li><a href="/2023/03">3月</a> "#,
+            r#"<small class="updated">最終更新 3/20</small></li>"#
+        )));
+    }
+
+    #[test]
+    fn render_archive() {
+        let dates = [(2022, 12, 31), (2023, 5, 5), (2023, 5, 6), (2023, 6, 1)]
+            .map(|(y, m, d)| Date::new(y, m, d).unwrap());
+        let mut buf = Vec::new();
+        generate_archive(&mut buf, &Config::default(), &dates).unwrap();
+        let html = String::from_utf8(buf).unwrap();
+        assert!(html.contains(concat!(
+            r#"<ul><li>2022年<ul><li>12月<ul><li><a href="/2022/12#31">2022/12/31</a></li>"#,
+            "</ul></li></ul></li>",
+            r#"<li>2023年<ul><li>5月<ul><li><a href="/2023/05#05">2023/05/05</a></li>"#,
+            r#"<li><a href="/2023/05#06">2023/05/06</a></li></ul></li>"#,
+            r#"<li>6月<ul><li><a href="/2023/06#01">2023/06/01</a></li></ul></li></ul></li></ul>"#,
+        )));
+    }
+}
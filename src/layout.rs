@@ -0,0 +1,116 @@
+use std::io::{self, Write};
+
+use crate::{config::Config, html::HtmlWriter};
+
+/// Writes everything from the doctype to the page heading. `path` is the
+/// site-absolute path of the page, by which its translations are linked.
+pub fn start_page<W: Write>(
+    writer: &mut HtmlWriter<'_, W>,
+    config: &Config,
+    path: &str,
+    title: &str,
+) -> io::Result<()> {
+    writer.doctype()?;
+    writer.start_attr("html", &[("lang", "ja")])?;
+    writer.start("head")?;
+    writer.start_attr("meta", &[("charset", "utf-8")])?;
+    writer.start("title")?;
+    writer.text(title)?;
+    writer.end("title")?;
+    for (lang, root) in config.alternates() {
+        let href = format!("{}{}", root, path);
+        writer.start_attr(
+            "link",
+            &[("rel", "alternate"), ("hreflang", lang), ("href", &href)],
+        )?;
+    }
+    if let Some(css) = config.critical_css() {
+        writer.start("style")?;
+        writer.raw(css)?;
+        writer.end("style")?;
+    }
+    if let Some(path) = config.print_css() {
+        writer.start_attr(
+            "link",
+            &[("rel", "stylesheet"), ("media", "print"), ("href", &config.path(path))],
+        )?;
+    }
+    writer.end("head")?;
+    writer.start("body")?;
+    writer.start("h1")?;
+    writer.text(title)?;
+    writer.end("h1")
+}
+
+pub fn home_link<W: Write>(writer: &mut HtmlWriter<'_, W>, config: &Config) -> io::Result<()> {
+    writer.start_attr("a", &[("href", &config.home_href())])?;
+    writer.text(&config.locale().home_label)?;
+    writer.end("a")?;
+    writer.text(&config.locale().home_suffix)
+}
+
+pub fn end_page<W: Write>(writer: &mut HtmlWriter<'_, W>, config: &Config) -> io::Result<()> {
+    if let Some(footer) = config.footer() {
+        writer.start("footer")?;
+        writer.text(footer)?;
+        writer.end("footer")?;
+    }
+    writer.end("body")?;
+    writer.end("html")?;
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        config::Config,
+        html::HtmlWriter,
+        layout::{home_link, start_page},
+        test_util::{config_with, TempDir},
+    };
+
+    #[test]
+    fn custom_home_link() {
+        let config = config_with(&[("base_path", "/diary"), ("home_label", "Custom")]);
+        let mut buf = Vec::new();
+        home_link(&mut HtmlWriter::new(&mut buf), &config).unwrap();
+        let html = String::from_utf8(buf).unwrap();
+        assert!(html.starts_with(r#"<a href="/diary/">Custom</a>"#));
+    }
+
+    #[test]
+    fn inline_critical_css() {
+        let dir = TempDir::new();
+        dir.write("critical.css", b"body > h1 { margin: 0 }");
+        let config = config_with(&[("critical_css", dir.join("critical.css").to_str().unwrap())]);
+        dir.write("critical.css", b"changed after the config was read");
+        let mut buf = Vec::new();
+        start_page(&mut HtmlWriter::new(&mut buf), &config, "/", "Title").unwrap();
+        let html = String::from_utf8(buf).unwrap();
+        assert!(html.contains("</title><style>body > h1 { margin: 0 }</style></head>"));
+    }
+
+    #[test]
+    fn reciprocal_alternates() {
+        let alternates = "ja:https://example.com, en:https://en.example.com/";
+        let render = |base_url: &str| {
+            let config = config_with(&[("base_url", base_url), ("alternates", alternates)]);
+            let mut buf = Vec::new();
+            start_page(&mut HtmlWriter::new(&mut buf), &config, "/2023/05", "Title").unwrap();
+            String::from_utf8(buf).unwrap()
+        };
+        let links = concat!(
+            r#"<link rel="alternate" hreflang="ja" href="https://example.com/2023/05">"#,
+            r#"<link rel="alternate" hreflang="en" href="https://en.example.com/2023/05">"#
+        );
+        assert!(render("https://example.com").contains(links));
+        assert!(render("https://en.example.com").contains(links));
+        assert!(Config::from_map(HashMap::from([(
+            "alternates".to_string(),
+            "https://example.com".to_string()
+        )]))
+        .is_err());
+    }
+}
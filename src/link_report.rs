@@ -0,0 +1,167 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    io::{self, Write},
+};
+
+use serde_json::json;
+
+use crate::{
+    config::{Config, PageGranularity},
+    date::Date,
+    diary_content::{Document, TextItem},
+    html::sanitize_token,
+    site::SiteInfo,
+};
+
+/// Every external link of the archive, with the dates of the entries referencing it.
+pub fn collect_links<'a, T: Sized + Clone + 'a, I: Iterator<Item = (Date, &'a Document<T>)>>(
+    docs: I,
+) -> BTreeMap<String, Vec<Date>> {
+    let mut links: BTreeMap<String, Vec<Date>> = BTreeMap::new();
+    for (date, doc) in docs {
+        for link in doc.web_links() {
+            let dates = links.entry(link.href.clone()).or_default();
+            if !dates.contains(&date) {
+                dates.push(date.clone());
+            }
+        }
+    }
+    links
+}
+
+/// The links of each entry which point within the site to an anchor, or an
+/// entry, which doesn't exist. `PostLink`s are always checked; web links only
+/// when they have a fragment and are relative to the page or to the site.
+pub fn dangling_links<'a, T: Sized + Clone + 'a, I: Iterator<Item = (Date, &'a Document<T>)>>(
+    config: &Config,
+    site: &SiteInfo,
+    docs: I,
+) -> Vec<(Date, String)> {
+    let docs = docs.collect::<Vec<_>>();
+    let mut anchors: HashMap<String, HashSet<String>> = HashMap::new();
+    for (date, doc) in &docs {
+        let mut ids = doc.anchors().into_iter().map(sanitize_token).collect::<HashSet<_>>();
+        ids.insert(site.anchor(date));
+        ids.insert(format!("{:02}", date.day()));
+        if config.page_granularity() == PageGranularity::Day {
+            anchors.insert(format!("/{}", date.format_slash_padded()), ids);
+        } else {
+            let month_page = format!("/{:04}/{:02}", date.year(), date.month());
+            anchors.entry(month_page).or_default().extend(ids);
+        }
+    }
+
+    // Fragments of pages which aren't entries, such as `/links`, aren't known.
+    let resolves = |target: &str, is_entry: bool| {
+        let (page, fragment) = target.split_once('#').unwrap_or((target, ""));
+        match anchors.get(page) {
+            Some(ids) => fragment.is_empty() || ids.contains(fragment),
+            None => !is_entry,
+        }
+    };
+    let mut dangling = Vec::new();
+    for (date, doc) in docs {
+        let page = config.entry_path(date.year(), date.month(), date.day());
+        let page = page.split('#').next().unwrap_or_default();
+        for item in doc.text_items() {
+            let (href, target, is_entry) = match item {
+                TextItem::PostLink((year, month, day)) => {
+                    let target = site.entry_path(config, *year, *month, *day);
+                    (format!("{:04}/{:02}/{:02}", year, month, day), target, true)
+                }
+                TextItem::WebLink(link) if link.href.starts_with('#') => {
+                    (link.href.clone(), format!("{}{}", page, link.href), true)
+                }
+                TextItem::WebLink(link)
+                    if link.href.starts_with('/') && link.href.contains('#') =>
+                {
+                    let target = link
+                        .href
+                        .strip_prefix(config.base_path())
+                        .filter(|path| path.starts_with('/'))
+                        .unwrap_or(&link.href);
+                    (link.href.clone(), target.to_string(), false)
+                }
+                _ => continue,
+            };
+            if !resolves(&target, is_entry) {
+                dangling.push((date.clone(), href));
+            }
+        }
+    }
+    dangling
+}
+
+/// Writes the links as a JSON object from each URL to its `YYYY-MM-DD` dates.
+pub fn write_links_json<W: Write>(
+    writer: &mut W,
+    links: &BTreeMap<String, Vec<Date>>,
+) -> io::Result<()> {
+    let json = links
+        .iter()
+        .map(|(url, dates)| {
+            let dates = dates
+                .iter()
+                .map(Date::format_iso)
+                .collect::<Vec<_>>();
+            (url.clone(), json!(dates))
+        })
+        .collect::<serde_json::Map<_, _>>();
+    serde_json::to_writer_pretty(&mut *writer, &json)?;
+    writeln!(writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::Config,
+        date::Date,
+        diary_content::{Document, Item, SourceDoucument, TextItem, WebLink},
+        link_report::{collect_links, dangling_links, write_links_json},
+        site::SiteInfo,
+        test_util::config_with,
+    };
+
+    fn link(href: &str) -> TextItem {
+        TextItem::WebLink(WebLink {
+            title: href.to_string(),
+            href: href.to_string(),
+        })
+    }
+
+    #[test]
+    fn url_from_two_days() {
+        let first: SourceDoucument = Document::new(vec![
+            Item::Text(vec![link("https://example.com/"), link("https://example.com/")]),
+            Item::List(vec![Item::Text(vec![link("https://example.org/")])]),
+        ]);
+        let second: SourceDoucument =
+            Document::new(vec![Item::Text(vec![link("https://example.com/")])]);
+        let may5 = Date::new(2023, 5, 5).unwrap();
+        let may6 = Date::new(2023, 5, 6).unwrap();
+        let links = collect_links([(may5.clone(), &first), (may6.clone(), &second)].into_iter());
+        assert_eq!(links.len(), 2);
+        assert_eq!(links["https://example.com/"], [may5.clone(), may6]);
+        assert_eq!(links["https://example.org/"], [may5]);
+
+        let mut buf = Vec::new();
+        write_links_json(&mut buf, &links).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(json["https://example.com/"][1], "2023-05-06");
+    }
+
+    #[test]
+    fn post_links_to_missing_entries() {
+        let doc: SourceDoucument = Document::new(vec![Item::Text(vec![
+            TextItem::PostLink((2023, 5, 5)),
+            TextItem::PostLink((2023, 5, 7)),
+        ])]);
+        let may5 = Date::new(2023, 5, 5).unwrap();
+        let day_pages = config_with(&[("page_granularity", "day")]);
+        for config in [Config::default(), day_pages] {
+            let site = SiteInfo::new(&config, [(may5.clone(), &doc)].into_iter());
+            let dangling = dangling_links(&config, &site, [(may5.clone(), &doc)].into_iter());
+            assert_eq!(dangling, [(may5.clone(), "2023/05/07".to_string())]);
+        }
+    }
+}
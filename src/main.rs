@@ -1,39 +1,72 @@
 use std::{
-    collections::BTreeMap,
-    env::{self, VarError},
+    collections::{BTreeMap, BTreeSet, HashMap},
+    env,
     ffi::OsString,
     fmt::Debug,
-    fs::{self, metadata, DirEntry, File},
-    io::{self, BufReader, BufWriter, Read},
+    fs::{self, metadata, File},
+    io::{self, BufReader, BufWriter, Read, Write},
     path::{Path, PathBuf},
     string::FromUtf8Error,
+    sync::Arc,
+    time::{Instant, SystemTime},
 };
 
 use crate::{
-    diary_content::parse_diary_content, image::ImageConverter, sexp::SExpParser,
+    config::{
+        resolve_config, Config, DuplicateAlbums, PageGranularity, CACHE_DIR_ENV, ENV_PREFIX,
+    },
+    diary_content::{parse_archive, parse_str_with, StrError}, image::{ImageConverter, ImagePath},
+    sexp::SExpParser,
     string_reader::StringReader,
 };
-use albums::parse_albums;
+use albums::{parse_albums, write_albums_json};
 use ::image::ImageError;
 use diary_content::{Document, ImageItem, Images, Item, SourceDoucument, SourceItem};
-use index_gen::generate_index;
+use date::Date;
+use feed_gen::{generate_feed, generate_opml, month_feed_path, FeedEntry};
+use index_gen::{generate_archive, generate_index};
+use link_report::{collect_links, dangling_links, write_links_json};
+use photos_gen::generate_photos;
 use log::{debug, info};
-use post_gen::{generate_monthly, OutputDocument, OutputItem};
+use post_gen::{
+    generate_contents, generate_day_page, generate_monthly, generate_print, OutputDocument,
+    OutputItem,
+};
+use report::BuildReport;
+use robots::write_robots_txt;
+use site::SiteInfo;
+use sink::{Sink, SinkFile};
 use sexp::{Expression, ParseError};
-use util::push_path;
+use source_dates::{collect_dates, path_name_to_usize};
+use util::{push_path, NewlineWriter};
 
 mod albums;
+mod cli;
+mod config;
 mod date;
 mod diary_content;
+mod feed_gen;
 mod html;
 mod image;
 mod index_gen;
+mod layout;
+mod link_report;
+mod photos_gen;
 mod post_gen;
+mod report;
+mod robots;
+mod serve;
 mod sexp;
+mod site;
+mod sink;
+mod source_dates;
 mod string_reader;
 mod syntax_error;
+mod tar;
 mod util;
 mod albums_gen;
+#[cfg(test)]
+mod test_util;
 
 #[derive(Debug)]
 enum Error {
@@ -43,129 +76,585 @@ enum Error {
     ParseError(ParseError),
     SyntaxError(syntax_error::Error),
     ImageError(ImageError),
+    /// An image referenced by an entry whose source file doesn't exist.
+    MissingImage(String),
+    /// An output directory which `--clean` refuses to empty.
+    UnsafeClean(PathBuf),
+    /// A zero-byte day file, which strict mode rejects.
+    EmptySource(PathBuf),
     NotUnicode(OsString),
+    ConfigError(config::Error),
+    ArgumentError(cli::Error),
 }
 
 type Result<T> = std::result::Result<T, Error>;
 
-const DEFAULT_CACHE_DIR: &str = "cache";
+const CONFIG_FILE: &str = "shdiary.conf";
+/// The file in the cache directory recording [`site_state`] of the last build.
+const SITE_STATE_FILE: &str = "site-state";
 
 fn main() -> Result<()> {
     env_logger::init();
 
+    let args = cli::parse_args(env::args().skip(1)).map_err(Error::ArgumentError)?;
     let current_path = env::current_dir().map_err(Error::IOError)?;
-    let cache_dir_str = env::var("CACHE_DIR").or_else(|err| match err {
-        VarError::NotPresent => Ok(DEFAULT_CACHE_DIR.to_string()),
-        VarError::NotUnicode(x) => Err(Error::NotUnicode(x)),
-    })?;
-    let cd_dir = fs::read_dir(current_path.clone()).map_err(Error::IOError)?;
-    let public_path = push_path(&current_path, "public");
-    let image_cache_dir = {
-        let mut tmp = PathBuf::from(cache_dir_str);
-        tmp.push("img");
-        tmp
+    let config_path = push_path(&current_path, CONFIG_FILE);
+    let config_file = if config_path.try_exists().map_err(Error::IOError)? {
+        Some(config_path.as_path())
+    } else {
+        None
     };
-    let image_converter = ImageConverter::new(
-        push_path(&current_path, "img"),
-        push_path(&public_path, "img"),
-        image_cache_dir,
-    )
+    let config = resolve_config(&args, read_env()?, config_file).map_err(Error::ConfigError)?;
+    let report = build(&config, &current_path, config.cache_dir())?;
+    report.log();
+    if let Some(report_path) = &args.report {
+        File::create(report_path)
+            .and_then(|f| report.write_json(&mut BufWriter::new(f)))
+            .map_err(Error::IOError)?;
+    }
+    if args.serve {
+        let port = args.port.unwrap_or(serve::DEFAULT_PORT);
+        let public_path = push_path(&current_path, "public");
+        serve::serve(&public_path, config.base_path(), port).map_err(Error::IOError)?;
+    }
+    Ok(())
+}
+
+/// Day documents of a month, indexed by `day - 1`.
+type Month<T> = Vec<Option<T>>;
+
+/// Every parsed source, by year and month.
+type Sources = BTreeMap<u32, BTreeMap<u32, Month<SourceDoucument>>>;
+
+/// The environment variables which may set config keys.
+fn read_env() -> Result<Vec<(String, String)>> {
+    env::vars_os()
+        .filter_map(|(name, value)| {
+            let name = name.into_string().ok()?;
+            (name.starts_with(ENV_PREFIX) || name == CACHE_DIR_ENV).then_some((name, value))
+        })
+        .map(|(name, value)| Ok((name, value.into_string().map_err(Error::NotUnicode)?)))
+        .collect()
+}
+
+fn build(config: &Config, current_path: &Path, cache_dir: &Path) -> Result<BuildReport> {
+    let started = Instant::now();
+    let public_path = current_path.join("public");
+    if config.clean() {
+        clean_dir(&public_path, current_path, config.keep())?;
+    }
+    let sink = match config.tar() {
+        Some(tar_path) => Sink::tar(public_path.clone(), &current_path.join(tar_path)),
+        None => mkdir_if_not_exists(public_path.clone()).map(|_| Sink::Dir),
+    }
+    .map(Arc::new)
     .map_err(Error::IOError)?;
-    mkdir_if_not_exists(public_path.clone()).map_err(Error::IOError)?;
+    let mut image_converter = new_image_converter(config, current_path, &public_path, cache_dir)
+        .map_err(Error::IOError)?
+        .with_sink(Arc::clone(&sink));
 
-    let mut years: BTreeMap<u32, Vec<bool>> = BTreeMap::new();
+    let mut report = BuildReport::new();
+    let sources = read_sources(config, current_path, &mut report)?;
+    let dated_sources = || {
+        sources.iter().flat_map(|(&year, months)| {
+            months.iter().flat_map(move |(&month, days)| {
+                days.iter().enumerate().filter_map(move |(day, doc)| {
+                    let date = Date::new(year, month, day as u32 + 1)?;
+                    doc.as_ref().map(|doc| (date, doc))
+                })
+            })
+        })
+    };
+    let site = SiteInfo::new(config, dated_sources());
+    let state = site_state(current_path, dated_sources());
+    let state_path = cache_dir.join(SITE_STATE_FILE);
+    // Every page may link to any entry, so a change to the set of entries or
+    // to the config file leaves `--changed-only` nothing to skip.
+    let site_unchanged = config.changed_only()
+        && fs::read_to_string(&state_path).is_ok_and(|previous| previous == state);
+    let featured = featured_dates(config, &sources, &mut report);
+    let links = collect_links(dated_sources());
+    for (date, href) in dangling_links(config, &site, dated_sources()) {
+        report.warn(format!(
+            "Link to \"{}\" in {} has no target",
+            href,
+            date.format_slash_padded()
+        ));
+    }
+    let all_dates = dated_sources().map(|(date, _)| date).collect::<Vec<_>>();
+    let mut feed_entries = dated_sources()
+        .map(|(date, doc)| FeedEntry::new(date, doc))
+        .collect::<Vec<_>>();
 
-    for year_dir in cd_dir.into_iter().filter_map(|res| res.ok()) {
-        let month_path = year_dir.path();
-        let metadata = metadata(month_path).map_err(Error::IOError)?;
-        if metadata.is_file() {
-            continue;
+    // Every image counts for name collisions, so that `--only` gives the
+    // images of its month the same names as a full build.
+    let all_images = dated_sources()
+        .flat_map(|(_, doc)| doc.images().into_iter().map(|image| image.data.clone()))
+        .filter(|name| image_converter.source_exists(name))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+    let image_names = dated_sources()
+        .filter(|(date, _)| {
+            config
+                .only()
+                .is_none_or(|only| only == (date.year(), date.month()))
+        })
+        .flat_map(|(_, doc)| doc.images().into_iter().map(|image| image.data.clone()))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+    if config.verify_cache() {
+        let broken = image_converter
+            .verify_cache(config.purge_cache())
+            .map_err(Error::IOError)?;
+        for name in broken {
+            report.warn(if config.purge_cache() {
+                format!("Purged the broken cache entry of \"{}\"", name)
+            } else {
+                format!("The cache entry of \"{}\" is broken", name)
+            });
         }
-        let month_list = fs::read_dir(year_dir.path()).map_err(Error::IOError)?;
-        let mut months = vec![false; 12];
-
-        if year_dir
-            .file_name()
-            .to_str()
-            .map_or(false, |s| s == "public")
-        {
-            continue;
+    }
+    // Outside strict mode a missing image only costs its own thumbnail.
+    let (mut image_names, missing_images): (Vec<_>, Vec<_>) = image_names
+        .into_iter()
+        .partition(|name| config.strict() || image_converter.source_exists(name));
+    let fallback = config.missing_image().filter(|_| !missing_images.is_empty());
+    if let Some(fallback) = fallback {
+        if !image_converter.source_exists(fallback) {
+            report.warn(format!("The missing image \"{}\" does not exist either", fallback));
+        } else if !image_names.iter().any(|name| name == fallback) {
+            image_names.push(fallback.to_string());
         }
+    }
+    let (converted, renamed) = image_converter
+        .convert_images(&image_names, &all_images, config.jobs())
+        .map_err(image_error)?;
+    for (name, new_name) in renamed {
+        report.warn(format!(
+            "Renamed \"{}\" to \"{}\" to avoid a name collision",
+            name, new_name
+        ));
+    }
+    let mut images = HashMap::with_capacity(converted.len());
+    for (name, (path, outcome)) in image_names.into_iter().zip(converted) {
+        report.record_image(&outcome);
+        images.insert(name, path);
+    }
+    let fallback = fallback.and_then(|name| images.get(name)).cloned();
+    for name in missing_images {
+        report.warn(format!("Image \"{}\" does not exist", name));
+        let path = match &fallback {
+            Some(fallback) => fallback.substitute(&name),
+            None => ImagePath::placeholder(&name),
+        };
+        images.insert(name, path);
+    }
 
-        let year_num = if let Ok(num) = path_name_to_usize(&year_dir) {
-            num as u32
-        } else {
+    let source_times = if site_unchanged {
+        Some(source_times(config, current_path, &sources).map_err(Error::IOError)?)
+    } else {
+        None
+    };
+    // Feeds identify their entries by absolute URLs, so they need `base_url`.
+    let feeds = !config.base_url().is_empty();
+    if !feeds {
+        info!("Skipping the feeds, which need base_url");
+    }
+    let mut years: BTreeMap<u32, Vec<Option<u32>>> = BTreeMap::new();
+    let mut feed_months = Vec::new();
+    let mut any_changed = false;
+
+    for (year_num, months) in sources {
+        if config.only().is_some_and(|(year, _)| year != year_num) {
             continue;
-        };
+        }
         let year_path = push_path(&public_path, &format!("{}", year_num));
-        mkdir_if_not_exists(year_path.clone()).map_err(Error::IOError)?;
+        sink.create_dir(&year_path).map_err(Error::IOError)?;
+        let mut latest_days = vec![None; 12];
 
-        for month_dir in month_list.into_iter().filter_map(|res| res.ok()) {
-            let day_list = fs::read_dir(month_dir.path()).map_err(Error::IOError)?;
-            let month_num = path_name_to_usize(&month_dir)?;
-            let mut days = vec![None; 31];
+        for (month_num, days) in months {
+            if config.only().is_some_and(|only| only != (year_num, month_num)) {
+                continue;
+            }
+            let days = days
+                .into_iter()
+                .map(|doc| {
+                    doc.map(|doc| handle_image(&images, doc))
+                })
+                .collect::<Month<OutputDocument>>();
+            report.record_entries(year_num, month_num, days.iter().flatten().count());
 
-            for day in day_list.into_iter().filter_map(|res| res.ok()) {
-                let day_num = path_name_to_usize(&day)?;
-                let reader = new_string_reader(day.path())?;
-                let reader = if let Some(r) = reader { r } else { continue };
+            if config.feed_full_content() {
+                for (day, doc) in days.iter().enumerate() {
+                    let Some(doc) = doc else { continue };
+                    let date = Date::new(year_num, month_num, day as u32 + 1).unwrap();
+                    let i = all_dates.binary_search(&date).unwrap();
+                    let mut buf = Vec::new();
+                    generate_contents(&mut buf, config, &site, doc).map_err(Error::IOError)?;
+                    feed_entries[i].set_content(String::from_utf8(buf).map_err(Error::Utf8Error)?);
+                }
+            }
 
-                debug!("Parsing a post of {}/{}/{}", year_num, month_num, day_num);
-                let expr = parse_to_expression(reader)?;
-                let post = parse_diary_content(expr).map_err(Error::SyntaxError)?;
+            latest_days[month_num as usize - 1] =
+                days.iter().rposition(Option::is_some).map(|day| day as u32 + 1);
+            feed_months.push((year_num, month_num));
 
-                let output = handle_image(&image_converter, post)?;
+            let file_name = push_path(&year_path, &format!("{:02}.html", month_num));
+            let feed_path = month_feed_path(year_num, month_num);
+            let feed_file_name = push_path(&public_path, feed_path.trim_start_matches('/'));
+            if let Some(times) = &source_times {
+                let newest = times.get(&(year_num, month_num)).copied();
+                // Day pages replace the month page, but every month has a feed,
+                // or without feeds a page of its last day.
+                let last_day = latest_days[month_num as usize - 1];
+                let output = match (config.page_granularity(), last_day) {
+                    (PageGranularity::Day, Some(day)) if !feeds => {
+                        push_path(&year_path, &format!("{:02}/{:02}.html", month_num, day))
+                    }
+                    (PageGranularity::Day, _) => feed_file_name.clone(),
+                    (PageGranularity::Month, _) => file_name.clone(),
+                };
+                if !is_stale(&output, newest).map_err(Error::IOError)? {
+                    debug!("Skipping the unchanged daily of {}/{}", year_num, month_num);
+                    continue;
+                }
+            }
+            any_changed = true;
 
-                days[day_num - 1] = Some(output);
+            let month_path = push_path(&year_path, &format!("{:02}", month_num));
+            let has_photos = config.photo_pages()
+                && days
+                    .iter()
+                    .flatten()
+                    .any(|doc| doc.images().iter().any(|image| image.data.has_image()));
+            if config.page_granularity() == PageGranularity::Day
+                || config.emit_txt()
+                || has_photos
+                || config.print_pages()
+            {
+                sink.create_dir(&month_path).map_err(Error::IOError)?;
             }
 
-            months[month_num - 1] = true;
+            if config.emit_txt() {
+                for (day, doc) in days.iter().enumerate() {
+                    let Some(doc) = doc else { continue };
+                    let file_name = push_path(&month_path, &format!("{:02}.txt", day + 1));
+                    let mut text = doc.plain_blocks().join("\n");
+                    text.push('\n');
+                    write_output(config, &sink, &file_name, |buf| {
+                        buf.write_all(text.as_bytes())
+                    })
+                    .map_err(Error::IOError)?;
+                }
+            }
 
-            let file_name = push_path(&year_path, &format!("{:02}.html", month_num));
-            info!("Generating the daily of {}/{}", year_num, month_num);
-            File::create(file_name)
-                .and_then(|f| {
-                    let mut buf = BufWriter::new(f);
-                    generate_monthly(&mut buf, year_num, month_num as u32, days)
+            if config.page_granularity() == PageGranularity::Day {
+                for (day, doc) in days.iter().enumerate() {
+                    let Some(doc) = doc else { continue };
+                    let date = Date::new(year_num, month_num, day as u32 + 1).unwrap();
+                    let i = all_dates.binary_search(&date).unwrap();
+                    let prev = i.checked_sub(1).map(|i| &all_dates[i]);
+                    let next = all_dates.get(i + 1);
+                    let file_name = push_path(&month_path, &format!("{:02}.html", day + 1));
+                    write_output(config, &sink, &file_name, |buf| {
+                        generate_day_page(buf, config, &site, &date, doc, prev, next)
+                    })
+                    .map_err(Error::IOError)?;
+                    report.record_page();
+                }
+            }
+
+            if has_photos {
+                write_output(config, &sink, &push_path(&month_path, "photos.html"), |buf| {
+                    generate_photos(buf, config, &site, year_num, month_num, &days)
+                })
+                .map_err(Error::IOError)?;
+                report.record_page();
+            }
+
+            if config.print_pages() {
+                write_output(config, &sink, &push_path(&month_path, "print.html"), |buf| {
+                    generate_print(buf, config, &site, year_num, month_num, &days)
+                })
+                .map_err(Error::IOError)?;
+                report.record_page();
+            }
+
+            if config.page_granularity() == PageGranularity::Month {
+                info!("Generating the daily of {}/{}", year_num, month_num);
+                write_output(config, &sink, &file_name, |buf| {
+                    generate_monthly(buf, config, &site, year_num, month_num, days)
+                })
+                .map_err(Error::IOError)?;
+                report.record_page();
+            }
+
+            let month_entries = feed_entries
+                .iter()
+                .filter(|e| e.date().year() == year_num && e.date().month() == month_num)
+                .cloned()
+                .collect::<Vec<_>>();
+            if feeds {
+                write_output(config, &sink, &feed_file_name, |buf| {
+                    let title = format!("Natuka.ge - {:4}/{:02}", year_num, month_num);
+                    generate_feed(buf, config, &title, &month_entries)
                 })
                 .map_err(Error::IOError)?;
+            }
         }
 
-        years.insert(year_num as u32, months);
+        years.insert(year_num, latest_days);
     }
 
-    let source_path = push_path(&current_path, "source");
+    if let Some((year, month)) = config.only() {
+        if feed_months.is_empty() {
+            report.warn(format!("No entries in {}/{:02}", year, month));
+        }
+        sink.finish().map_err(Error::IOError)?;
+        report.elapsed = started.elapsed();
+        return Ok(report);
+    }
+
+    let source_path = current_path.join("source");
     let source_path_exists = source_path.try_exists().map_err(Error::IOError)?;
     if source_path_exists {
-        copy_source(&source_path, &public_path).map_err(Error::IOError)?;
+        copy_source(&sink, &source_path, &public_path).map_err(Error::IOError)?;
+    }
+
+    if config.robots_txt() {
+        // A sitemap can only come from `source/` for now.
+        let sitemap = push_path(&source_path, "sitemap.xml")
+            .try_exists()
+            .map_err(Error::IOError)?;
+        write_output(config, &sink, &push_path(&public_path, "robots.txt"), |buf| {
+            write_robots_txt(buf, config, sitemap)
+        })
+        .map_err(Error::IOError)?;
     }
 
-    let albums_path = push_path(&current_path, "albums.lisp");
+    let albums_path = current_path.join("albums.lisp");
     let album_path_exists = albums_path.try_exists().map_err(Error::IOError)?;
     if album_path_exists {
-        generate_albums(albums_path, push_path(&public_path, "albums.html"))?;
+        generate_albums(config, &sink, albums_path, &public_path, &mut report)?;
+        report.record_page();
+    }
+
+    if source_times.is_some() && !any_changed {
+        info!("No month has changed; keeping the index and the feeds");
+        sink.finish().map_err(Error::IOError)?;
+        report.elapsed = started.elapsed();
+        return Ok(report);
     }
 
     let index_file_name = push_path(&public_path, "index.html");
     info!("Generating the index file");
-    File::create(index_file_name)
-        .and_then(|f| {
-            let mut buf = BufWriter::new(f);
-            generate_index(&mut buf, years.iter())
+    write_output(config, &sink, &index_file_name, |buf| {
+        generate_index(buf, config, &featured, years.iter())
+    })
+    .map_err(Error::IOError)?;
+    report.record_page();
+
+    if config.archive_page() {
+        info!("Generating the archive page");
+        write_output(config, &sink, &push_path(&public_path, "archive.html"), |buf| {
+            generate_archive(buf, config, &all_dates)
         })
-        .map_err(Error::IOError)
+        .map_err(Error::IOError)?;
+        report.record_page();
+    }
+
+    if feeds {
+        let feed_file_name = push_path(&public_path, "feed.xml");
+        info!("Generating the feed");
+        write_output(config, &sink, &feed_file_name, |buf| {
+            generate_feed(buf, config, "Natuka.ge", &feed_entries)
+        })
+        .map_err(Error::IOError)?;
+    }
+
+    write_output(config, &sink, &push_path(&public_path, "links.json"), |buf| {
+        write_links_json(buf, &links)
+    })
+    .map_err(Error::IOError)?;
+
+    if feeds {
+        let opml_file_name = push_path(&public_path, "feeds.opml");
+        write_output(config, &sink, &opml_file_name, |buf| {
+            generate_opml(buf, config, &feed_months)
+        })
+        .map_err(Error::IOError)?;
+    }
+
+    // A build of one month leaves the other months as they were.
+    if config.only().is_none() {
+        fs::create_dir_all(cache_dir)
+            .and_then(|_| fs::write(&state_path, state))
+            .map_err(Error::IOError)?;
+    }
+
+    sink.finish().map_err(Error::IOError)?;
+    report.elapsed = started.elapsed();
+    Ok(report)
+}
+
+/// What the pages of a month depend on beyond its own sources: the config
+/// file, and the date, the title, the tags and the series of every entry,
+/// which make the navigation, the related entries and the index. Settings
+/// given by the environment or the command line are not part of it.
+fn site_state<'a>(
+    current_path: &Path,
+    docs: impl Iterator<Item = (Date, &'a SourceDoucument)>,
+) -> String {
+    let mut state = fs::read_to_string(current_path.join(CONFIG_FILE)).unwrap_or_default();
+    for (date, doc) in docs {
+        state.push_str(&format!(
+            "\n{} {:?} {:?} {:?}",
+            date.format_iso(),
+            doc.title(),
+            doc.tags(),
+            doc.series()
+        ));
+    }
+    state
+}
+
+/// Parses every `{year}/{month}/{day}` source file under `current_path`.
+/// The files are found by `collect_dates`, which skips with a warning the
+/// months and days whose path isn't a valid date, such as `2023/02/30.lisp`.
+fn read_sources(
+    config: &Config,
+    current_path: &Path,
+    report: &mut BuildReport,
+) -> Result<Sources> {
+    if let Some(archive) = config.archive() {
+        return read_archive(config, &current_path.join(archive));
+    }
+    let mut sources = Sources::new();
+    for (date, path) in collect_dates(current_path, report)? {
+        let (year_num, month_num, day_num) = (date.year(), date.month(), date.day());
+        let days = sources
+            .entry(year_num)
+            .or_default()
+            .entry(month_num)
+            .or_insert_with(|| vec![None; 31]);
+        let source = fs::read(&path).map_err(Error::IOError)?;
+        let source = String::from_utf8(source).map_err(Error::Utf8Error)?;
+        if source.strip_prefix('\u{feff}').unwrap_or(&source).is_empty() {
+            if config.strict() {
+                return Err(Error::EmptySource(path));
+            }
+            report.warn(format!(
+                "Skipping the empty file of {}/{:02}/{:02}",
+                year_num, month_num, day_num
+            ));
+            continue;
+        }
+
+        debug!("Parsing a post of {}/{}/{}", year_num, month_num, day_num);
+        let post = parse_str_with(&source, config).map_err(|err| match err {
+            StrError::Read(err) => read_error(err),
+            StrError::Syntax(err) => Error::SyntaxError(err),
+        })?;
+        if post.meta().draft {
+            debug!("Skipping the draft of {}/{}/{}", year_num, month_num, day_num);
+            continue;
+        }
+
+        days[day_num as usize - 1] = Some(post);
+    }
+
+    Ok(sources)
+}
+
+/// The newest modification time of the sources of each month of `sources`.
+/// The month directories count too, so that removing an entry is a change.
+fn source_times(
+    config: &Config,
+    current_path: &Path,
+    sources: &Sources,
+) -> io::Result<BTreeMap<(u32, u32), SystemTime>> {
+    let months = sources
+        .iter()
+        .flat_map(|(&year, months)| months.keys().map(move |&month| (year, month)));
+    if let Some(archive) = config.archive() {
+        let modified = metadata(current_path.join(archive))?.modified()?;
+        return Ok(months.map(|month| (month, modified)).collect());
+    }
+    let mut times = BTreeMap::new();
+    let dirs = |path| {
+        fs::read_dir(path).map(|dir| {
+            dir.filter_map(|res| res.ok())
+                .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+        })
+    };
+    for year_dir in dirs(current_path.to_path_buf())? {
+        let Ok(year) = path_name_to_usize(&year_dir) else { continue };
+        let Some(months) = sources.get(&(year as u32)) else { continue };
+        for month_dir in dirs(year_dir.path())? {
+            let Ok(month) = path_name_to_usize(&month_dir) else { continue };
+            if !months.contains_key(&(month as u32)) {
+                continue;
+            }
+            let mut newest = month_dir.metadata()?.modified()?;
+            for day in fs::read_dir(month_dir.path())?.filter_map(|res| res.ok()) {
+                newest = newest.max(day.metadata()?.modified()?);
+            }
+            times.insert((year as u32, month as u32), newest);
+        }
+    }
+    Ok(times)
+}
+
+/// Whether `output` is missing or older than `newest_source`, which is unknown
+/// when the sources of its month could not be found.
+fn is_stale(output: &Path, newest_source: Option<SystemTime>) -> io::Result<bool> {
+    match metadata(output) {
+        Ok(output) => {
+            let modified = output.modified()?;
+            Ok(newest_source.is_none_or(|source| modified < source))
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(true),
+        Err(err) => Err(err),
+    }
+}
+
+fn read_archive(config: &Config, path: &Path) -> Result<Sources> {
+    let mut sources = Sources::new();
+    let reader = new_string_reader(path.to_path_buf())?;
+    let reader = if let Some(r) = reader { r } else { return Ok(sources) };
+    let mut parser = SExpParser::new(reader);
+    let expr = parser.parse_expression().map_err(read_error)?;
+    let entries = parse_archive(expr, config, parser.empty_tuples()).map_err(Error::SyntaxError)?;
+    for (date, doc) in entries {
+        if doc.meta().draft {
+            continue;
+        }
+        let days = sources
+            .entry(date.year())
+            .or_default()
+            .entry(date.month())
+            .or_insert_with(|| vec![None; 31]);
+        days[date.day() as usize - 1] = Some(doc);
+    }
+    Ok(sources)
 }
 
 fn parse_to_expression<R: Read>(reader: StringReader<R>) -> Result<Expression> {
     let mut parser = SExpParser::new(reader);
-    parser.parse_expression().map_err(|err| match err {
+    parser.parse_expression().map_err(read_error)
+}
+
+fn read_error(err: sexp::Error) -> Error {
+    match err {
         sexp::Error::IOError(err) => Error::IOError(err),
         sexp::Error::Utf8Error(err) => Error::Utf8Error(err),
         sexp::Error::ParseError(err) => Error::ParseError(err),
-    })
+    }
 }
 
-fn copy_source(src: &PathBuf, dst: &PathBuf) -> io::Result<()> {
+fn copy_source(sink: &Sink, src: &PathBuf, dst: &PathBuf) -> io::Result<()> {
     let src_dir = fs::read_dir(src)?;
     for f in src_dir.into_iter().filter_map(|res| res.ok()) {
         let src_path = f.path();
@@ -178,67 +667,174 @@ fn copy_source(src: &PathBuf, dst: &PathBuf) -> io::Result<()> {
         };
 
         if file_type.is_dir() {
-            copy_source(&src_path, &push_path(dst, entry_name))?;
+            let dst_path = push_path(dst, entry_name);
+            sink.create_dir(&dst_path)?;
+            copy_source(sink, &src_path, &dst_path)?;
         } else if file_type.is_file() {
             let dst_path = push_path(dst, entry_name);
-            fs::copy(src_path, dst_path)?;
+            sink.copy(&src_path, &dst_path)?;
         }
     }
     Ok(())
 }
 
-fn generate_albums(src: PathBuf, dst: PathBuf) -> Result<()> {
+/// Creates a generated file, which is written with the configured line ending.
+/// Writes the page or file at `path` to `sink` with what `write` produces.
+fn write_output<F>(config: &Config, sink: &Sink, path: &Path, write: F) -> io::Result<()>
+where
+    F: FnOnce(&mut NewlineWriter<SinkFile>) -> io::Result<()>,
+{
+    let mut writer = NewlineWriter::new(sink.create(path)?, config.line_ending());
+    write(&mut writer)?;
+    writer.into_inner().finish()
+}
+
+fn generate_albums(
+    config: &Config,
+    sink: &Sink,
+    src: PathBuf,
+    public_path: &Path,
+    report: &mut BuildReport,
+) -> Result<()> {
     let reader = new_string_reader(src)?;
     let reader = if let Some(r) = reader { r } else { return Ok(()) };
     let expr = parse_to_expression(reader)?;
-    let album_index = parse_albums(expr).map_err(Error::SyntaxError)?;
-    File::create(dst).and_then(|f| {
-        let mut writer = BufWriter::new(f);
-        albums_gen::generate_albums(&mut writer, album_index)
+    let mut album_index = parse_albums(expr).map_err(Error::SyntaxError)?;
+    if config.duplicate_albums() != DuplicateAlbums::Keep {
+        for (artist, album) in album_index.duplicates() {
+            report.warn(format!("{} is listed more than once under {}", album, artist));
+        }
+    }
+    if config.duplicate_albums() == DuplicateAlbums::Dedup {
+        album_index.dedup();
+    }
+    if config.albums_json() {
+        write_output(config, sink, &public_path.join("albums.json"), |writer| {
+            write_albums_json(writer, config, &album_index)
+        })
+        .map_err(Error::IOError)?;
+    }
+    write_output(config, sink, &public_path.join("albums.html"), |writer| {
+        albums_gen::generate_albums(writer, config, album_index)
     }).map_err(Error::IOError)
 }
 
-fn handle_image(converter: &ImageConverter, src: SourceDoucument) -> Result<OutputDocument> {
-    src.into_contents()
+fn image_error(err: crate::image::Error) -> Error {
+    match err {
+        crate::image::Error::ImageError(err) => Error::ImageError(err),
+        crate::image::Error::IOError(err) => Error::IOError(err),
+        crate::image::Error::MissingSource(name) => Error::MissingImage(name),
+    }
+}
+
+/// Replaces the image names of the document with their converted images.
+fn handle_image(images: &HashMap<String, ImagePath>, src: SourceDoucument) -> OutputDocument {
+    let meta = src.meta().clone();
+    let contents = src
+        .into_contents()
         .into_iter()
-        .map(|item| handle_image_items(converter, item))
-        .collect::<Result<Vec<OutputItem>>>()
-        .map(Document::new)
+        .map(|item| handle_image_items(images, item))
+        .collect();
+    Document::new(contents).with_meta(meta)
 }
 
-fn handle_image_items(converter: &ImageConverter, src: SourceItem) -> Result<OutputItem> {
+fn handle_image_items(images: &HashMap<String, ImagePath>, src: SourceItem) -> OutputItem {
     match src {
-        Item::Images(image) => {
-            let mut images = Vec::with_capacity(image.items.len());
-            for item in image.items {
-                let path = converter
-                    .convert_image(item.data)
-                    .map_err(|err| match err {
-                        crate::image::Error::ImageError(err) => Error::ImageError(err),
-                        crate::image::Error::IOError(err) => Error::IOError(err),
-                    })?;
-                images.push(ImageItem {
-                    data: path,
+        Item::Images(image) => Item::Images(Images {
+            title: image.title,
+            items: image
+                .items
+                .into_iter()
+                .map(|item| ImageItem {
+                    data: images[&item.data].clone(),
                     caption: item.caption,
-                });
-            }
+                })
+                .collect(),
+            caption: image.caption,
+        }),
+        Item::List(li) => Item::List(
+            li.into_iter()
+                .map(|item| handle_image_items(images, item))
+                .collect(),
+        ),
+        Item::OrderedList(li) => Item::OrderedList(
+            li.into_iter()
+                .map(|item| handle_image_items(images, item))
+                .collect(),
+        ),
+        Item::ListEntry(li) => Item::ListEntry(
+            li.into_iter()
+                .map(|item| handle_image_items(images, item))
+                .collect(),
+        ),
+        Item::Text(x) => Item::Text(x),
+        Item::Header(level, x) => Item::Header(level, x),
+        Item::Anchor(x) => Item::Anchor(x),
+        Item::Tags(x) => Item::Tags(x),
+        Item::Title(x) => Item::Title(x),
+        Item::Class(x) => Item::Class(x),
+        Item::Lang(x) => Item::Lang(x),
+        Item::Geo { lat, lon, label } => Item::Geo { lat, lon, label },
+        Item::Series { name, part } => Item::Series { name, part },
+        Item::At { time, body } => Item::At {
+            time,
+            body: body
+                .into_iter()
+                .map(|item| handle_image_items(images, item))
+                .collect(),
+        },
+        Item::Details { summary, body } => Item::Details {
+            summary,
+            body: body
+                .into_iter()
+                .map(|item| handle_image_items(images, item))
+                .collect(),
+        },
+        Item::Quote { body, cite } => Item::Quote {
+            body: body
+                .into_iter()
+                .map(|item| handle_image_items(images, item))
+                .collect(),
+            cite,
+        },
+    }
+}
 
-            Ok(Item::Images(Images {
-                title: image.title,
-                items: images,
-            }))
-        }
-        Item::List(li) => {
-            let mut contents = Vec::with_capacity(li.len());
-            for item in li {
-                let output_item = handle_image_items(converter, item)?;
-                contents.push(output_item);
+/// The featured dates from the config that have an entry, in the listed order.
+fn featured_dates(config: &Config, sources: &Sources, report: &mut BuildReport) -> Vec<Date> {
+    config
+        .featured()
+        .iter()
+        .filter_map(|s| {
+            let date = Date::parse(s).filter(|date| {
+                sources
+                    .get(&date.year())
+                    .and_then(|months| months.get(&date.month()))
+                    .and_then(|days| days.get(date.day() as usize - 1))
+                    .is_some_and(Option::is_some)
+            });
+            if date.is_none() {
+                report.warn(format!("Featured entry {} does not exist", s));
             }
-            Ok(Item::List(contents))
-        }
-        Item::Text(x) => Ok(Item::Text(x)),
-        Item::Header(x) => Ok(Item::Header(x)),
-    }
+            date
+        })
+        .collect()
+}
+
+fn new_image_converter(
+    config: &Config,
+    current_path: &Path,
+    public_path: &Path,
+    cache_dir: &Path,
+) -> io::Result<ImageConverter> {
+    let image_dir = config.image_dir();
+    ImageConverter::new(
+        current_path.join(image_dir),
+        public_path.join(image_dir),
+        cache_dir.join("img"),
+        config.path(&format!("/{}", image_dir)),
+    )
+    .map(|converter| converter.with_name_templates(config.thumbnail_name(), config.image_name()))
 }
 
 fn new_string_reader(src: PathBuf) -> Result<Option<StringReader<BufReader<File>>>> {
@@ -247,6 +843,39 @@ fn new_string_reader(src: PathBuf) -> Result<Option<StringReader<BufReader<File>
         .map_err(Error::IOError)
 }
 
+/// Removes everything in `dir` except the entries named in `keep`.
+fn clean_dir(dir: &Path, current_path: &Path, keep: &[String]) -> Result<()> {
+    if !dir.try_exists().map_err(Error::IOError)? {
+        return Ok(());
+    }
+    let canonical = dir.canonicalize().map_err(Error::IOError)?;
+    let current_path = current_path.canonicalize().map_err(Error::IOError)?;
+    if !is_safe_to_clean(&canonical, &current_path) {
+        return Err(Error::UnsafeClean(canonical));
+    }
+    info!("Cleaning {}", canonical.display());
+    for entry in fs::read_dir(&canonical).map_err(Error::IOError)? {
+        let entry = entry.map_err(Error::IOError)?;
+        if keep.iter().any(|name| entry.file_name() == name.as_str()) {
+            continue;
+        }
+        // `file_type` doesn't follow symlinks, so linked directories are only unlinked.
+        let removed = if entry.file_type().map_err(Error::IOError)?.is_dir() {
+            fs::remove_dir_all(entry.path())
+        } else {
+            fs::remove_file(entry.path())
+        };
+        removed.map_err(Error::IOError)?;
+    }
+    Ok(())
+}
+
+/// Rejects the root and the directories containing the sources, as a
+/// symlinked `public/` may point anywhere.
+fn is_safe_to_clean(dir: &Path, current_path: &Path) -> bool {
+    dir.parent().is_some() && !current_path.starts_with(dir)
+}
+
 fn mkdir_if_not_exists(path: PathBuf) -> io::Result<()> {
     let exists = path.try_exists()?;
     if !exists {
@@ -256,22 +885,873 @@ fn mkdir_if_not_exists(path: PathBuf) -> io::Result<()> {
     Ok(())
 }
 
-fn path_name_to_usize(entry: &DirEntry) -> Result<usize> {
-    Path::new(&entry.file_name())
-        .file_stem()
-        .ok_or_else(|| path_name_err(&entry))
-        .and_then(|s| {
-            s.to_str()
-                .and_then(|s| s.parse::<usize>().ok())
-                .ok_or_else(|| path_name_err(&entry))
-        })
-}
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{BTreeMap, HashMap},
+        fs::{self, File},
+        path::{Path, PathBuf},
+        time::{Duration, SystemTime},
+    };
+
+    use crate::{
+        build,
+        cli::Args,
+        config::Config,
+        is_safe_to_clean, new_image_converter,
+        CONFIG_FILE,
+        report::BuildReport,
+        Error,
+        test_util::{config_with, TempDir, TINY_WEBP},
+    };
+
+    fn build_fixture(dir: &TempDir, config: &Config) -> BuildReport {
+        build(config, dir.path(), &dir.join("cache")).unwrap()
+    }
+
+    #[test]
+    fn custom_image_dir() {
+        let dir = TempDir::new();
+        let config = config_with(&[("image_dir", "photos")]);
+        dir.write("photos/a.webp", TINY_WEBP);
+        let converter = new_image_converter(
+            &config,
+            dir.path(),
+            &dir.join("public"),
+            &dir.join("cache"),
+        )
+        .unwrap();
+        let (path, _) = converter
+            .convert_images(&["a.webp".to_string()], &[], 1)
+            .unwrap()
+            .0
+            .remove(0);
+        assert_eq!(path.actual_path(), "/photos/a.webp");
+        assert!(dir.join("public/photos/a.webp").exists());
+        assert!(dir.join("public/photos/a-thumb.jpeg").exists());
+        assert!(!dir.join("img").exists());
+    }
+
+    #[test]
+    fn feeds_opml_lists_months() {
+        let dir = TempDir::new();
+        dir.write("2023/04/01.lisp", br#"((txt "April"))"#);
+        dir.write("2023/05/05.lisp", br#"((txt "May"))"#);
+        build_fixture(&dir, &Config::default());
+        // Feeds need absolute ids, so they aren't written without `base_url`.
+        for feed in ["feeds.opml", "feed.xml", "2023/04.xml"] {
+            assert!(!dir.join(&format!("public/{}", feed)).exists(), "{}", feed);
+        }
+
+        build_fixture(&dir, &config_with(&[("base_url", "https://example.com")]));
+        let opml = fs::read_to_string(dir.join("public/feeds.opml")).unwrap();
+        assert_eq!(opml.matches("<outline ").count(), 3);
+        assert!(opml.contains(r#"xmlUrl="https://example.com/feed.xml""#));
+        assert!(opml.contains(r#"xmlUrl="https://example.com/2023/04.xml""#));
+        assert!(opml.contains(r#"xmlUrl="https://example.com/2023/05.xml""#));
+        let april = fs::read_to_string(dir.join("public/2023/04.xml")).unwrap();
+        assert!(april.contains("<summary>April</summary>"));
+        assert!(!april.contains("May"));
+    }
+
+    #[test]
+    fn featured_skips_missing_entries() {
+        let dir = TempDir::new();
+        dir.write("2023/05/05.lisp", br#"((txt "May"))"#);
+        let config = config_with(&[("featured", "2023/05/06,2023/05/05,2023/13/01")]);
+        build_fixture(&dir, &config);
+
+        let index = fs::read_to_string(dir.join("public/index.html")).unwrap();
+        assert!(index.contains(r#"<ul><li><a href="/2023/05#05">2023/05/05</a></li></ul>"#));
+        assert!(!index.contains("2023/05/06"));
+    }
+
+    #[test]
+    fn day_granularity_writes_day_pages() {
+        let dir = TempDir::new();
+        dir.write("2023/04/30.lisp", br#"((txt "April"))"#);
+        dir.write("2023/05/05.lisp", br#"((txt "May 5"))"#);
+        dir.write("2023/05/06.lisp", br#"((txt "May 6"))"#);
+        let config = config_with(&[("page_granularity", "day")]);
+        build_fixture(&dir, &config);
+
+        let april = fs::read_to_string(dir.join("public/2023/04/30.html")).unwrap();
+        assert!(april.contains(r#"<nav><a rel="next" href="/2023/05/05">2023/05/05</a></nav>"#));
+        let may5 = fs::read_to_string(dir.join("public/2023/05/05.html")).unwrap();
+        assert!(may5.contains("<p>May 5</p>"));
+        assert!(may5.contains(concat!(
+            r#"<nav><a rel="prev" href="/2023/04/30">2023/04/30</a> | "#,
+            r#"<a rel="next" href="/2023/05/06">2023/05/06</a></nav>"#
+        )));
+        assert!(dir.join("public/2023/05/06.html").exists());
+        assert_eq!(fs::read_dir(dir.join("public/2023/05")).unwrap().count(), 2);
+        assert!(!dir.join("public/2023/05.html").exists());
+        let index = fs::read_to_string(dir.join("public/index.html")).unwrap();
+        assert!(index.contains(r#"<a href="/2023/05/06">"#));
+    }
+
+    #[test]
+    fn footer_on_every_page() {
+        let dir = TempDir::new();
+        dir.write("2023/05/05.lisp", br#"((txt "May"))"#);
+        dir.write("albums.lisp", br#"((artist "A" (studio "X" (2020 1 1))))"#);
+        let config = config_with(&[
+            ("footer", "true"),
+            ("footer_text", "Made by hand"),
+            ("page_granularity", "day"),
+        ]);
+        build_fixture(&dir, &config);
+
+        for page in ["index.html", "albums.html", "2023/05/05.html"] {
+            let html = fs::read_to_string(dir.join(&format!("public/{}", page))).unwrap();
+            assert!(
+                html.ends_with("<footer>Made by hand</footer></body></html>"),
+                "{}",
+                page
+            );
+        }
+    }
+
+    #[test]
+    fn report_json() {
+        let dir = TempDir::new();
+        dir.write("2023/04/01.lisp", br#"((txt "April"))"#);
+        dir.write("2023/05/05.lisp", br#"((txt "May"))"#);
+        let config = config_with(&[("featured", "2023/05/06")]);
+        let report = build_fixture(&dir, &config);
+
+        let mut buf = Vec::new();
+        report.write_json(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(json["pages"], 3);
+        assert_eq!(json["images"]["converted"], 0);
+        assert_eq!(
+            json["warnings"],
+            serde_json::json!(["Featured entry 2023/05/06 does not exist"])
+        );
+    }
+
+    #[test]
+    fn report_entries_per_month() {
+        let dir = TempDir::new();
+        dir.write("2023/04/01.lisp", br#"((txt "April"))"#);
+        dir.write("2023/05/05.lisp", br#"((txt "May"))"#);
+        dir.write("2023/05/06.lisp", br#"((txt "May"))"#);
+        dir.write("2024/01/01.lisp", br#"((txt "January"))"#);
+        let report = build_fixture(&dir, &Config::default());
+
+        let mut buf = Vec::new();
+        report.write_json(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(
+            json["entries"],
+            serde_json::json!({
+                "total": 4,
+                "years": {
+                    "2023": {"total": 3, "months": {"04": 1, "05": 2}},
+                    "2024": {"total": 1, "months": {"01": 1}},
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn build_from_archive() {
+        let dir = TempDir::new();
+        dir.write(
+            "archive.lisp",
+            br#"((entry 2023 4 1 ((txt "April")))
+                 (entry 2023 5 5 ((txt "May"))))"#,
+        );
+        let mut config = Config::default();
+        config.apply_args(&Args {
+            archive: Some(PathBuf::from("archive.lisp")),
+            ..Args::default()
+        });
+        build_fixture(&dir, &config);
+
+        let april = fs::read_to_string(dir.join("public/2023/04.html")).unwrap();
+        assert!(april.contains("<p>April</p>"));
+        let may = fs::read_to_string(dir.join("public/2023/05.html")).unwrap();
+        assert!(may.contains(r#"<h2 id="05">"#));
+        assert!(may.contains("<p>May</p>"));
+    }
+
+    fn read_tree(root: &Path) -> BTreeMap<PathBuf, Vec<u8>> {
+        let mut files = BTreeMap::new();
+        let mut dirs = vec![root.to_path_buf()];
+        while let Some(dir) = dirs.pop() {
+            for entry in fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    dirs.push(path);
+                } else {
+                    let content = fs::read(&path).unwrap();
+                    files.insert(path.strip_prefix(root).unwrap().to_path_buf(), content);
+                }
+            }
+        }
+        files
+    }
+
+    #[test]
+    fn jobs_do_not_change_output() {
+        let outputs = [1, 4].map(|jobs| {
+            let dir = TempDir::new();
+            for (i, name) in ["a", "b", "c", "d", "e"].iter().enumerate() {
+                let mut webp = TINY_WEBP.to_vec();
+                webp.extend(std::iter::repeat_n(0, i));
+                dir.write(&format!("img/{}.webp", name), &webp);
+            }
+            dir.write(
+                "2023/05/05.lisp",
+                br#"((img "" ("a.webp") ("b.webp" "B")) (li (img "" ("c.webp"))))"#,
+            );
+            dir.write("2023/05/06.lisp", br#"((img "" ("d.webp") ("e.webp") ("a.webp")))"#);
+            let mut config = Config::default();
+            config.apply_args(&Args {
+                jobs: Some(jobs),
+                ..Args::default()
+            });
+            let report = build_fixture(&dir, &config);
+            assert_eq!(report.images_converted, 5);
+            read_tree(&dir.join("public"))
+        });
+        assert!(outputs[0].len() > 10);
+        assert_eq!(outputs[0], outputs[1]);
+    }
+
+    #[test]
+    fn emit_plain_text() {
+        let dir = TempDir::new();
+        dir.write(
+            "2023/05/05.lisp",
+            br#"((h "Morning") (txt "Woke " (b "early") ".") (li (txt "one") (txt "two")))"#,
+        );
+        let mut config = Config::default();
+        config.apply_args(&Args {
+            emit_txt: true,
+            ..Args::default()
+        });
+        build_fixture(&dir, &config);
+
+        let text = fs::read_to_string(dir.join("public/2023/05/05.txt")).unwrap();
+        assert_eq!(text, "Morning\nWoke early.\none\ntwo\n");
+    }
+
+    #[test]
+    fn archive_page_links_every_day() {
+        let dir = TempDir::new();
+        for day in ["2023/04/01", "2023/05/05", "2023/05/06"] {
+            dir.write(&format!("{}.lisp", day), br#"((txt "x"))"#);
+        }
+        let config = config_with(&[("archive_page", "true")]);
+        build_fixture(&dir, &config);
+
+        let archive = fs::read_to_string(dir.join("public/archive.html")).unwrap();
+        for href in ["/2023/04#01", "/2023/05#05", "/2023/05#06"] {
+            assert!(archive.contains(&format!(r#"<a href="{}">"#, href)), "{}", href);
+        }
+    }
+
+    #[test]
+    fn feed_limit_keeps_latest_entries() {
+        let dir = TempDir::new();
+        for day in 1..=5 {
+            dir.write(&format!("2023/05/{:02}.lisp", day), br#"((txt "x"))"#);
+        }
+        let config = config_with(&[("feed_limit", "2"), ("base_url", "https://example.com")]);
+        build_fixture(&dir, &config);
+
+        for feed in ["feed.xml", "2023/05.xml"] {
+            let xml = fs::read_to_string(dir.join(&format!("public/{}", feed))).unwrap();
+            assert_eq!(xml.matches("<entry>").count(), 2, "{}", feed);
+            assert!(xml.contains("<title>2023/05/05</title>"), "{}", feed);
+            assert!(xml.contains("<title>2023/05/04</title>"), "{}", feed);
+        }
+    }
+
+    #[test]
+    fn validate_built_pages() {
+        let dir = TempDir::new();
+        dir.write("img/a.webp", TINY_WEBP);
+        dir.write("albums.lisp", br#"((artist "A" (studio "X" (2020 1 1) (tracks 3))))"#);
+        dir.write(
+            "2023/05/05.lisp",
+            br#"((title "Trip") (tags "travel") (series "Trip" 1)
+                (h "Morning") (txt "x" (b "bold") (sup "2") (abbr "HTML" "HyperText"))
+                (li "a" (ol "one" (li "b") "two")) (ordered "x")
+                (details "More" (txt "Body") (li "c"))
+                (quote (txt "To be") (cite "Hamlet"))
+                (at "14:30" (txt "later"))
+                (img "Gallery" ("a.webp" "A") ("missing.webp")))"#,
+        );
+        dir.write("2023/05/06.lisp", br#"((tags "travel") (li (img "" ("a.webp"))))"#);
+        let mut config = config_with(&[
+            ("base_url", "https://example.com"),
+            ("photo_pages", "true"),
+            ("print_pages", "true"),
+            ("archive_page", "true"),
+            ("highlight_search", "true"),
+            ("number_headings", "true"),
+            ("group_by_week", "true"),
+            ("footer", "true"),
+        ]);
+        config.apply_args(&Args {
+            validate_html: true,
+            ..Args::default()
+        });
+        build_fixture(&dir, &config);
+        let pages = ["2023/05.html", "2023/05/photos.html", "2023/05/print.html"];
+        for page in ["index.html", "archive.html", "albums.html"].iter().chain(&pages) {
+            assert!(dir.join(&format!("public/{}", page)).is_file(), "{}", page);
+        }
+    }
+
+    #[test]
+    fn crlf_line_endings() {
+        let dir = TempDir::new();
+        dir.write("2023/05/05.lisp", br#"((txt "x"))"#);
+        let css = dir.write("critical.css", b"body {\n  margin: 0;\n}\n");
+        for (line_ending, crlf) in [("lf", false), ("crlf", true)] {
+            let config = config_with(&[
+                ("line_ending", line_ending),
+                ("critical_css", css.to_str().unwrap()),
+            ]);
+            build_fixture(&dir, &config);
+            let html = fs::read_to_string(dir.join("public/index.html")).unwrap();
+            assert!(html.contains("body {"), "{}", line_ending);
+            assert_eq!(html.contains("\r\n"), crlf, "{}", line_ending);
+            assert_eq!(html.matches('\n').count(), 3, "{}", line_ending);
+        }
+    }
+
+    #[test]
+    fn changed_only_rebuilds_touched_month() {
+        let dir = TempDir::new();
+        dir.write("2023/04/01.lisp", br#"((txt "x"))"#);
+        let touched = dir.write("2023/05/05.lisp", br#"((txt "y"))"#);
+        let mut config = config_with(&[("base_url", "https://example.com")]);
+        build_fixture(&dir, &config);
+        let outputs = ["2023/04.html", "2023/05.html", "2023/04.xml", "index.html", "feed.xml"];
+        for output in outputs {
+            dir.write(&format!("public/{}", output), b"stale");
+        }
+
+        config.apply_args(&Args {
+            changed_only: true,
+            ..Args::default()
+        });
+        build_fixture(&dir, &config);
+        for output in outputs {
+            assert_eq!(fs::read(dir.join(&format!("public/{}", output))).unwrap(), b"stale");
+        }
+
+        File::options()
+            .write(true)
+            .open(touched)
+            .and_then(|f| f.set_modified(SystemTime::now() + Duration::from_secs(10)))
+            .unwrap();
+        build_fixture(&dir, &config);
+        let stale = outputs
+            .into_iter()
+            .filter(|output| fs::read(dir.join(&format!("public/{}", output))).unwrap() == b"stale")
+            .collect::<Vec<_>>();
+        assert_eq!(stale, ["2023/04.html", "2023/04.xml"]);
+    }
 
-fn path_name_err(day: &DirEntry) -> Error {
-    let path = day
-        .path()
-        .as_path()
-        .to_str()
-        .map_or("".to_string(), |s| s.to_string());
-    Error::PathNameError(path)
+    #[test]
+    fn changed_only_rebuilds_all_on_new_entry_or_config() {
+        let dir = TempDir::new();
+        dir.write("2023/04/01.lisp", br#"((txt "x"))"#);
+        build_fixture(&dir, &Config::default());
+        let mut config = Config::default();
+        config.apply_args(&Args {
+            changed_only: true,
+            ..Args::default()
+        });
+        let is_stale = |output: &str| fs::read(dir.join(output)).unwrap() == b"stale";
+
+        dir.write("public/2023/04.html", b"stale");
+        dir.write("2023/05/05.lisp", br#"((txt "y"))"#);
+        build_fixture(&dir, &config);
+        assert!(!is_stale("public/2023/04.html"));
+        assert!(dir.join("public/2023/05.html").exists());
+
+        dir.write("public/2023/04.html", b"stale");
+        build_fixture(&dir, &config);
+        assert!(is_stale("public/2023/04.html"));
+        dir.write(CONFIG_FILE, b"lang = en\n");
+        build_fixture(&dir, &config);
+        assert!(!is_stale("public/2023/04.html"));
+    }
+
+    #[test]
+    fn clean_removes_stale_files() {
+        let dir = TempDir::new();
+        dir.write("2023/05/05.lisp", br#"((txt "x"))"#);
+        dir.write("public/2022/01.html", b"stale");
+        dir.write("public/CNAME", b"example.com");
+        let mut config = config_with(&[("keep", "CNAME")]);
+        build_fixture(&dir, &config);
+        assert!(dir.join("public/2022/01.html").exists());
+
+        config.apply_args(&Args {
+            clean: true,
+            ..Args::default()
+        });
+        build_fixture(&dir, &config);
+        assert!(!dir.join("public/2022").exists());
+        assert!(dir.join("public/2023/05.html").exists());
+        assert_eq!(fs::read(dir.join("public/CNAME")).unwrap(), b"example.com");
+    }
+
+    #[test]
+    fn refuse_to_clean_ancestors() {
+        let current = Path::new("/home/user/diary");
+        assert!(is_safe_to_clean(&current.join("public"), current));
+        assert!(!is_safe_to_clean(current, current));
+        assert!(!is_safe_to_clean(Path::new("/home"), current));
+        assert!(!is_safe_to_clean(Path::new("/"), current));
+    }
+
+    #[test]
+    fn feed_full_content() {
+        let dir = TempDir::new();
+        dir.write("2023/05/05.lisp", br#"((txt "First") (txt "Later"))"#);
+        for (full, later) in [("true", true), ("false", false)] {
+            let config = config_with(&[
+                ("feed_full_content", full),
+                ("base_url", "https://example.com"),
+            ]);
+            build_fixture(&dir, &config);
+            let xml = fs::read_to_string(dir.join("public/feed.xml")).unwrap();
+            assert!(xml.contains("<summary>First</summary>"), "{}", full);
+            assert_eq!(xml.contains("Later"), later, "{}", full);
+        }
+        let xml = fs::read_to_string(dir.join("public/2023/05.xml")).unwrap();
+        assert!(!xml.contains("<content"));
+
+        let config = config_with(&[
+            ("feed_full_content", "true"),
+            ("base_url", "https://example.com"),
+            ("base_path", "/diary"),
+        ]);
+        build_fixture(&dir, &config);
+        let xml = fs::read_to_string(dir.join("public/2023/05.xml")).unwrap();
+        assert!(xml.contains(concat!(
+            r#"<content type="html" xml:base="https://example.com/diary/">"#,
+            "&lt;p&gt;First&lt;/p&gt;&lt;p&gt;Later&lt;/p&gt;</content>"
+        )));
+    }
+
+    #[test]
+    fn warn_dangling_fragments() {
+        let dir = TempDir::new();
+        dir.write(
+            "2023/05/05.lisp",
+            br##"((anchor "setup") (txt (a "ok" "#setup") (a "missing" "#nowhere")))"##,
+        );
+        dir.write("2023/05/06.lisp", br##"((txt (p 2023 5 5) (a "x" "/2023/05#setup")))"##);
+        let report = build_fixture(&dir, &Config::default());
+        assert_eq!(report.warnings, ["Link to \"#nowhere\" in 2023/05/05 has no target"]);
+    }
+
+    #[test]
+    fn write_site_into_tar() {
+        let dir = TempDir::new();
+        dir.write("2023/05/05.lisp", br#"((txt "May") (img "" ("a.webp")))"#);
+        dir.write("img/a.webp", TINY_WEBP);
+        dir.write("source/css/style.css", b"body {}");
+        let mut config = Config::default();
+        config.apply_args(&Args {
+            tar: Some(PathBuf::from("site.tar")),
+            ..Args::default()
+        });
+        build_fixture(&dir, &config);
+
+        assert!(!dir.join("public").exists());
+        let entries = crate::tar::tests::entries(&fs::read(dir.join("site.tar")).unwrap());
+        let content = |path: &str| {
+            entries
+                .iter()
+                .find(|(name, _)| name == path)
+                .map(|(_, content)| String::from_utf8_lossy(content).into_owned())
+        };
+        assert!(content("index.html").is_some_and(|html| html.contains("2023")));
+        assert!(content("2023/05.html").is_some_and(|html| html.contains("May")));
+        assert_eq!(content("css/").as_deref(), Some(""));
+        assert_eq!(content("css/style.css").as_deref(), Some("body {}"));
+        assert!(content("img/a.webp").is_some());
+        assert!(content("img/a-thumb.jpeg").is_some());
+    }
+
+    #[test]
+    fn duplicate_albums() {
+        let dir = TempDir::new();
+        dir.write("2023/05/05.lisp", br#"((txt "x"))"#);
+        dir.write(
+            "albums.lisp",
+            br#"((artist "A" (studio "X" (2020 1 1))
+                (live "X" (2021 1 1)) (studio "Y" (2022 1 1))))"#,
+        );
+        let build_with = |mode: &str| {
+            let config = config_with(&[("duplicate_albums", mode)]);
+            let report = build_fixture(&dir, &config);
+            let html = fs::read_to_string(dir.join("public/albums.html")).unwrap();
+            (report.warnings, html.matches("<li>X</li>").count())
+        };
+        let warning = "X is listed more than once under A".to_string();
+        assert_eq!(build_with("warn"), (vec![warning.clone()], 2));
+        assert_eq!(build_with("dedup"), (vec![warning], 1));
+        assert_eq!(build_with("keep"), (vec![], 2));
+    }
+
+    #[test]
+    fn timed_parts_in_order() {
+        let dir = TempDir::new();
+        dir.write(
+            "2023/05/05.lisp",
+            br#"((at "21:00" (txt "Night")) (at "07:30" (txt "Morning")))"#,
+        );
+        build_fixture(&dir, &Config::default());
+
+        let may = fs::read_to_string(dir.join("public/2023/05.html")).unwrap();
+        assert!(may.contains(concat!(
+            r#"<section class="at"><h4><time>07:30</time></h4><p>Morning</p></section>"#,
+            r#"<section class="at"><h4><time>21:00</time></h4><p>Night</p></section>"#,
+        )));
+    }
+
+    #[test]
+    fn skip_empty_sources() {
+        let dir = TempDir::new();
+        dir.write("2023/05/05.lisp", br#"((txt "x"))"#);
+        dir.write("2023/05/06.lisp", b"");
+        let report = build_fixture(&dir, &Config::default());
+        assert_eq!(report.warnings, ["Skipping the empty file of 2023/05/06"]);
+        assert_eq!(report.total_entries(), 1);
+
+        let config = config_with(&[("strict", "true")]);
+        assert!(matches!(
+            build(&config, dir.path(), &dir.join("cache")),
+            Err(Error::EmptySource(path)) if path.ends_with("2023/05/06.lisp")
+        ));
+    }
+
+    #[test]
+    fn warn_misfiled_entries() {
+        let dir = TempDir::new();
+        dir.write("2023/02/28.lisp", br#"((txt "ok"))"#);
+        dir.write("2023/02/30.lisp", br#"((txt "misfiled"))"#);
+        dir.write("2023/13/01.lisp", br#"((txt "misfiled"))"#);
+        let report = build_fixture(&dir, &Config::default());
+        let mut warnings = report.warnings.clone();
+        warnings.sort();
+        assert_eq!(
+            warnings,
+            [
+                "Skipping 2023/02/30, which is not a valid date",
+                "Skipping 2023/13, which is not a valid month",
+            ]
+        );
+        assert_eq!(report.total_entries(), 1);
+    }
+
+    #[test]
+    fn verify_cache_purges_truncated_thumbnail() {
+        let dir = TempDir::new();
+        dir.write("img/a.webp", TINY_WEBP);
+        dir.write("2023/05/05.lisp", br#"((img "" ("a.webp")))"#);
+        build_fixture(&dir, &Config::default());
+        let thumbnail = fs::read(dir.join("cache/img/a-thumb.jpeg")).unwrap();
+        dir.write("cache/img/a-thumb.jpeg", &thumbnail[..thumbnail.len() / 2]);
+
+        let mut config = Config::default();
+        config.apply_args(&Args {
+            verify_cache: true,
+            ..Args::default()
+        });
+        let verify = |config: &Config| {
+            new_image_converter(config, dir.path(), &dir.join("public"), &dir.join("cache"))
+                .unwrap()
+                .verify_cache(config.purge_cache())
+                .unwrap()
+        };
+        assert_eq!(verify(&config), ["a"]);
+        assert!(dir.join("cache/img/a.xxh3").exists());
+
+        let mut config = Config::default();
+        config.apply_args(&Args {
+            purge_cache: true,
+            ..Args::default()
+        });
+        let report = build_fixture(&dir, &config);
+        assert_eq!(report.warnings, ["Purged the broken cache entry of \"a\""]);
+        assert_eq!(report.images_converted, 1);
+        assert_eq!(fs::read(dir.join("cache/img/a-thumb.jpeg")).unwrap(), thumbnail);
+        assert!(verify(&config).is_empty());
+
+        // A thumbnail left without its hash file is purged as well, and one of
+        // other dimensions than recorded is broken even if it decodes.
+        fs::remove_file(dir.join("cache/img/a.xxh3")).unwrap();
+        dir.write("cache/img/b-thumb.jpeg", &thumbnail);
+        assert_eq!(verify(&config), ["a", "b"]);
+        assert!(!dir.join("cache/img/b-thumb.jpeg").exists());
+        build_fixture(&dir, &Config::default());
+        let mut hash = fs::read(dir.join("cache/img/a.xxh3")).unwrap();
+        hash[8] += 1;
+        dir.write("cache/img/a.xxh3", &hash);
+        assert_eq!(verify(&config), ["a"]);
+    }
+
+    #[test]
+    fn verify_cache_regenerates_broken_thumbnail() {
+        let dir = TempDir::new();
+        dir.write("img/a.webp", TINY_WEBP);
+        dir.write("2023/05/05.lisp", br#"((img "" ("a.webp")))"#);
+        build_fixture(&dir, &Config::default());
+        let thumbnail = fs::read(dir.join("cache/img/a-thumb.jpeg")).unwrap();
+        dir.write("cache/img/a-thumb.jpeg", &thumbnail[..thumbnail.len() / 2]);
+
+        let mut config = Config::default();
+        config.apply_args(&Args {
+            verify_cache: true,
+            ..Args::default()
+        });
+        let report = build_fixture(&dir, &config);
+        assert_eq!(report.warnings, ["The cache entry of \"a\" is broken"]);
+        assert_eq!(report.images_converted, 1);
+        assert_eq!(fs::read(dir.join("public/img/a-thumb.jpeg")).unwrap(), thumbnail);
+    }
+
+    #[test]
+    fn only_builds_one_month() {
+        let dir = TempDir::new();
+        dir.write("2023/04/01.lisp", br#"((txt "April"))"#);
+        dir.write("2023/05/05.lisp", br#"((txt "May"))"#);
+        dir.write("2022/05/05.lisp", br#"((txt "Last year"))"#);
+        let mut config = Config::default();
+        config.apply_args(&Args {
+            only: Some((2023, 5)),
+            ..Args::default()
+        });
+        let report = build_fixture(&dir, &config);
+
+        assert_eq!(report.pages, 1);
+        assert!(dir.join("public/2023/05.html").exists());
+        assert!(!dir.join("public/2023/04.html").exists());
+        assert!(!dir.join("public/2022").exists());
+        assert!(!dir.join("public/index.html").exists());
+    }
+
+    #[test]
+    fn only_keeps_names_of_colliding_images() {
+        let dir = TempDir::new();
+        let mut other = TINY_WEBP.to_vec();
+        other.push(0);
+        dir.write("img/04/photo.webp", TINY_WEBP);
+        dir.write("img/05/photo.webp", &other);
+        dir.write("2023/04/01.lisp", br#"((img "" ("04/photo.webp")))"#);
+        dir.write("2023/05/05.lisp", br#"((img "" ("05/photo.webp")))"#);
+        build_fixture(&dir, &Config::default());
+        let may = fs::read_to_string(dir.join("public/2023/05.html")).unwrap();
+
+        let mut config = Config::default();
+        config.apply_args(&Args {
+            only: Some((2023, 5)),
+            ..Args::default()
+        });
+        build_fixture(&dir, &config);
+        assert_eq!(fs::read_to_string(dir.join("public/2023/05.html")).unwrap(), may);
+        let april = fs::read_to_string(dir.join("public/2023/04.html")).unwrap();
+        let (_, src) = april.split_once(r#"<a href="/img/"#).unwrap();
+        let (src, _) = src.split_once('"').unwrap();
+        assert_eq!(fs::read(dir.join(&format!("public/img/{}", src))).unwrap(), TINY_WEBP);
+    }
+
+    #[test]
+    fn substitute_missing_image() {
+        let dir = TempDir::new();
+        dir.write("img/missing.webp", TINY_WEBP);
+        dir.write("2023/05/05.lisp", br#"((img "" ("nowhere.webp") ("gone.webp")))"#);
+        let config = config_with(&[("missing_image", "missing.webp")]);
+        let report = build_fixture(&dir, &config);
+        assert_eq!(report.warnings.len(), 2);
+        assert_eq!(report.images_converted, 1);
+        let may = fs::read_to_string(dir.join("public/2023/05.html")).unwrap();
+        let cell = concat!(
+            r#"<td><a href="/img/missing.webp">"#,
+            r#"<img src="/img/missing-thumb.jpeg" width="96" height="96"></a></td>"#
+        );
+        assert_eq!(may.matches(cell).count(), 2);
+        assert!(!may.contains("missing-image"));
+        assert!(dir.join("public/img/missing.webp").exists());
+
+        dir.write("2023/05/05.lisp", br#"((img "" ("nowhere.webp")))"#);
+        let config = config_with(&[("missing_image", "nothing.webp")]);
+        let report = build_fixture(&dir, &config);
+        assert_eq!(report.warnings[0], "The missing image \"nothing.webp\" does not exist either");
+        let may = fs::read_to_string(dir.join("public/2023/05.html")).unwrap();
+        assert!(may.contains(r#"<span class="missing-image">nowhere.webp</span>"#));
+    }
+
+    #[test]
+    fn missing_image() {
+        let dir = TempDir::new();
+        dir.write("2023/05/05.lisp", br#"((img "" ("nowhere.webp")))"#);
+        let report = build_fixture(&dir, &Config::default());
+        assert_eq!(report.warnings, ["Image \"nowhere.webp\" does not exist"]);
+        let may = fs::read_to_string(dir.join("public/2023/05.html")).unwrap();
+        assert!(may.contains(r#"<td><span class="missing-image">nowhere.webp</span></td>"#));
+
+        let config = config_with(&[("strict", "true")]);
+        assert!(matches!(
+            build(&config, dir.path(), &dir.join("cache")),
+            Err(Error::MissingImage(name)) if name == "nowhere.webp"
+        ));
+    }
+
+    #[test]
+    fn robots_txt() {
+        let dir = TempDir::new();
+        dir.write("source/sitemap.xml", b"<urlset/>");
+        let config = config_with(&[
+            ("base_url", "https://example.com"),
+            ("robots_txt", "true"),
+            ("disallow", "/drafts, /img"),
+        ]);
+        build_fixture(&dir, &config);
+
+        let robots = fs::read_to_string(dir.join("public/robots.txt")).unwrap();
+        assert_eq!(
+            robots,
+            concat!(
+                "User-agent: *\n",
+                "Disallow: /drafts\n",
+                "Disallow: /img\n",
+                "\n",
+                "Sitemap: https://example.com/sitemap.xml\n"
+            )
+        );
+
+        let dir = TempDir::new();
+        dir.write("source/sitemap.xml", b"<urlset/>");
+        let config = config_with(&[("robots_txt", "true")]);
+        build_fixture(&dir, &config);
+        let robots = fs::read_to_string(dir.join("public/robots.txt")).unwrap();
+        assert_eq!(robots, "User-agent: *\nDisallow:\n");
+
+        let dir = TempDir::new();
+        build_fixture(&dir, &Config::default());
+        assert!(!dir.join("public/robots.txt").exists());
+    }
+
+    #[test]
+    fn custom_image_name_templates() {
+        let dir = TempDir::new();
+        dir.write("img/a.webp", TINY_WEBP);
+        dir.write("2023/05/05.lisp", br#"((img "" ("a.webp")))"#);
+        let config = config_with(&[
+            ("thumbnail_name", "{name}_thumb.{ext}"),
+            ("image_name", "full_{name}.{ext}"),
+        ]);
+        build_fixture(&dir, &config);
+
+        assert!(dir.join("cache/img/a_thumb.jpeg").exists());
+        assert!(dir.join("public/img/a_thumb.jpeg").exists());
+        assert!(dir.join("public/img/full_a.webp").exists());
+        let may = fs::read_to_string(dir.join("public/2023/05.html")).unwrap();
+        assert!(may.contains(r#"<a href="/img/full_a.webp"><img src="/img/a_thumb.jpeg""#));
+        for settings in [
+            [("thumbnail_name", "thumb.jpeg"), ("image_name", "{name}.{ext}")],
+            [("thumbnail_name", "{name}"), ("image_name", "full_{name}.{ext}")],
+            [("thumbnail_name", "{name}.{ext}"), ("image_name", "{name}.{ext}")],
+        ] {
+            let map = settings.map(|(key, value)| (key.to_string(), value.to_string()));
+            assert!(Config::from_map(HashMap::from(map)).is_err(), "{:?}", settings);
+        }
+    }
+
+    #[test]
+    fn decorative_alt() {
+        let dir = TempDir::new();
+        dir.write("img/a.webp", TINY_WEBP);
+        dir.write("img/b.webp", TINY_WEBP);
+        dir.write("2023/05/05.lisp", br#"((img "" ("a.webp") ("b.webp" "B")))"#);
+        let render = |decorative: &str| {
+            let config = config_with(&[("decorative_alt", decorative)]);
+            build_fixture(&dir, &config);
+            fs::read_to_string(dir.join("public/2023/05.html")).unwrap()
+        };
+
+        let html = render("true");
+        assert!(html.contains(r#"<img src="/img/a-thumb.jpeg" width="96" height="96" alt="">"#));
+        assert!(html.contains(r#"<img src="/img/b-thumb.jpeg" width="96" height="96" alt="B">"#));
+        let html = render("false");
+        assert!(html.contains(r#"<img src="/img/a-thumb.jpeg" width="96" height="96">"#));
+        assert!(html.contains(r#"<img src="/img/b-thumb.jpeg" width="96" height="96" alt="B">"#));
+    }
+
+    #[test]
+    fn photo_page_collects_month_images() {
+        let dir = TempDir::new();
+        dir.write("img/a.webp", TINY_WEBP);
+        dir.write("img/b.webp", TINY_WEBP);
+        dir.write("2023/05/05.lisp", br#"((txt "x") (img "" ("a.webp" "A")))"#);
+        dir.write("2023/05/20.lisp", br#"((li (img "" ("b.webp"))))"#);
+        dir.write("2023/05/21.lisp", br#"((img "Gallery" ("b.webp")))"#);
+        dir.write("2023/06/01.lisp", br#"((txt "no photos"))"#);
+        dir.write("2023/07/01.lisp", br#"((img "" ("gone.webp")))"#);
+        let config = config_with(&[("photo_pages", "true")]);
+        build_fixture(&dir, &config);
+
+        let photos = fs::read_to_string(dir.join("public/2023/05/photos.html")).unwrap();
+        assert!(photos.contains(concat!(
+            r#"<li><a href="/img/a.webp"><img src="/img/a-thumb.jpeg" width="96" height="96" "#,
+            r#"alt="A"></a>A <a href="/2023/05#05">2023/05/05</a></li>"#,
+        )));
+        assert!(photos.contains(concat!(
+            r#"<li><a href="/img/b.webp"><img src="/img/b-thumb.jpeg" width="96" height="96"></a>"#,
+            r#"<a href="/2023/05#20">2023/05/20</a></li>"#,
+        )));
+        assert!(photos.contains(r#"height="96" alt="Gallery"></a><a href="/2023/05#21">"#));
+        assert!(!dir.join("public/2023/06/photos.html").exists());
+        assert!(!dir.join("public/2023/07/photos.html").exists());
+    }
+
+    #[test]
+    fn print_page_shows_full_images() {
+        let dir = TempDir::new();
+        dir.write("img/a.webp", TINY_WEBP);
+        dir.write("2023/05/05.lisp", br#"((txt "x") (img "" ("a.webp" "A")))"#);
+        let mut config = Config::default();
+        config.apply_args(&Args {
+            print: true,
+            ..Args::default()
+        });
+        build_fixture(&dir, &config);
+
+        let print = fs::read_to_string(dir.join("public/2023/05/print.html")).unwrap();
+        assert!(print.contains(r#"<td><img src="/img/a.webp" alt="A"></td>"#));
+        assert!(!print.contains("a-thumb.jpeg"));
+    }
+
+    #[test]
+    fn escape_entry_text() {
+        let dir = TempDir::new();
+        dir.write("img/a.webp", TINY_WEBP);
+        dir.write(
+            "2023/05/05.lisp",
+            br#"((h "<h>") (txt "a < b & c > d" (b "<b>") (code "x<y"))
+                (img "t&t" ("a.webp" "1<2")))"#,
+        );
+        build_fixture(&dir, &Config::default());
+
+        let html = fs::read_to_string(dir.join("public/2023/05.html")).unwrap();
+        assert!(html.contains("<h3>&lt;h&gt;</h3>"));
+        assert!(html.contains("<p>a &lt; b &amp; c &gt; d<b>&lt;b&gt;</b><code>x&lt;y</code></p>"));
+        assert!(html.contains("t&amp;t"));
+        assert!(html.contains("<td>1&lt;2</td>"));
+    }
 }
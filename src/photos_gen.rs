@@ -0,0 +1,100 @@
+use std::io::{self, Write};
+
+use crate::{
+    config::Config,
+    date::Date,
+    html::HtmlWriter,
+    image::ImagePath,
+    layout,
+    post_gen::{self, OutputDocument},
+    site::SiteInfo,
+};
+
+struct PhotosGenerator<'a, W: Write> {
+    writer: HtmlWriter<'a, W>,
+    config: &'a Config,
+    site: &'a SiteInfo,
+}
+
+impl<'a, W: Write> PhotosGenerator<'a, W> {
+    fn new(writer: &'a mut W, config: &'a Config, site: &'a SiteInfo) -> Self {
+        Self {
+            writer: HtmlWriter::new(writer).validating(config.validate_html()),
+            config,
+            site,
+        }
+    }
+
+    fn generate(
+        &mut self,
+        year: u32,
+        month: u32,
+        days: &[Option<OutputDocument>],
+    ) -> io::Result<()> {
+        let path = format!("/{:04}/{:02}/photos", year, month);
+        let title = format!("Natuka.ge - {:04}/{:02} Photos", year, month);
+        layout::start_page(&mut self.writer, self.config, &path, &title)?;
+        layout::home_link(&mut self.writer, self.config)?;
+        self.writer.start("hr")?;
+        self.writer.start_attr("ul", &[("class", "photos")])?;
+        for (day, doc) in days.iter().enumerate() {
+            let Some(doc) = doc else { continue };
+            let Some(date) = Date::new(year, month, day as u32 + 1) else { continue };
+            for images in doc.galleries() {
+                for image in &images.items {
+                    if !image.data.has_image() {
+                        continue;
+                    }
+                    self.write_photo(&date, &images.title, &image.data, image.caption.as_deref())?;
+                }
+            }
+        }
+        self.writer.end("ul")?;
+        layout::end_page(&mut self.writer, self.config)
+    }
+
+    fn write_photo(
+        &mut self,
+        date: &Date,
+        title: &str,
+        image: &ImagePath,
+        caption: Option<&str>,
+    ) -> io::Result<()> {
+        self.writer.start("li")?;
+        self.writer.start_attr("a", &[("href", &image.actual_path())])?;
+        let (src, width, height) =
+            (image.thumbnail_path(), image.width().to_string(), image.height().to_string());
+        let attrs = [("src", src.as_str()), ("width", &width), ("height", &height)]
+            .into_iter()
+            .chain(post_gen::alt_attr(self.config, title, caption));
+        self.writer.start_attr("img", &attrs.collect::<Vec<_>>())?;
+        self.writer.end("a")?;
+        if let Some(caption) = caption {
+            self.writer.text(caption)?;
+            self.writer.raw(" ")?;
+        }
+        let href = self.config.path(&self.site.entry_path(
+            self.config,
+            date.year(),
+            date.month(),
+            date.day(),
+        ));
+        self.writer.start_attr("a", &[("href", &href)])?;
+        self.writer.text(&date.format_slash_padded())?;
+        self.writer.end("a")?;
+        self.writer.end("li")
+    }
+}
+
+/// Collects every image of a month into a single gallery, each linking back to its day.
+pub fn generate_photos<W: Write>(
+    writer: &mut W,
+    config: &Config,
+    site: &SiteInfo,
+    year: u32,
+    month: u32,
+    days: &[Option<OutputDocument>],
+) -> io::Result<()> {
+    let mut gen = PhotosGenerator::new(writer, config, site);
+    gen.generate(year, month, days)
+}
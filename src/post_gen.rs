@@ -1,22 +1,52 @@
 use std::io::{self, Write};
 
+use serde_json::{json, Value};
+
+use crate::config::{CaptionPosition, Config, GalleryLayout};
 use crate::date::Date;
 use crate::diary_content::{Document, ImageItem, Images, Item, Text, TextItem};
-use crate::html::HtmlWriter;
+use crate::html::{sanitize_token, HtmlWriter};
 use crate::image::ImagePath;
+use crate::layout;
+use crate::site::SiteInfo;
+use crate::util::{bare_urls, url_host};
 
 pub type OutputDocument = Document<ImagePath>;
 
 pub type OutputItem = Item<ImagePath>;
 
+const HEADLINE_MAX_CHARS: usize = 110;
+
+/// The class of the elements whose text `HIGHLIGHT_SCRIPT` searches.
+const SEARCHABLE_CLASS: &str = "searchable";
+
+/// Wraps the occurrences of the `q` query parameter in `<mark>`, once
+/// joined by `SEARCHABLE_CLASS`.
+const HIGHLIGHT_SCRIPT: [&str; 2] = [
+    r#"(()=>{const q=new URLSearchParams(location.search).get("q");if(!q)return;const l=q.toLowerCase();for(const c of document.querySelectorAll("."#,
+    r#"")){const w=document.createTreeWalker(c,NodeFilter.SHOW_TEXT);const ns=[];while(w.nextNode())ns.push(w.currentNode);for(const n of ns){let i=n.data.toLowerCase().indexOf(l);let t=n;while(i>=0){const m=t.splitText(i);t=m.splitText(q.length);const k=document.createElement("mark");m.replaceWith(k);k.append(m);i=t.data.toLowerCase().indexOf(l)}}}})();"#,
+];
+const CJK_CHARS_PER_MINUTE: usize = 500;
+const WORDS_PER_MINUTE: usize = 200;
+
 struct PostGenerator<'a, W: Write> {
     writer: HtmlWriter<'a, W>,
+    config: &'a Config,
+    site: &'a SiteInfo,
+    /// The numbers of the enclosing headings of the current day.
+    heading_numbers: Vec<u32>,
+    /// Whether navigation is left out and images are shown at full size.
+    print: bool,
 }
 
 impl<'a, W: Write> PostGenerator<'a, W> {
-    fn new(writer: &'a mut W) -> Self {
+    fn new(writer: &'a mut W, config: &'a Config, site: &'a SiteInfo) -> Self {
         Self {
-            writer: HtmlWriter::new(writer),
+            writer: HtmlWriter::new(writer).validating(config.validate_html()),
+            config,
+            site,
+            heading_numbers: Vec::new(),
+            print: false,
         }
     }
 
@@ -27,80 +57,463 @@ impl<'a, W: Write> PostGenerator<'a, W> {
         docs: Vec<Option<OutputDocument>>,
     ) -> io::Result<()> {
         let title = format!("Natuka.ge - {:4}/{:02}", year, month);
-        self.writer.doctype()?;
-        self.writer.start_attr("html", &[("lang", "ja")])?;
-        self.writer.start("head")?;
-        self.writer.start_attr("meta", &[("charset", "utf-8")])?;
-        self.writer.start("title")?;
-        write!(self.writer, "{}", title)?;
-        self.writer.end("title")?;
-        self.writer.end("head")?;
-        self.writer.start("body")?;
-        self.writer.start("h1")?;
-        write!(self.writer, "{}", title)?;
-        self.writer.end("h1")?;
-        self.writer.start_attr("a", &[("href", "/")])?;
-        write!(self.writer, "ホーム")?;
-        self.writer.end("a")?;
-        write!(self.writer, "へ")?;
+        let path = format!("/{:04}/{:02}", year, month);
+        layout::start_page(&mut self.writer, self.config, &path, &title)?;
+        layout::home_link(&mut self.writer, self.config)?;
         self.writer.start("hr")?;
-        self.writer.start("dl")?;
 
-        for (day, doc) in docs
+        let days = docs
             .into_iter()
             .enumerate()
             .filter_map(|(day, doc)| doc.map(|doc| (day, doc)))
             .rev()
-        {
-            let date = Date::new(year, month, (day + 1) as u32)
-                .unwrap_or_else(|| panic!("Wrong date: ({}, {}, {})", year, month, day + 1));
-            self.generate_daily(&date, &doc)?;
+            .map(|(day, doc)| {
+                let date = Date::new(year, month, (day + 1) as u32)
+                    .unwrap_or_else(|| panic!("Wrong date: ({}, {}, {})", year, month, day + 1));
+                (date, doc)
+            })
+            .collect::<Vec<_>>();
+        let groups = if self.config.group_by_week() {
+            days.chunk_by(|(a, _), (b, _)| a.iso_week() == b.iso_week())
+                .collect()
+        } else {
+            vec![days.as_slice()]
+        };
+        for group in groups {
+            if self.config.group_by_week() {
+                let (_, week) = group[0].0.iso_week();
+                self.writer.start_attr("h2", &[("class", "week")])?;
+                self.writer
+                    .text(&self.config.locale().week_heading.replace("{}", &week.to_string()))?;
+                self.writer.end("h2")?;
+            }
+            self.writer.start("dl")?;
+            for (date, doc) in group {
+                self.generate_daily(date, doc)?;
+            }
+            self.writer.end("dl")?;
         }
 
+        self.write_json_ld(&days)?;
+        self.write_highlight_script()?;
+        layout::end_page(&mut self.writer, self.config)
+    }
+
+    /// Writes the days of a month oldest first, for printing.
+    fn generate_print(
+        &mut self,
+        year: u32,
+        month: u32,
+        docs: &[Option<OutputDocument>],
+    ) -> io::Result<()> {
+        self.print = true;
+        let title = format!("Natuka.ge - {:04}/{:02}", year, month);
+        let path = format!("/{:04}/{:02}/print", year, month);
+        layout::start_page(&mut self.writer, self.config, &path, &title)?;
+        self.writer.start("dl")?;
+        for (day, doc) in docs.iter().enumerate() {
+            let Some(doc) = doc else { continue };
+            let Some(date) = Date::new(year, month, day as u32 + 1) else { continue };
+            self.generate_daily(&date, doc)?;
+        }
         self.writer.end("dl")?;
-        self.writer.end("body")?;
-        self.writer.end("html")?;
-        Ok(())
+        layout::end_page(&mut self.writer, self.config)
+    }
+
+    fn generate_day_page(
+        &mut self,
+        date: &Date,
+        doc: &OutputDocument,
+        prev: Option<&Date>,
+        next: Option<&Date>,
+    ) -> io::Result<()> {
+        let title = format!("Natuka.ge - {}", date.format_slash_padded());
+        let path = format!("/{}", date.format_slash_padded());
+        layout::start_page(&mut self.writer, self.config, &path, &title)?;
+        layout::home_link(&mut self.writer, self.config)?;
+        self.write_adjacent_days(prev, next)?;
+        self.writer.start("hr")?;
+        self.writer.start("dl")?;
+        self.generate_daily(date, doc)?;
+        self.writer.end("dl")?;
+        self.write_highlight_script()?;
+        layout::end_page(&mut self.writer, self.config)
+    }
+
+    fn write_adjacent_days(&mut self, prev: Option<&Date>, next: Option<&Date>) -> io::Result<()> {
+        if prev.is_none() && next.is_none() {
+            return Ok(());
+        }
+        self.writer.start("nav")?;
+        if let Some(prev) = prev {
+            self.write_day_link(prev, "prev")?;
+        }
+        if prev.is_some() && next.is_some() {
+            self.writer.raw(" | ")?;
+        }
+        if let Some(next) = next {
+            self.write_day_link(next, "next")?;
+        }
+        self.writer.end("nav")
+    }
+
+    fn write_day_link(&mut self, date: &Date, rel: &str) -> io::Result<()> {
+        let href = self.config.path(&self.site.entry_path(
+            self.config,
+            date.year(),
+            date.month(),
+            date.day(),
+        ));
+        self.writer.start_attr("a", &[("rel", rel), ("href", &href)])?;
+        self.writer.text(&date.format_slash_padded())?;
+        self.writer.end("a")
+    }
+
+    fn write_highlight_script(&mut self) -> io::Result<()> {
+        if !self.config.highlight_search() {
+            return Ok(());
+        }
+        self.writer.start("script")?;
+        let [start, end] = HIGHLIGHT_SCRIPT;
+        self.writer.raw(format_args!("{}{}{}", start, SEARCHABLE_CLASS, end))?;
+        self.writer.end("script")
+    }
+
+    /// Describes the entries as schema.org `BlogPosting`s for search engines.
+    fn write_json_ld(&mut self, days: &[(Date, OutputDocument)]) -> io::Result<()> {
+        let posts = days
+            .iter()
+            .map(|(date, doc)| {
+                let date_str = date.format_iso();
+                let headline = doc
+                    .title()
+                    .map(str::to_string)
+                    .or_else(|| doc.summary())
+                    .filter(|s| !s.is_empty())
+                    .unwrap_or_else(|| date_str.clone())
+                    .chars()
+                    .take(HEADLINE_MAX_CHARS)
+                    .collect::<String>();
+                let mut post = json!({
+                    "@type": "BlogPosting",
+                    "headline": headline,
+                    "datePublished": date_str,
+                    "url": self.config.url(&self.site.entry_path(
+                        self.config,
+                        date.year(),
+                        date.month(),
+                        date.day()
+                    )),
+                });
+                if let Some((lat, lon, label)) = doc.geo() {
+                    post["contentLocation"] = json!({
+                        "@type": "Place",
+                        "name": label,
+                        "geo": {
+                            "@type": "GeoCoordinates",
+                            "latitude": lat,
+                            "longitude": lon,
+                        },
+                    });
+                }
+                post
+            })
+            .collect::<Vec<Value>>();
+        let json_ld = json!({
+            "@context": "https://schema.org",
+            "@graph": posts,
+        });
+        self.writer
+            .start_attr("script", &[("type", "application/ld+json")])?;
+        // `<` only appears inside JSON strings, where `\u003c` is equivalent
+        // and can't close the script element.
+        self.writer.raw(json_ld.to_string().replace('<', "\\u003c"))?;
+        self.writer.end("script")
     }
 
     fn generate_daily(&mut self, date: &Date, doc: &OutputDocument) -> io::Result<()> {
         self.writer.start("dt")?;
         self.write_date(date)?;
+        if let Some(title) = doc.title() {
+            self.writer.start_attr("h3", &[("class", "title")])?;
+            self.writer.text(title)?;
+            self.writer.end("h3")?;
+        }
         self.writer.end("dt")?;
 
-        self.writer.start("dd")?;
-        for item in doc.contents() {
-            match item {
-                Item::Text(txt) => self.write_paragraph(txt),
-                Item::List(li) => self.write_list(li),
-                Item::Header(txt) => self.write_header(txt),
-                Item::Images(images) => self.write_images(images),
-            }?;
+        let mut classes = doc
+            .classes()
+            .into_iter()
+            .map(sanitize_token)
+            .filter(|class| !class.is_empty())
+            .collect::<Vec<_>>();
+        if self.config.highlight_search() {
+            classes.push(SEARCHABLE_CLASS.to_string());
+        }
+        let classes = classes.join(" ");
+        let mut attrs = Vec::new();
+        if !classes.is_empty() {
+            attrs.push(("class", classes.as_str()));
+        }
+        if let Some(lang) = doc.lang() {
+            attrs.push(("lang", lang));
+        }
+        self.writer.start_attr("dd", &attrs)?;
+        self.heading_numbers.clear();
+        self.write_reading_time(doc)?;
+        let contents = doc.contents();
+        let split = if self.print {
+            contents.len()
+        } else {
+            self.collapse_point(doc).unwrap_or(contents.len())
+        };
+        for item in &contents[..split] {
+            self.write_item(item)?;
+        }
+        if split < contents.len() {
+            self.writer.start("details")?;
+            self.writer.start("summary")?;
+            self.writer.text(&self.config.locale().read_more)?;
+            self.writer.end("summary")?;
+            for item in &contents[split..] {
+                self.write_item(item)?;
+            }
+            self.writer.end("details")?;
+        }
+        if !self.print {
+            self.write_series(date)?;
+            self.write_related(date)?;
         }
         self.writer.end("dd")?;
 
         Ok(())
     }
 
+    fn write_item(&mut self, item: &OutputItem) -> io::Result<()> {
+        match item {
+            Item::Text(txt) => self.write_paragraph(txt),
+            Item::List(li) => self.write_list("ul", li),
+            Item::OrderedList(li) => self.write_list("ol", li),
+            Item::ListEntry(li) => {
+                for item in li {
+                    self.write_item(item)?;
+                }
+                Ok(())
+            }
+            Item::Header(level, txt) => self.write_header(*level, txt),
+            Item::Images(images) => self.write_images(images),
+            Item::Anchor(id) => self.write_anchor(id),
+            Item::Details { summary, body } => self.write_details(summary, body),
+            Item::At { time, body } => self.write_at(*time, body),
+            Item::Quote { body, cite } => self.write_quote(body, cite.as_deref()),
+            Item::Geo { lat, lon, label } => self.write_geo(*lat, *lon, label),
+            Item::Tags(_)
+            | Item::Title(_)
+            | Item::Class(_)
+            | Item::Lang(_)
+            | Item::Series { .. } => Ok(()),
+        }
+    }
+
+    /// The index of the first item hidden in `<details>`, i.e. the one after
+    /// the first paragraph, if the day is long enough to be collapsed.
+    fn collapse_point(&self, doc: &OutputDocument) -> Option<usize> {
+        let limit = self.config.collapse_length();
+        let length = doc.plain_blocks().iter().map(|b| b.chars().count()).sum::<usize>();
+        if limit == 0 || length <= limit {
+            return None;
+        }
+        doc.contents()
+            .iter()
+            .position(|item| matches!(item, Item::Text(_)))
+            .map(|i| i + 1)
+    }
+
+    fn write_related(&mut self, date: &Date) -> io::Result<()> {
+        let related = self.site.related(date);
+        if related.is_empty() {
+            return Ok(());
+        }
+        self.writer.start_attr("aside", &[("class", "related")])?;
+        self.writer.start("h4")?;
+        self.writer.text(&self.config.locale().related_label)?;
+        self.writer.end("h4")?;
+        self.writer.start("ul")?;
+        for other in related {
+            let href = self.config.path(&self.site.entry_path(
+                self.config,
+                other.year(),
+                other.month(),
+                other.day(),
+            ));
+            self.writer.start("li")?;
+            self.writer.start_attr("a", &[("href", &href)])?;
+            self.writer.text(&other.format_slash_padded())?;
+            self.writer.end("a")?;
+            self.writer.end("li")?;
+        }
+        self.writer.end("ul")?;
+        self.writer.end("aside")
+    }
+
+    /// Lists the parts of the series of the day, linking to all but itself.
+    fn write_series(&mut self, date: &Date) -> io::Result<()> {
+        let Some((name, parts)) = self.site.series(date) else {
+            return Ok(());
+        };
+        let part = parts.iter().find(|(_, d)| d == date).map_or(0, |(part, _)| *part);
+        let label = self
+            .config
+            .locale()
+            .series_label
+            .replace("{series}", name)
+            .replace("{part}", &part.to_string());
+        self.writer.start_attr("nav", &[("class", "series")])?;
+        self.writer.start("h4")?;
+        self.writer.text(&label)?;
+        self.writer.end("h4")?;
+        self.writer.start("ol")?;
+        for (_, other) in parts {
+            if other == date {
+                self.writer.start_attr("li", &[("aria-current", "page")])?;
+                self.writer.text(&other.format_slash_padded())?;
+                self.writer.end("li")?;
+                continue;
+            }
+            let href = self.config.path(&self.site.entry_path(
+                self.config,
+                other.year(),
+                other.month(),
+                other.day(),
+            ));
+            self.writer.start("li")?;
+            self.writer.start_attr("a", &[("href", &href)])?;
+            self.writer.text(&other.format_slash_padded())?;
+            self.writer.end("a")?;
+            self.writer.end("li")?;
+        }
+        self.writer.end("ol")?;
+        self.writer.end("nav")
+    }
+
     fn write_date(&mut self, date: &Date) -> io::Result<()> {
-        let id = format!("{:02}", date.day());
+        let day_id = format!("{:02}", date.day());
+        let id = self.site.anchor(date);
+        if id != day_id {
+            // Keeps links by the day number, as from feeds, working.
+            self.write_anchor(&day_id)?;
+        }
+        let href = format!("#{}", id);
         self.writer.start_attr("h2", &[("id", &id)])?;
+        self.writer.start_attr("a", &[("href", &href)])?;
+        let heading = self
+            .config
+            .locale()
+            .date_heading
+            .replace("{date}", &date.format_slash_padded())
+            .replace("{weekday}", date.weekday_ja());
+        self.writer.text(&heading)?;
+        self.writer.end("a")?;
+        if self.print {
+            return self.writer.end("h2");
+        }
+        self.writer.raw(" ")?;
         self.writer
-            .start_attr("a", &[("href", &format!("#{}", id))])?;
-        write!(
-            self.writer,
-            "{} ({})",
-            format!("{}/{:02}/{:02}", date.year(), date.month(), date.day()),
-            date.weekday_ja()
-        )?;
+            .start_attr("a", &[("class", "permalink"), ("href", &href)])?;
+        self.writer.raw("#")?;
         self.writer.end("a")?;
         self.writer.end("h2")
     }
 
-    fn write_header(&mut self, txt: &str) -> io::Result<()> {
-        self.writer.start("h3")?;
-        write!(self.writer, "{}", txt)?;
-        self.writer.end("h3")
+    fn write_reading_time(&mut self, doc: &OutputDocument) -> io::Result<()> {
+        let minutes = reading_minutes(&doc.plain_blocks().join("\n"));
+        let label = self
+            .config
+            .locale()
+            .reading_time
+            .replace("{}", &minutes.to_string());
+        self.writer.start_attr("small", &[("class", "reading-time")])?;
+        self.writer.text(&label)?;
+        self.writer.end("small")
+    }
+
+    fn write_anchor(&mut self, id: &str) -> io::Result<()> {
+        self.writer
+            .start_attr("span", &[("id", &sanitize_token(id))])?;
+        self.writer.end("span")
+    }
+
+    fn write_header(&mut self, level: u32, txt: &str) -> io::Result<()> {
+        let tag = format!("h{}", level);
+        self.writer.start(&tag)?;
+        if self.config.number_headings() && level == 2 {
+            // Left unnumbered, as a section of its own above the numbered ones.
+            self.heading_numbers.clear();
+        } else if self.config.number_headings() {
+            // Level 3 headings are the top level of a day.
+            let depth = level as usize - 2;
+            self.heading_numbers.resize(depth, 0);
+            self.heading_numbers[depth - 1] += 1;
+            let number = self
+                .heading_numbers
+                .iter()
+                .map(u32::to_string)
+                .collect::<Vec<_>>()
+                .join(".");
+            self.writer.text(&format!("{} ", number))?;
+        }
+        self.writer.text(txt)?;
+        self.writer.end(&tag)
+    }
+
+    fn write_details(&mut self, summary: &str, body: &[OutputItem]) -> io::Result<()> {
+        self.writer.start("details")?;
+        self.writer.start("summary")?;
+        self.writer.text(summary)?;
+        self.writer.end("summary")?;
+        for item in body {
+            self.write_item(item)?;
+        }
+        self.writer.end("details")
+    }
+
+    fn write_at(&mut self, (hours, minutes): (u32, u32), body: &[OutputItem]) -> io::Result<()> {
+        self.writer.start_attr("section", &[("class", "at")])?;
+        self.writer.start("h4")?;
+        self.writer.start("time")?;
+        self.writer.text(&format!("{:02}:{:02}", hours, minutes))?;
+        self.writer.end("time")?;
+        self.writer.end("h4")?;
+        for item in body {
+            self.write_item(item)?;
+        }
+        self.writer.end("section")
+    }
+
+    fn write_quote(&mut self, body: &[OutputItem], cite: Option<&str>) -> io::Result<()> {
+        self.writer.start("blockquote")?;
+        for item in body {
+            self.write_item(item)?;
+        }
+        if let Some(cite) = cite {
+            self.writer.start("cite")?;
+            self.writer.text(cite)?;
+            self.writer.end("cite")?;
+        }
+        self.writer.end("blockquote")
+    }
+
+    fn write_geo(&mut self, lat: f64, lon: f64, label: &str) -> io::Result<()> {
+        let href = format!(
+            "https://www.openstreetmap.org/?mlat={lat}&mlon={lon}#map=15/{lat}/{lon}",
+            lat = lat,
+            lon = lon
+        );
+        self.writer.start_attr("p", &[("class", "geo")])?;
+        self.writer.start_attr("a", &[("href", &href)])?;
+        self.writer.text(label)?;
+        self.writer.end("a")?;
+        self.writer.end("p")
     }
 
     fn write_paragraph(&mut self, txt: &Text) -> io::Result<()> {
@@ -109,8 +522,9 @@ impl<'a, W: Write> PostGenerator<'a, W> {
         self.writer.end("p")
     }
 
-    fn write_list(&mut self, items: &Vec<OutputItem>) -> io::Result<()> {
-        self.writer.start("ul")?;
+    /// Writes a list as a `ul` or an `ol`, given by `tag`.
+    fn write_list(&mut self, tag: &str, items: &Vec<OutputItem>) -> io::Result<()> {
+        self.writer.start(tag)?;
         for item in items {
             match item {
                 Item::Text(txt) => {
@@ -118,8 +532,26 @@ impl<'a, W: Write> PostGenerator<'a, W> {
                     self.write_text(&txt)?;
                     self.writer.end("li")
                 }
-                Item::List(li) => self.write_list(&li),
-                Item::Header(_) => unreachable!(),
+                Item::List(li) => self.write_list("ul", li),
+                Item::OrderedList(li) => self.write_list("ol", li),
+                Item::ListEntry(li) => {
+                    self.writer.start("li")?;
+                    for item in li {
+                        self.write_item(item)?;
+                    }
+                    self.writer.end("li")
+                }
+                Item::Header(..)
+                | Item::Anchor(_)
+                | Item::Tags(_)
+                | Item::Title(_)
+                | Item::Class(_)
+                | Item::Lang(_)
+                | Item::Details { .. }
+                | Item::At { .. }
+                | Item::Quote { .. }
+                | Item::Geo { .. }
+                | Item::Series { .. } => unreachable!(),
                 Item::Images(images) => {
                     self.writer.start("li")?;
                     self.write_images(images)?;
@@ -127,7 +559,7 @@ impl<'a, W: Write> PostGenerator<'a, W> {
                 }
             }?;
         }
-        self.writer.end("ul")
+        self.writer.end(tag)
     }
 
     fn write_text(&mut self, txt: &Text) -> io::Result<()> {
@@ -135,78 +567,973 @@ impl<'a, W: Write> PostGenerator<'a, W> {
             match e {
                 TextItem::Bold(txt) => {
                     self.writer.start("b")?;
-                    write!(self.writer, "{}", txt)?;
+                    self.writer.text(txt)?;
                     self.writer.end("b")?;
                 }
+                TextItem::Italic(txt) => {
+                    self.writer.start("em")?;
+                    self.writer.text(txt)?;
+                    self.writer.end("em")?;
+                }
+                TextItem::Strike(txt) => {
+                    self.writer.start("del")?;
+                    self.writer.text(txt)?;
+                    self.writer.end("del")?;
+                }
+                TextItem::Mark(txt) => {
+                    self.writer.start("mark")?;
+                    self.writer.text(txt)?;
+                    self.writer.end("mark")?;
+                }
+                TextItem::RawString(txt) if self.config.autolink() => {
+                    let mut rest = 0;
+                    for range in bare_urls(txt) {
+                        self.writer.text(&txt[rest..range.start])?;
+                        let url = &txt[range.start..range.end];
+                        self.write_web_link(url, url)?;
+                        rest = range.end;
+                    }
+                    self.writer.text(&txt[rest..])?;
+                }
                 TextItem::RawString(txt) => {
-                    write!(self.writer, "{}", txt)?;
+                    self.writer.text(txt)?;
                 }
                 TextItem::WebLink(link) => {
-                    self.writer.start_attr("a", &[("href", &link.href)])?;
-                    write!(self.writer, "{}", link.title)?;
-                    self.writer.end("a")?;
+                    self.write_web_link(&link.href, &link.title)?;
                 }
                 TextItem::PostLink((year, month, day)) => {
-                    let href = format!("/{:04}/{:02}#{:02}", year, month, day);
-                    write!(self.writer, "(ref. ")?;
+                    let href = self.config.path(&self.site.entry_path(
+                        self.config,
+                        *year,
+                        *month,
+                        *day,
+                    ));
+                    self.writer.raw("(ref. ")?;
                     self.writer.start_attr("a", &[("href", &href)])?;
-                    write!(self.writer, "{:04}/{:02}/{:02}", year, month, day)?;
+                    self.writer.text(&format!("{:04}/{:02}/{:02}", year, month, day))?;
                     self.writer.end("a")?;
-                    write!(self.writer, ")")?;
+                    self.writer.raw(")")?;
                 }
                 TextItem::Code(txt) => {
                     self.writer.start("code")?;
-                    write!(self.writer, "{}", txt)?;
+                    self.writer.text(txt)?;
                     self.writer.end("code")?;
                 }
+                TextItem::Sup(txt) => {
+                    self.writer.start("sup")?;
+                    self.writer.text(txt)?;
+                    self.writer.end("sup")?;
+                }
+                TextItem::Abbr { text, title } => {
+                    self.writer.start_attr("abbr", &[("title", title)])?;
+                    self.writer.text(text)?;
+                    self.writer.end("abbr")?;
+                }
+                TextItem::Math(tex) => {
+                    self.writer.start_attr("span", &[("class", "math")])?;
+                    self.writer.text(tex)?;
+                    self.writer.end("span")?;
+                }
+                TextItem::Sub(txt) => {
+                    self.writer.start("sub")?;
+                    self.writer.text(txt)?;
+                    self.writer.end("sub")?;
+                }
+                TextItem::Ruby { base, reading } => {
+                    self.writer.start("ruby")?;
+                    self.writer.text(base)?;
+                    self.writer.start("rt")?;
+                    self.writer.text(reading)?;
+                    self.writer.end("rt")?;
+                    self.writer.end("ruby")?;
+                }
+                TextItem::Break => {
+                    self.writer.start("br")?;
+                }
             }
         }
         Ok(())
     }
 
-    fn write_images(&mut self, images: &Images<ImagePath>) -> io::Result<()> {
-        write!(self.writer, "{}", images.title)?;
-        self.writer.start("table")?;
-        self.writer.start("tbody")?;
-        self.writer.start("tr")?;
-        for ImageItem { data, .. } in &images.items {
-            self.writer.start("td")?;
-            self.writer
-                .start_attr("a", &[("href", &data.actual_path())])?;
+    fn write_web_link(&mut self, href: &str, title: &str) -> io::Result<()> {
+        if !self.config.allows_url(href) {
+            // Rendered inert rather than as a link with a dangerous scheme.
+            return self.writer.text(title);
+        }
+        self.writer.start_attr("a", &[("href", href)])?;
+        let favicon = url_host(href).and_then(|host| self.config.favicon_url(host));
+        if let Some(src) = favicon {
             self.writer.start_attr(
                 "img",
                 &[
-                    ("src", &data.thumbnail_path()),
-                    ("width", &data.width().to_string()),
-                    ("height", &data.height().to_string()),
+                    ("class", "favicon"),
+                    ("src", &src),
+                    ("alt", ""),
+                    ("width", "16"),
+                    ("height", "16"),
                 ],
             )?;
-            self.writer.end("a")?;
+        }
+        self.writer.text(title)?;
+        self.writer.end("a")
+    }
+
+    fn write_images(&mut self, images: &Images<ImagePath>) -> io::Result<()> {
+        self.writer.text(&images.title)?;
+        if self.config.gallery_layout() == GalleryLayout::Figure {
+            return self.write_figures(images);
+        }
+        if images.caption.is_some() {
+            self.writer.start("figure")?;
+        }
+        self.writer.start("table")?;
+        self.writer.start("tbody")?;
+        let position = self.config.caption_position();
+        if position == CaptionPosition::Above {
+            self.write_caption_row(images)?;
+        }
+        self.write_image_row(images)?;
+        if position == CaptionPosition::Below {
+            self.write_caption_row(images)?;
+        }
+        self.writer.end("tbody")?;
+        self.writer.end("table")?;
+        if let Some(caption) = &images.caption {
+            self.writer.start("figcaption")?;
+            self.writer.text(caption)?;
+            self.writer.end("figcaption")?;
+            self.writer.end("figure")?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes a gallery as a `figure` per image, in a wrapper which is itself
+    /// a `figure` when the gallery has a caption.
+    fn write_figures(&mut self, images: &Images<ImagePath>) -> io::Result<()> {
+        let wrapper = if images.caption.is_some() { "figure" } else { "div" };
+        self.writer.start_attr(wrapper, &[("class", "gallery")])?;
+        let position = self.config.caption_position();
+        for item in &images.items {
+            self.writer.start("figure")?;
+            let caption = item.caption.as_ref().filter(|_| position != CaptionPosition::None);
+            if let Some(caption) = caption.filter(|_| position == CaptionPosition::Above) {
+                self.write_figcaption(caption)?;
+            }
+            self.write_image(&images.title, item)?;
+            if let Some(caption) = caption.filter(|_| position == CaptionPosition::Below) {
+                self.write_figcaption(caption)?;
+            }
+            self.writer.end("figure")?;
+        }
+        if let Some(caption) = &images.caption {
+            self.write_figcaption(caption)?;
+        }
+        self.writer.end(wrapper)
+    }
+
+    fn write_figcaption(&mut self, caption: &str) -> io::Result<()> {
+        self.writer.start("figcaption")?;
+        self.writer.text(caption)?;
+        self.writer.end("figcaption")
+    }
+
+    fn write_image_row(&mut self, images: &Images<ImagePath>) -> io::Result<()> {
+        self.writer.start("tr")?;
+        for item in &images.items {
+            self.writer.start("td")?;
+            self.write_image(&images.title, item)?;
             self.writer.end("td")?;
         }
-        self.writer.end("tr")?;
+        self.writer.end("tr")
+    }
+
+    /// Writes the thumbnail of an image linking to the image, the image itself
+    /// in print mode, or the name of its source if it is missing.
+    fn write_image(&mut self, title: &str, item: &ImageItem<ImagePath>) -> io::Result<()> {
+        let ImageItem { data, caption } = item;
+        if !data.has_image() {
+            self.writer
+                .start_attr("span", &[("class", "missing-image")])?;
+            self.writer.text(data.name())?;
+            return self.writer.end("span");
+        }
+        let alt_attr = alt_attr(self.config, title, caption.as_deref());
+        if self.print {
+            let src = data.actual_path();
+            let attrs = [("src", src.as_str())].into_iter().chain(alt_attr);
+            return self.writer.start_attr("img", &attrs.collect::<Vec<_>>());
+        }
+        self.writer
+            .start_attr("a", &[("href", &data.actual_path())])?;
+        let (src, width, height) =
+            (data.thumbnail_path(), data.width().to_string(), data.height().to_string());
+        let attrs = [("src", src.as_str()), ("width", &width), ("height", &height)]
+            .into_iter()
+            .chain(alt_attr);
+        self.writer.start_attr("img", &attrs.collect::<Vec<_>>())?;
+        self.writer.end("a")
+    }
+
+    fn write_caption_row(&mut self, images: &Images<ImagePath>) -> io::Result<()> {
         self.writer.start("tr")?;
         for image in &images.items {
             self.writer.start("td")?;
             if let Some(caption) = &image.caption {
-                write!(self.writer, "{}", caption)?;
+                self.writer.text(caption)?;
             }
             self.writer.end("td")?;
         }
-        self.writer.end("tr")?;
-        self.writer.end("tbody")?;
-        self.writer.end("table")?;
-
-        Ok(())
+        self.writer.end("tr")
     }
 }
 
+fn is_cjk(chr: char) -> bool {
+    matches!(chr,
+        '\u{3040}'..='\u{30ff}' // Hiragana and Katakana
+        | '\u{3400}'..='\u{4dbf}' // CJK Extension A
+        | '\u{4e00}'..='\u{9fff}' // CJK Unified Ideographs
+        | '\u{ac00}'..='\u{d7af}' // Hangul
+        | '\u{ff00}'..='\u{ffef}' // Fullwidth forms
+    )
+}
+
+/// Estimates the reading time in minutes, counting CJK characters and
+/// space-separated words at their own rates. Never returns less than a minute.
+fn reading_minutes(text: &str) -> usize {
+    let cjk_chars = text.chars().filter(|&c| is_cjk(c)).count();
+    let words = text
+        .split(|c: char| c.is_whitespace() || is_cjk(c))
+        .filter(|w| !w.is_empty())
+        .count();
+    let seconds = cjk_chars * 60 / CJK_CHARS_PER_MINUTE + words * 60 / WORDS_PER_MINUTE;
+    seconds.div_ceil(60).max(1)
+}
+
+/// The `alt` of an image: its caption, or else the title of its gallery. An
+/// image with neither is left without one unless `decorative_alt` is set.
+pub fn alt_attr<'a>(
+    config: &Config,
+    title: &'a str,
+    caption: Option<&'a str>,
+) -> Option<(&'static str, &'a str)> {
+    let alt = caption.unwrap_or(title);
+    (!alt.is_empty() || config.decorative_alt()).then_some(("alt", alt))
+}
+
 pub fn generate_monthly<W: Write>(
     writer: &mut W,
+    config: &Config,
+    site: &SiteInfo,
     year: u32,
     month: u32,
     docs: Vec<Option<OutputDocument>>,
 ) -> io::Result<()> {
-    let mut gen = PostGenerator::new(writer);
+    let mut gen = PostGenerator::new(writer, config, site);
     gen.generate_monthly(year, month, docs)
 }
+
+/// Writes the printable page of a month, without navigation.
+pub fn generate_print<W: Write>(
+    writer: &mut W,
+    config: &Config,
+    site: &SiteInfo,
+    year: u32,
+    month: u32,
+    docs: &[Option<OutputDocument>],
+) -> io::Result<()> {
+    let mut gen = PostGenerator::new(writer, config, site);
+    gen.generate_print(year, month, docs)
+}
+
+/// Writes the items of a day alone, without the date and the page around them.
+pub fn generate_contents<W: Write>(
+    writer: &mut W,
+    config: &Config,
+    site: &SiteInfo,
+    doc: &OutputDocument,
+) -> io::Result<()> {
+    let mut gen = PostGenerator::new(writer, config, site);
+    for item in doc.contents() {
+        gen.write_item(item)?;
+    }
+    gen.writer.finish()
+}
+
+/// Writes the standalone page of a single day, linking to the days around it.
+pub fn generate_day_page<W: Write>(
+    writer: &mut W,
+    config: &Config,
+    site: &SiteInfo,
+    date: &Date,
+    doc: &OutputDocument,
+    prev: Option<&Date>,
+    next: Option<&Date>,
+) -> io::Result<()> {
+    let mut gen = PostGenerator::new(writer, config, site);
+    gen.generate_day_page(date, doc, prev, next)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        config::Config,
+        date::Date,
+        diary_content::{Document, ImageItem, Images, Item, TextItem, WebLink},
+        image::ImagePath,
+        post_gen::{
+            generate_day_page, generate_monthly, generate_print, reading_minutes, OutputDocument,
+        },
+        site::SiteInfo,
+        test_util::config_with,
+    };
+
+    fn render_day_with(config: &Config, doc: OutputDocument) -> String {
+        let mut days = vec![None; 31];
+        days[4] = Some(doc);
+        let mut buf = Vec::new();
+        generate_monthly(&mut buf, config, &SiteInfo::default(), 2023, 5, days).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    fn render_day(doc: OutputDocument) -> String {
+        render_day_with(&Config::default(), doc)
+    }
+
+    #[test]
+    fn autolink_bare_urls() {
+        let config = config_with(&[("autolink", "true")]);
+        let doc = || {
+            Document::new(vec![
+                Item::Text(vec![TextItem::RawString(
+                    "a & b: https://example.com/?q=1&r=2.".to_string(),
+                )]),
+                Item::Text(vec![TextItem::RawString("no <links>".to_string())]),
+            ])
+        };
+        let html = render_day_with(&config, doc());
+        assert!(html.contains(concat!(
+            r#"<p>a &amp; b: <a href="https://example.com/?q=1&amp;r=2">"#,
+            "https://example.com/?q=1&amp;r=2</a>.</p>"
+        )));
+        assert!(html.contains("<p>no &lt;links&gt;</p>"));
+        let html = render_day(doc());
+        assert!(html.contains("<p>a &amp; b: https://example.com/?q=1&amp;r=2.</p>"));
+    }
+
+    #[test]
+    fn render_sup_and_sub() {
+        let html = render_day(Document::new(vec![Item::Text(vec![
+            TextItem::RawString("x".to_string()),
+            TextItem::Sup("2".to_string()),
+            TextItem::RawString("H".to_string()),
+            TextItem::Sub("2".to_string()),
+            TextItem::RawString("O".to_string()),
+        ])]));
+        assert!(html.contains("<p>x<sup>2</sup>H<sub>2</sub>O</p>"));
+    }
+
+    #[test]
+    fn render_post_link_with_base_path() {
+        let config = config_with(&[("base_path", "/diary")]);
+        let html = render_day_with(
+            &config,
+            Document::new(vec![Item::Text(vec![TextItem::PostLink((2023, 5, 5))])]),
+        );
+        assert!(html.contains(r#"<a href="/diary/2023/05#05">"#));
+        assert!(html.contains(r#"<a href="/diary/">"#));
+    }
+
+    #[test]
+    fn estimate_reading_time() {
+        assert_eq!(reading_minutes(""), 1);
+        assert_eq!(reading_minutes(&"あ".repeat(1000)), 2);
+        assert_eq!(reading_minutes(&"word ".repeat(450)), 3);
+
+        let html = render_day(Document::new(vec![Item::Text(vec![TextItem::RawString(
+            "日".repeat(1200),
+        )])]));
+        assert!(html.contains(r#"<small class="reading-time">読了目安: 約3分</small>"#));
+    }
+
+    #[test]
+    fn render_anchor() {
+        let html = render_day(Document::new(vec![
+            Item::Anchor("set up".to_string()),
+            Item::Header(3, "Setup".to_string()),
+        ]));
+        assert!(html.contains(r#"<span id="set-up"></span><h3>Setup</h3>"#));
+    }
+
+    #[test]
+    fn render_ruby() {
+        let html = render_day(Document::new(vec![Item::Text(vec![TextItem::Ruby {
+            base: "漢字".to_string(),
+            reading: "<かんじ>".to_string(),
+        }])]));
+        assert!(html.contains("<ruby>漢字<rt>&lt;かんじ&gt;</rt></ruby>"));
+    }
+
+    #[test]
+    fn render_permalink() {
+        let html = render_day(Document::new(vec![]));
+        assert!(html.contains(
+            r##"<h2 id="05"><a href="#05">2023/05/05 (金)</a> <a class="permalink" href="#05">#</a></h2>"##
+        ));
+    }
+
+    #[test]
+    fn render_json_ld() {
+        let mut days = vec![None; 31];
+        days[4] = Some(Document::new(vec![Item::Text(vec![TextItem::RawString(
+            "</script> first".to_string(),
+        )])]));
+        days[19] = Some(Document::new(vec![Item::Geo {
+            lat: 35.68,
+            lon: 139.76,
+            label: "Tokyo".to_string(),
+        }]));
+        let mut buf = Vec::new();
+        generate_monthly(&mut buf, &Config::default(), &SiteInfo::default(), 2023, 5, days)
+            .unwrap();
+        let html = String::from_utf8(buf).unwrap();
+
+        let start = html.find(r#"<script type="application/ld+json">"#).unwrap();
+        let json = &html[start..];
+        let json = &json[json.find('>').unwrap() + 1..json.find("</script>").unwrap()];
+        let value: serde_json::Value = serde_json::from_str(json).unwrap();
+        let posts = value["@graph"].as_array().unwrap();
+        assert_eq!(posts.len(), 2);
+        assert_eq!(posts[0]["datePublished"], "2023-05-20");
+        assert_eq!(posts[1]["datePublished"], "2023-05-05");
+        assert_eq!(posts[1]["headline"], "</script> first");
+        assert_eq!(posts[1]["url"], "/2023/05#05");
+        assert_eq!(
+            posts[0]["contentLocation"],
+            serde_json::json!({
+                "@type": "Place",
+                "name": "Tokyo",
+                "geo": { "@type": "GeoCoordinates", "latitude": 35.68, "longitude": 139.76 },
+            })
+        );
+        assert!(posts[1].get("contentLocation").is_none());
+    }
+
+    #[test]
+    fn render_related_entries() {
+        let config = config_with(&[("related_entries", "2")]);
+        let tagged = |tags: &[&str]| -> OutputDocument {
+            Document::new(vec![Item::Tags(tags.iter().map(|s| s.to_string()).collect())])
+        };
+        let docs = [
+            (Date::new(2023, 5, 5).unwrap(), tagged(&["a", "b"])),
+            (Date::new(2023, 5, 6).unwrap(), tagged(&["a"])),
+            (Date::new(2023, 4, 1).unwrap(), tagged(&["a", "b"])),
+        ];
+        let site = SiteInfo::new(&config, docs.iter().map(|(d, doc)| (d.clone(), doc)));
+
+        let mut days = vec![None; 31];
+        days[4] = Some(docs[0].1.clone());
+        let mut buf = Vec::new();
+        generate_monthly(&mut buf, &config, &site, 2023, 5, days).unwrap();
+        let html = String::from_utf8(buf).unwrap();
+        assert!(html.contains(concat!(
+            r#"<aside class="related"><h4>関連する投稿</h4><ul>"#,
+            r#"<li><a href="/2023/04#01">2023/04/01</a></li>"#,
+            r#"<li><a href="/2023/05#06">2023/05/06</a></li></ul></aside>"#
+        )));
+    }
+
+    #[test]
+    fn render_series_in_part_order() {
+        let config = Config::default();
+        let part = |part: u32| -> OutputDocument {
+            Document::new(vec![Item::Series {
+                name: "Hokkaido Trip".to_string(),
+                part,
+            }])
+        };
+        // The parts are deliberately not in date order.
+        let docs = [
+            (Date::new(2023, 5, 1).unwrap(), part(1)),
+            (Date::new(2023, 5, 3).unwrap(), part(2)),
+            (Date::new(2023, 5, 2).unwrap(), part(3)),
+        ];
+        let site = SiteInfo::new(&config, docs.iter().map(|(d, doc)| (d.clone(), doc)));
+
+        let mut days = vec![None; 31];
+        for (date, doc) in &docs {
+            days[date.day() as usize - 1] = Some(doc.clone());
+        }
+        let mut buf = Vec::new();
+        generate_monthly(&mut buf, &config, &site, 2023, 5, days).unwrap();
+        let html = String::from_utf8(buf).unwrap();
+        let nav = |part: u32, items: [&str; 3]| {
+            format!(
+                r#"<nav class="series"><h4>Hokkaido Trip 第{}回</h4><ol>{}</ol></nav>"#,
+                part,
+                items.concat()
+            )
+        };
+        let first = r#"<li><a href="/2023/05#01">2023/05/01</a></li>"#;
+        let second = r#"<li><a href="/2023/05#03">2023/05/03</a></li>"#;
+        let third = r#"<li><a href="/2023/05#02">2023/05/02</a></li>"#;
+        let current = |date: &str| format!(r#"<li aria-current="page">{}</li>"#, date);
+        assert!(html.contains(&nav(1, [&current("2023/05/01"), second, third])));
+        assert!(html.contains(&nav(2, [first, &current("2023/05/03"), third])));
+        assert!(html.contains(&nav(3, [first, second, &current("2023/05/02")])));
+    }
+
+    #[test]
+    fn render_break() {
+        let txt = vec![
+            TextItem::RawString("a".to_string()),
+            TextItem::Break,
+            TextItem::RawString("b".to_string()),
+        ];
+        let html = render_day(Document::new(vec![
+            Item::Text(txt.clone()),
+            Item::List(vec![Item::Text(txt)]),
+        ]));
+        assert!(html.contains("<p>a<br>b</p>"));
+        assert!(html.contains("<ul><li>a<br>b</li></ul>"));
+    }
+
+    #[test]
+    fn render_favicons() {
+        let doc = || {
+            Document::new(vec![Item::Text(vec![
+                TextItem::WebLink(WebLink {
+                    title: "site".to_string(),
+                    href: "https://example.com/page".to_string(),
+                }),
+                TextItem::WebLink(WebLink {
+                    title: "mail".to_string(),
+                    href: "mailto:someone@example.com".to_string(),
+                }),
+            ])])
+        };
+        let plain = concat!(
+            r#"<a href="https://example.com/page">site</a>"#,
+            r#"<a href="mailto:someone@example.com">mail</a>"#
+        );
+        assert!(render_day(doc()).contains(plain));
+
+        let config = config_with(&[("favicon_url", "https://icons.example.net/{host}.ico")]);
+        assert!(render_day_with(&config, doc()).contains(concat!(
+            r#"<a href="https://example.com/page"><img class="favicon" "#,
+            r#"src="https://icons.example.net/example.com.ico" alt="" width="16" height="16">"#,
+            r#"site</a><a href="mailto:someone@example.com">mail</a>"#
+        )));
+        assert!(Config::from_map(HashMap::from([(
+            "favicon_url".to_string(),
+            "https://icons.example.net/".to_string(),
+        )]))
+        .is_err());
+    }
+
+    #[test]
+    fn render_disallowed_scheme_inert() {
+        let link = |title: &str, href: &str| {
+            TextItem::WebLink(WebLink {
+                title: title.to_string(),
+                href: href.to_string(),
+            })
+        };
+        let html = render_day(Document::new(vec![Item::Text(vec![
+            link("ok", "http://example.com"),
+            link("bad", "javascript:alert(1)"),
+        ])]));
+        assert!(html.contains(r#"<p><a href="http://example.com">ok</a>bad</p>"#));
+        assert!(!html.contains("javascript:"));
+    }
+
+    #[test]
+    fn render_collapsed_long_day() {
+        let config = config_with(&[("collapse_length", "20")]);
+        let paragraph = |s: &str| Item::Text(vec![TextItem::RawString(s.to_string())]);
+
+        let long = Document::new(vec![paragraph("First paragraph."), paragraph("The rest of it.")]);
+        let html = render_day_with(&config, long);
+        assert!(html.contains(concat!(
+            "<p>First paragraph.</p>",
+            "<details><summary>続きを読む</summary><p>The rest of it.</p></details>"
+        )));
+
+        let short = Document::new(vec![paragraph("Short.")]);
+        assert!(!render_day_with(&config, short).contains("<details>"));
+    }
+
+    #[test]
+    fn render_title() {
+        let html = render_day(Document::new(vec![Item::Title("<My Day>".to_string())]));
+        assert!(html.contains(r#"</h2><h3 class="title">&lt;My Day&gt;</h3></dt>"#));
+    }
+
+    #[test]
+    fn render_classes() {
+        let html = render_day(Document::new(vec![
+            Item::Class("trip".to_string()),
+            Item::Class("long haul".to_string()),
+        ]));
+        assert!(html.contains(r#"<dd class="trip long-haul">"#));
+        assert!(render_day(Document::new(vec![])).contains("<dd>"));
+    }
+
+    #[test]
+    fn render_week_groups() {
+        let config = config_with(&[("group_by_week", "true")]);
+        let mut docs = vec![None; 31];
+        for day in [1, 5, 8] {
+            docs[day - 1] = Some(Document::new(vec![]));
+        }
+        let mut buf = Vec::new();
+        generate_monthly(&mut buf, &config, &SiteInfo::default(), 2023, 5, docs).unwrap();
+        let html = String::from_utf8(buf).unwrap();
+        let week19 = html.find(r#"<h2 class="week">第19週</h2><dl><dt><h2 id="08">"#).unwrap();
+        let week18 = html.find(r#"<h2 class="week">第18週</h2><dl><dt><h2 id="05">"#).unwrap();
+        assert!(week19 < week18);
+        assert!(html[week18..].contains(r#"<h2 id="01">"#));
+    }
+
+    #[test]
+    fn render_numbered_headings() {
+        let config = config_with(&[("number_headings", "true")]);
+        let header = |level, s: &str| Item::Header(level, s.to_string());
+        let html = render_day_with(
+            &config,
+            Document::new(vec![
+                header(3, "A"),
+                header(4, "A-a"),
+                header(4, "A-b"),
+                header(3, "B"),
+                header(4, "B-a"),
+            ]),
+        );
+        assert!(html.contains(concat!(
+            "<h3>1 A</h3><h4>1.1 A-a</h4><h4>1.2 A-b</h4>",
+            "<h3>2 B</h3><h4>2.1 B-a</h4>"
+        )));
+        let html = render_day_with(
+            &config,
+            Document::new(vec![header(3, "A"), header(2, "Part"), header(3, "B")]),
+        );
+        assert!(html.contains("<h3>1 A</h3><h2>Part</h2><h3>1 B</h3>"));
+    }
+
+    #[test]
+    fn render_header_levels() {
+        let html = render_day(Document::new(vec![
+            Item::Header(2, "Two".to_string()),
+            Item::Header(3, "Three".to_string()),
+            Item::Header(6, "Six".to_string()),
+        ]));
+        assert!(html.contains("<h2>Two</h2><h3>Three</h3><h6>Six</h6>"));
+    }
+
+    #[test]
+    fn render_lang() {
+        let mut days = vec![None; 31];
+        days[4] = Some(Document::new(vec![Item::Lang("en".to_string())]));
+        days[5] = Some(Document::new(vec![]));
+        let mut buf = Vec::new();
+        generate_monthly(&mut buf, &Config::default(), &SiteInfo::default(), 2023, 5, days)
+            .unwrap();
+        let html = String::from_utf8(buf).unwrap();
+        assert!(html.contains(r#"<html lang="ja">"#));
+        assert_eq!(html.matches("<dd ").count(), 1);
+        assert!(html.contains(r#"<dd lang="en">"#));
+    }
+
+    #[test]
+    fn render_highlight_script() {
+        let doc = || Document::new(vec![Item::Class("trip".to_string())]);
+        let html = render_day(doc());
+        assert!(!html.contains("<script>"));
+        assert!(!html.contains("searchable"));
+
+        let config = config_with(&[("highlight_search", "true")]);
+        let html = render_day_with(&config, doc());
+        assert!(html.contains(r#"<dd class="trip searchable">"#));
+        assert!(html.contains(r#"<script>(()=>{const q=new URLSearchParams"#));
+        assert!(html.contains(r#"querySelectorAll(".searchable")"#));
+        assert!(html.ends_with("</script></body></html>"));
+    }
+
+    #[test]
+    fn print_omits_navigation() {
+        let config = config_with(&[("print_css", "/print.css"), ("base_path", "/diary")]);
+        let mut days = vec![None; 31];
+        days[4] = Some(Document::new(vec![Item::Text(vec![TextItem::RawString(
+            "x".to_string(),
+        )])]));
+        let mut buf = Vec::new();
+        generate_print(&mut buf, &config, &SiteInfo::default(), 2023, 5, &days).unwrap();
+        let html = String::from_utf8(buf).unwrap();
+        assert!(html.contains(r#"<link rel="stylesheet" media="print" href="/diary/print.css">"#));
+        assert!(html.contains("<h1>Natuka.ge - 2023/05</h1><dl><dt>"));
+        assert!(!html.contains(r#"<a href="/diary/">"#));
+        assert!(!html.contains("<nav>"));
+        assert!(!html.contains("permalink"));
+
+        let date = Date::new(2023, 5, 5).unwrap();
+        let (prev, next) = (Date::new(2023, 5, 4).unwrap(), Date::new(2023, 5, 6).unwrap());
+        let doc = days[4].as_ref().unwrap();
+        let site = SiteInfo::default();
+        let mut buf = Vec::new();
+        generate_day_page(&mut buf, &config, &site, &date, doc, Some(&prev), Some(&next)).unwrap();
+        let day_page = String::from_utf8(buf).unwrap();
+        assert!(day_page.contains(r#"<a href="/diary/">"#));
+        assert!(day_page.contains(r#"<nav><a rel="prev""#));
+    }
+
+    #[test]
+    fn render_details() {
+        let html = render_day(Document::new(vec![Item::Details {
+            summary: "<More>".to_string(),
+            body: vec![
+                Item::Text(vec![TextItem::RawString("Body".to_string())]),
+                Item::List(vec![Item::Text(vec![TextItem::RawString("a".to_string())])]),
+            ],
+        }]));
+        assert!(html.contains(
+            "<details><summary>&lt;More&gt;</summary><p>Body</p><ul><li>a</li></ul></details>"
+        ));
+    }
+
+    #[test]
+    fn render_quote() {
+        let quote = |cite: Option<&str>| Item::Quote {
+            body: vec![Item::Text(vec![TextItem::RawString("To be".to_string())])],
+            cite: cite.map(str::to_string),
+        };
+        let html = render_day(Document::new(vec![quote(Some("<Hamlet>")), quote(None)]));
+        assert!(html.contains(concat!(
+            "<blockquote><p>To be</p><cite>&lt;Hamlet&gt;</cite></blockquote>",
+            "<blockquote><p>To be</p></blockquote>"
+        )));
+    }
+
+    #[test]
+    fn render_math() {
+        let html = render_day(Document::new(vec![Item::Text(vec![TextItem::Math(
+            "a<b".to_string(),
+        )])]));
+        assert!(html.contains(r#"<p><span class="math">a&lt;b</span></p>"#));
+    }
+
+    #[test]
+    fn render_italic() {
+        let html = render_day(Document::new(vec![
+            Item::Text(vec![
+                TextItem::RawString("a ".to_string()),
+                TextItem::Italic("<b>".to_string()),
+            ]),
+            Item::List(vec![Item::Text(vec![TextItem::Italic("c".to_string())])]),
+        ]));
+        assert!(html.contains("<p>a <em>&lt;b&gt;</em></p>"));
+        assert!(html.contains("<ul><li><em>c</em></li></ul>"));
+    }
+
+    #[test]
+    fn render_strike() {
+        let html = render_day(Document::new(vec![Item::Text(vec![
+            TextItem::RawString("before".to_string()),
+            TextItem::Strike("wrong".to_string()),
+            TextItem::RawString("after".to_string()),
+            TextItem::Bold("!".to_string()),
+        ])]));
+        assert!(html.contains("<p>before<del>wrong</del>after<b>!</b></p>"));
+    }
+
+    #[test]
+    fn render_nested_ordered_list() {
+        let text = |s: &str| Item::Text(vec![TextItem::RawString(s.to_string())]);
+        let html = render_day(Document::new(vec![Item::List(vec![
+            text("a"),
+            Item::OrderedList(vec![text("one"), text("two")]),
+            text("b"),
+        ])]));
+        assert!(html.contains("<ul><li>a</li><ol><li>one</li><li>two</li></ol><li>b</li></ul>"));
+    }
+
+    #[test]
+    fn render_mark() {
+        let html = render_day(Document::new(vec![Item::List(vec![Item::Text(vec![
+            TextItem::Mark("<important>".to_string()),
+        ])])]));
+        assert!(html.contains("<ul><li><mark>&lt;important&gt;</mark></li></ul>"));
+    }
+
+    #[test]
+    fn render_shared_image_caption() {
+        let items = ["a.webp", "b.webp"]
+            .map(|name| ImageItem {
+                data: ImagePath::placeholder(name),
+                caption: Some(name.to_uppercase()),
+            })
+            .to_vec();
+        let html = render_day(Document::new(vec![Item::Images(Images {
+            title: String::new(),
+            items,
+            caption: Some("Trip & food".to_string()),
+        })]));
+        assert!(html.contains(concat!(
+            "<tr><td>A.WEBP</td><td>B.WEBP</td></tr></tbody></table>",
+            "<figcaption>Trip &amp; food</figcaption></figure>"
+        )));
+        assert!(html.contains("<figure><table>"));
+    }
+
+    #[test]
+    fn render_caption_positions() {
+        let doc = || {
+            Document::new(vec![Item::Images(Images {
+                title: String::new(),
+                items: vec![ImageItem {
+                    data: ImagePath::placeholder("a.webp"),
+                    caption: Some("A".to_string()),
+                }],
+                caption: None,
+            })])
+        };
+        let render = |position: &str| {
+            let config = config_with(&[("caption_position", position)]);
+            render_day_with(&config, doc())
+        };
+        let image_row = r#"<tr><td><span class="missing-image">a.webp</span></td></tr>"#;
+        let caption_row = "<tr><td>A</td></tr>";
+        let table = |rows: &[&str]| format!("<table><tbody>{}</tbody></table>", rows.concat());
+        assert!(render_day(doc()).contains(&table(&[image_row, caption_row])));
+        assert!(render("below").contains(&table(&[image_row, caption_row])));
+        assert!(render("above").contains(&table(&[caption_row, image_row])));
+        assert!(render("none").contains(&table(&[image_row])));
+        assert!(Config::from_map(HashMap::from([(
+            "caption_position".to_string(),
+            "left".to_string()
+        )]))
+        .is_err());
+    }
+
+    #[test]
+    fn render_gallery_layouts() {
+        let doc = |caption: Option<&str>| {
+            let items = [("a.webp", Some("A")), ("b.webp", None)]
+                .map(|(name, caption)| ImageItem {
+                    data: ImagePath::placeholder(name),
+                    caption: caption.map(str::to_string),
+                })
+                .to_vec();
+            Document::new(vec![Item::Images(Images {
+                title: String::new(),
+                items,
+                caption: caption.map(str::to_string),
+            })])
+        };
+        let render = |settings: &[(&str, &str)], caption: Option<&str>| {
+            render_day_with(&config_with(settings), doc(caption))
+        };
+        let a = r#"<span class="missing-image">a.webp</span>"#;
+        let b = r#"<span class="missing-image">b.webp</span>"#;
+
+        let html = render(&[("gallery_layout", "figure")], None);
+        assert!(html.contains(&format!(
+            concat!(
+                r#"<div class="gallery"><figure>{}<figcaption>A</figcaption></figure>"#,
+                "<figure>{}</figure></div>"
+            ),
+            a, b
+        )));
+        assert!(!html.contains("<table>"));
+        let settings = [("gallery_layout", "figure"), ("caption_position", "above")];
+        let html = render(&settings, Some("C"));
+        assert!(html.contains(&format!(
+            concat!(
+                r#"<figure class="gallery"><figure><figcaption>A</figcaption>{}</figure>"#,
+                "<figure>{}</figure><figcaption>C</figcaption></figure>"
+            ),
+            a, b
+        )));
+        let html = render(&[("gallery_layout", "table")], None);
+        assert!(html.contains(&format!(
+            concat!(
+                "<table><tbody><tr><td>{}</td><td>{}</td></tr>",
+                "<tr><td>A</td><td></td></tr></tbody></table>"
+            ),
+            a, b
+        )));
+        assert!(!html.contains("<figure>"));
+        assert_eq!(html, render(&[], None));
+    }
+
+    #[test]
+    fn render_geo() {
+        let html = render_day(Document::new(vec![Item::Geo {
+            lat: 35.68,
+            lon: -0.5,
+            label: "Tokyo & around".to_string(),
+        }]));
+        assert!(html.contains(concat!(
+            r#"<p class="geo"><a href="https://www.openstreetmap.org/"#,
+            r#"?mlat=35.68&amp;mlon=-0.5#map=15/35.68/-0.5">Tokyo &amp; around</a></p>"#
+        )));
+    }
+
+    #[test]
+    fn render_abbr() {
+        let html = render_day(Document::new(vec![Item::Text(vec![TextItem::Abbr {
+            text: "R&D".to_string(),
+            title: "Research \"and\" Development".to_string(),
+        }])]));
+        assert!(html.contains(
+            r#"<p><abbr title="Research &quot;and&quot; Development">R&amp;D</abbr></p>"#
+        ));
+    }
+
+    #[test]
+    fn render_custom_date_heading() {
+        let config = config_with(&[("date_heading", "{date}・{weekday}")]);
+        let html = render_day_with(&config, Document::new(vec![]));
+        assert!(html.contains(r##"<h2 id="05"><a href="#05">2023/05/05・金</a> "##));
+    }
+
+    #[test]
+    fn slug_anchors() {
+        let config = config_with(&[("slug_anchors", "true")]);
+        let docs: [(Date, OutputDocument); 2] = [
+            (
+                Date::new(2023, 5, 5).unwrap(),
+                Document::new(vec![
+                    Item::Title("Trip to Kyoto".to_string()),
+                    Item::Text(vec![TextItem::PostLink((2023, 5, 5))]),
+                ]),
+            ),
+            (Date::new(2023, 5, 6).unwrap(), Document::new(vec![])),
+        ];
+        let site = SiteInfo::new(&config, docs.iter().map(|(d, doc)| (d.clone(), doc)));
+
+        let mut days = vec![None; 31];
+        days[4] = Some(docs[0].1.clone());
+        days[5] = Some(docs[1].1.clone());
+        let mut buf = Vec::new();
+        generate_monthly(&mut buf, &config, &site, 2023, 5, days).unwrap();
+        let html = String::from_utf8(buf).unwrap();
+        assert!(html.contains(
+            r##"<span id="05"></span><h2 id="trip-to-kyoto"><a href="#trip-to-kyoto">"##
+        ));
+        assert!(html.contains(r##"<a href="/2023/05#trip-to-kyoto">2023/05/05</a>"##));
+        assert!(html.contains(r##"<dt><h2 id="06"><a href="#06">"##));
+    }
+
+    #[test]
+    fn render_multi_paragraph_list_item() {
+        let paragraph = |s: &str| Item::Text(vec![TextItem::RawString(s.to_string())]);
+        let html = render_day(Document::new(vec![Item::List(vec![
+            Item::ListEntry(vec![paragraph("first"), paragraph("second")]),
+            paragraph("plain"),
+        ])]));
+        assert!(html.contains("<ul><li><p>first</p><p>second</p></li><li>plain</li></ul>"));
+    }
+}
@@ -0,0 +1,98 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+    time::Duration,
+};
+
+use log::{info, warn};
+use serde_json::json;
+
+use crate::image::ImageOutcome;
+
+#[derive(Clone, Debug, Default)]
+pub struct BuildReport {
+    pub pages: usize,
+    pub images_converted: usize,
+    pub images_cached: usize,
+    pub thumbnail_bytes: u64,
+    pub warnings: Vec<String>,
+    pub elapsed: Duration,
+    /// The number of days with an entry of each `(year, month)` built.
+    pub entries: BTreeMap<(u32, u32), usize>,
+}
+
+impl BuildReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_image(&mut self, outcome: &ImageOutcome) {
+        match outcome {
+            ImageOutcome::CacheHit => self.images_cached += 1,
+            ImageOutcome::Converted { thumbnail_bytes } => {
+                self.images_converted += 1;
+                self.thumbnail_bytes += thumbnail_bytes;
+            }
+        }
+    }
+
+    pub fn record_page(&mut self) {
+        self.pages += 1;
+    }
+
+    pub fn record_entries(&mut self, year: u32, month: u32, count: usize) {
+        *self.entries.entry((year, month)).or_default() += count;
+    }
+
+    /// The number of days with an entry over every month built.
+    pub fn total_entries(&self) -> usize {
+        self.entries.values().sum()
+    }
+
+    /// Logs the warning and keeps it for the report.
+    pub fn warn(&mut self, message: String) {
+        warn!("{}", message);
+        self.warnings.push(message);
+    }
+
+    pub fn log(&self) {
+        info!(
+            "Images: {} converted, {} cached ({} bytes of thumbnails generated)",
+            self.images_converted, self.images_cached, self.thumbnail_bytes
+        );
+        info!(
+            "{} pages of {} entries generated in {} ms with {} warnings",
+            self.pages,
+            self.total_entries(),
+            self.elapsed.as_millis(),
+            self.warnings.len()
+        );
+    }
+
+    pub fn write_json<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut years = BTreeMap::<String, serde_json::Value>::new();
+        for (&(year, month), &count) in &self.entries {
+            let year = years
+                .entry(year.to_string())
+                .or_insert_with(|| json!({ "total": 0, "months": {} }));
+            year["total"] = json!(year["total"].as_u64().unwrap_or(0) + count as u64);
+            year["months"][format!("{:02}", month)] = json!(count);
+        }
+        let json = json!({
+            "pages": self.pages,
+            "images": {
+                "converted": self.images_converted,
+                "cached": self.images_cached,
+                "thumbnail_bytes": self.thumbnail_bytes,
+            },
+            "entries": {
+                "total": self.total_entries(),
+                "years": years,
+            },
+            "warnings": self.warnings,
+            "elapsed_ms": self.elapsed.as_millis() as u64,
+        });
+        serde_json::to_writer_pretty(&mut *writer, &json)?;
+        writeln!(writer)
+    }
+}
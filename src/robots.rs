@@ -0,0 +1,25 @@
+use std::io::{self, Write};
+
+use crate::config::Config;
+
+/// Writes a `robots.txt` allowing everything but the `disallow` paths, and
+/// pointing at `/sitemap.xml` if `sitemap` is true. The `Sitemap:` line needs
+/// a full URL, so it is left out without `base_url`.
+pub fn write_robots_txt<W: Write>(
+    writer: &mut W,
+    config: &Config,
+    sitemap: bool,
+) -> io::Result<()> {
+    writeln!(writer, "User-agent: *")?;
+    if config.disallow().is_empty() {
+        writeln!(writer, "Disallow:")?;
+    }
+    for path in config.disallow() {
+        writeln!(writer, "Disallow: {}", config.path(path))?;
+    }
+    if sitemap && !config.base_url().is_empty() {
+        writeln!(writer)?;
+        writeln!(writer, "Sitemap: {}", config.url("/sitemap.xml"))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,149 @@
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    path::{Component, Path, PathBuf},
+    time::Duration,
+};
+
+use log::{info, warn};
+
+pub const DEFAULT_PORT: u16 = 8000;
+
+/// How long a client may take to send its request line, so that an idle one
+/// can't hold up the others.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serves `root` under `base_path` on localhost until the process is killed.
+pub fn serve(root: &Path, base_path: &str, port: u16) -> io::Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    info!("Serving {} at http://127.0.0.1:{}{}/", root.display(), port, base_path);
+    for stream in listener.incoming() {
+        if let Err(err) = stream.and_then(|stream| handle(root, base_path, stream)) {
+            warn!("Failed to handle a request: {}", err);
+        }
+    }
+    Ok(())
+}
+
+fn handle(root: &Path, base_path: &str, mut stream: TcpStream) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+
+    let found = if method == "GET" || method == "HEAD" {
+        resolve(root, base_path, target).and_then(|path| Some((fs::read(&path).ok()?, path)))
+    } else {
+        None
+    };
+    match found {
+        Some((body, path)) => {
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                content_type(&path),
+                body.len()
+            )?;
+            if method == "GET" {
+                stream.write_all(&body)?;
+            }
+        }
+        None => {
+            let body = "Not Found";
+            write!(
+                stream,
+                "HTTP/1.1 404 Not Found\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )?;
+        }
+    }
+    stream.flush()
+}
+
+/// Maps a request target to a file under `root` the way the production server
+/// does: `/2023/05` is `2023/05.html` and `/` is `index.html`, both under
+/// `base_path`. Targets outside `base_path` are not found.
+pub fn resolve(root: &Path, base_path: &str, target: &str) -> Option<PathBuf> {
+    let path = target.split(['?', '#']).next().unwrap_or_default();
+    let path = path.strip_prefix(base_path)?;
+    if !path.is_empty() && !path.starts_with('/') {
+        return None;
+    }
+    let relative = PathBuf::from(percent_decode(path)?.trim_start_matches('/'));
+    if !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+        return None;
+    }
+
+    let base = root.join(&relative);
+    let mut candidates = vec![base.join("index.html")];
+    if relative.file_name().is_some() && !path.ends_with('/') {
+        candidates.insert(0, base.with_extension("html"));
+        candidates.insert(0, base.clone());
+    }
+    candidates.into_iter().find(|candidate| candidate.is_file())
+}
+
+fn percent_decode(s: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut iter = s.bytes();
+    while let Some(b) = iter.next() {
+        if b == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(b);
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("xml") => "application/xml",
+        Some("opml") => "text/x-opml",
+        Some("json") => "application/json",
+        Some("txt") | Some("lisp") => "text/plain; charset=utf-8",
+        Some("css") => "text/css",
+        Some("jpeg") | Some("jpg") => "image/jpeg",
+        Some("webp") => "image/webp",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{serve::resolve, test_util::TempDir};
+
+    #[test]
+    fn resolve_routes() {
+        let dir = TempDir::new();
+        dir.write("index.html", b"");
+        dir.write("2023/05.html", b"");
+        dir.write("2023/05/05.html", b"");
+        dir.write("img/my photo.webp", b"");
+        let root = dir.path();
+
+        assert_eq!(resolve(root, "", "/"), Some(root.join("index.html")));
+        assert_eq!(resolve(root, "", "/2023/05"), Some(root.join("2023/05.html")));
+        assert_eq!(resolve(root, "", "/2023/05#05"), Some(root.join("2023/05.html")));
+        assert_eq!(resolve(root, "", "/2023/05/05"), Some(root.join("2023/05/05.html")));
+        assert_eq!(resolve(root, "", "/2023/05.html"), Some(root.join("2023/05.html")));
+        assert_eq!(
+            resolve(root, "", "/img/my%20photo.webp"),
+            Some(root.join("img/my photo.webp"))
+        );
+        assert_eq!(resolve(root, "", "/2023/06"), None);
+        assert_eq!(resolve(root, "", "/../index.html"), None);
+        assert_eq!(resolve(root, "", "/2023/%2E%2E/index.html"), None);
+
+        assert_eq!(resolve(root, "/diary", "/diary"), Some(root.join("index.html")));
+        assert_eq!(resolve(root, "/diary", "/diary/"), Some(root.join("index.html")));
+        assert_eq!(resolve(root, "/diary", "/diary/2023/05"), Some(root.join("2023/05.html")));
+        assert_eq!(resolve(root, "/diary", "/2023/05"), None);
+        assert_eq!(resolve(root, "/diary", "/diary2023/05"), None);
+    }
+}
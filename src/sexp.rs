@@ -6,13 +6,15 @@ use std::{
 
 use crate::string_reader::StringReader;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Expression {
     Tuple(Vec<Expression>),
     Literal(String),
     String(String),
     BackQuotedString(String),
     Integer(u32),
+    /// A decimal number with a sign or a fractional part, e.g. `-0.5`.
+    Float(f64),
 }
 
 pub type RandIter = IntoIter<Expression>;
@@ -48,12 +50,15 @@ pub enum Error {
 pub enum ParseError {
     UnexpectedEOF,
     UnexpectedCharacter(u8),
+    IntegerOverflow,
 }
 
 pub type ParseResult<T> = Result<T, Error>;
 
 pub struct SExpParser<R: Read> {
     reader: StringReader<R>,
+    /// The line and column of the `(` of every `()` parsed, in source order.
+    empty_tuples: Vec<(usize, usize)>,
 }
 
 enum ExpressionOrChr {
@@ -63,7 +68,16 @@ enum ExpressionOrChr {
 
 impl<R: Read> SExpParser<R> {
     pub const fn new(reader: StringReader<R>) -> Self {
-        Self { reader }
+        Self {
+            reader,
+            empty_tuples: Vec::new(),
+        }
+    }
+
+    /// Where each `()` parsed so far starts, in source order, so that an
+    /// error about one can be located. See `count_empty_tuples`.
+    pub fn empty_tuples(&self) -> &[(usize, usize)] {
+        &self.empty_tuples
     }
 
     const fn chr(&self) -> Option<u8> {
@@ -83,20 +97,22 @@ impl<R: Read> SExpParser<R> {
 
     fn parse_expression_or_chr(&mut self) -> ParseResult<ExpressionOrChr> {
         let chr = self.roll_up_and_get()?;
+        let position = self.reader.position();
         self.seek()?;
         match chr {
-            b'(' => self.parse_tuple().map(ExpressionOrChr::Expression),
+            b'(' => self.parse_tuple(position).map(ExpressionOrChr::Expression),
             b'"' => self.parse_string().map(ExpressionOrChr::Expression),
             b'`' => self
                 .parse_backquoted_string()
                 .map(ExpressionOrChr::Expression),
             b'0'..=b'9' => self.parse_number(chr).map(ExpressionOrChr::Expression),
+            b'-' => self.parse_negative().map(ExpressionOrChr::Expression),
             b'a'..=b'z' | b'A'..=b'Z' => self.parse_literal(chr).map(ExpressionOrChr::Expression),
             _ => Ok(ExpressionOrChr::Chr(chr)),
         }
     }
 
-    fn parse_tuple(&mut self) -> ParseResult<Expression> {
+    fn parse_tuple(&mut self, position: (usize, usize)) -> ParseResult<Expression> {
         let mut result = Vec::new();
 
         loop {
@@ -105,6 +121,9 @@ impl<R: Read> SExpParser<R> {
                 ExpressionOrChr::Expression(e) => result.push(e),
                 ExpressionOrChr::Chr(chr) => {
                     return if chr == b')' {
+                        if result.is_empty() {
+                            self.empty_tuples.push(position);
+                        }
                         Ok(Expression::Tuple(result))
                     } else {
                         unexpected_chr(chr)
@@ -181,23 +200,91 @@ impl<R: Read> SExpParser<R> {
     }
 
     fn parse_number(&mut self, initial: u8) -> ParseResult<Expression> {
-        fn str_to_u32(n: u8) -> u32 {
-            (n - b'0').into()
+        if initial == b'0' {
+            match self.chr() {
+                Some(b'x' | b'X') => {
+                    self.seek()?;
+                    return self.parse_radix_number(16).map(Expression::Integer);
+                }
+                Some(b'b' | b'B') => {
+                    self.seek()?;
+                    return self.parse_radix_number(2).map(Expression::Integer);
+                }
+                _ => {}
+            }
+        }
+
+        let integer = self.parse_digits(10, digit_value(initial, 10).unwrap_or(0))?;
+        if self.chr() == Some(b'.') {
+            self.seek()?;
+            self.parse_fraction(integer).map(Expression::Float)
+        } else {
+            Ok(Expression::Integer(integer))
+        }
+    }
+
+    /// Parses the digits after the decimal point of `integer`, of which there
+    /// must be at least one.
+    fn parse_fraction(&mut self, integer: u32) -> ParseResult<f64> {
+        let mut text = format!("{}.", integer);
+        match self.chr() {
+            Some(chr) if chr.is_ascii_digit() => {}
+            Some(chr) => return unexpected_chr(chr),
+            None => return unexpected_eof(),
+        }
+        while let Some(chr) = self.chr().filter(u8::is_ascii_digit) {
+            text.push(chr as char);
+            self.seek()?;
+        }
+        Ok(text.parse().expect("digits around a decimal point"))
+    }
+
+    /// Parses the number after a `-`, which is always a `Float`.
+    fn parse_negative(&mut self) -> ParseResult<Expression> {
+        let initial = match self.chr() {
+            Some(chr) if chr.is_ascii_digit() => chr,
+            Some(chr) => return unexpected_chr(chr),
+            None => return unexpected_eof(),
+        };
+        self.seek()?;
+        match self.parse_number(initial)? {
+            Expression::Integer(n) => Ok(Expression::Float(-f64::from(n))),
+            Expression::Float(x) => Ok(Expression::Float(-x)),
+            _ => unreachable!(),
         }
+    }
 
-        let mut result = str_to_u32(initial);
+    /// Parses the digits following a `0x`/`0b` prefix, which must not be empty.
+    fn parse_radix_number(&mut self, radix: u32) -> ParseResult<u32> {
+        match self.chr() {
+            Some(chr) => match digit_value(chr, radix) {
+                Some(initial) => {
+                    self.seek()?;
+                    self.parse_digits(radix, initial)
+                }
+                None => unexpected_chr(chr),
+            },
+            None => unexpected_eof(),
+        }
+    }
+
+    fn parse_digits(&mut self, radix: u32, initial: u32) -> ParseResult<u32> {
+        let mut result = initial;
 
         while let Some(chr) = self.chr() {
-            match chr {
-                b'0'..=b'9' => {
+            match digit_value(chr, radix) {
+                Some(digit) => {
                     self.seek()?;
-                    result = result * 10 + str_to_u32(chr);
+                    result = result
+                        .checked_mul(radix)
+                        .and_then(|n| n.checked_add(digit))
+                        .ok_or(Error::ParseError(ParseError::IntegerOverflow))?;
                 }
-                _ => break,
+                None => break,
             }
         }
 
-        Ok(Expression::Integer(result))
+        Ok(result)
     }
 
     fn parse_literal(&mut self, initial: u8) -> ParseResult<Expression> {
@@ -229,6 +316,20 @@ impl<R: Read> SExpParser<R> {
     }
 }
 
+/// How many `()` there are in `expr`, including itself, to index the
+/// positions recorded by `SExpParser::empty_tuples` from.
+pub fn count_empty_tuples(expr: &Expression) -> usize {
+    match expr {
+        Expression::Tuple(t) if t.is_empty() => 1,
+        Expression::Tuple(t) => t.iter().map(count_empty_tuples).sum(),
+        _ => 0,
+    }
+}
+
+fn digit_value(chr: u8, radix: u32) -> Option<u32> {
+    (chr as char).to_digit(radix)
+}
+
 const fn unexpected_eof<T>() -> Result<T, Error> {
     Err(Error::ParseError(ParseError::UnexpectedEOF))
 }
@@ -279,7 +380,7 @@ macro_rules! parse_func {
 #[cfg(test)]
 mod tests {
     use crate::{
-        sexp::{Expression, SExpParser},
+        sexp::{count_empty_tuples, Error, Expression, ParseError, SExpParser},
         string_reader::StringReader,
     };
     use std::iter;
@@ -297,6 +398,16 @@ mod tests {
         test_base(r"()", Expression::Tuple(vec![]));
     }
 
+    #[test]
+    fn locate_empty_tuples() {
+        let txt = "(() (a\n  ()) `あ` ())";
+        let reader = StringReader::new(txt.as_bytes()).unwrap().unwrap();
+        let mut parser = SExpParser::new(reader);
+        let expr = parser.parse_expression().unwrap();
+        assert_eq!(parser.empty_tuples(), [(1, 2), (2, 3), (2, 11)]);
+        assert_eq!(count_empty_tuples(&expr), 3);
+    }
+
     #[test]
     fn parse_string() {
         let text = "TestString1234567890!@#$%^&*()_+|~";
@@ -321,6 +432,51 @@ mod tests {
         test_base(&format!("{}", i), Expression::Integer(i));
     }
 
+    #[test]
+    fn parse_hex_and_binary_int() {
+        test_base("0xFF", Expression::Integer(255));
+        test_base("0b101", Expression::Integer(5));
+        test_base(
+            "(0x1f 0)",
+            Expression::Tuple(vec![Expression::Integer(31), Expression::Integer(0)]),
+        );
+    }
+
+    #[test]
+    fn parse_float() {
+        test_base("35.68", Expression::Float(35.68));
+        test_base("-0.5", Expression::Float(-0.5));
+        test_base("-12", Expression::Float(-12.0));
+        test_base(
+            "(1.25 7)",
+            Expression::Tuple(vec![Expression::Float(1.25), Expression::Integer(7)]),
+        );
+        for text in ["1.", "1.x", "-", "-x"] {
+            let reader = StringReader::new(text.as_bytes()).unwrap().unwrap();
+            assert!(SExpParser::new(reader).parse_expression().is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn parse_hex_without_digits() {
+        let reader = StringReader::new("0x".as_bytes()).unwrap().unwrap();
+        let mut parser = SExpParser::new(reader);
+        assert!(matches!(
+            parser.parse_expression(),
+            Err(Error::ParseError(ParseError::UnexpectedEOF))
+        ));
+    }
+
+    #[test]
+    fn parse_int_overflow() {
+        let reader = StringReader::new("0x100000000".as_bytes()).unwrap().unwrap();
+        let mut parser = SExpParser::new(reader);
+        assert!(matches!(
+            parser.parse_expression(),
+            Err(Error::ParseError(ParseError::IntegerOverflow))
+        ));
+    }
+
     #[test]
     fn parse_literal() {
         let text = "TestString1234567890gnirtStseT";
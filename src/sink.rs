@@ -0,0 +1,141 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::tar::TarWriter;
+
+/// Where the built site is written: loose files under `public/`, or a tar
+/// archive which pages and copied files are streamed into as they are built.
+pub enum Sink {
+    Dir,
+    Tar {
+        /// The directory which the paths in the archive are relative to.
+        root: PathBuf,
+        archive: Mutex<TarWriter<BufWriter<File>>>,
+    },
+}
+
+/// A file being written to a `Sink`, which is only complete after `finish`.
+pub enum SinkFile<'a> {
+    File(BufWriter<File>),
+    /// An archive entry, which is buffered as its header needs the size.
+    Entry {
+        archive: &'a Mutex<TarWriter<BufWriter<File>>>,
+        name: String,
+        buf: Vec<u8>,
+    },
+}
+
+impl Sink {
+    /// Streams the files under `root` into a new tar archive at `tar_path`.
+    pub fn tar(root: PathBuf, tar_path: &Path) -> io::Result<Self> {
+        let file = File::create(tar_path)?;
+        Ok(Self::Tar {
+            root,
+            archive: Mutex::new(TarWriter::new(BufWriter::new(file))),
+        })
+    }
+
+    /// Creates the directory at `path` and its parents unless they exist.
+    pub fn create_dir(&self, path: &Path) -> io::Result<()> {
+        match self {
+            Self::Dir => fs::create_dir_all(path),
+            Self::Tar { root, archive } => {
+                let name = entry_name(root, path)?;
+                if name.is_empty() {
+                    return Ok(());
+                }
+                lock(archive).append_dir(&name, mtime(SystemTime::now()))
+            }
+        }
+    }
+
+    pub fn create(&self, path: &Path) -> io::Result<SinkFile<'_>> {
+        match self {
+            Self::Dir => Ok(SinkFile::File(BufWriter::new(File::create(path)?))),
+            Self::Tar { root, archive } => Ok(SinkFile::Entry {
+                archive,
+                name: entry_name(root, path)?,
+                buf: Vec::new(),
+            }),
+        }
+    }
+
+    /// Copies the file at `src` to `dst`, keeping its modification time in an
+    /// archive.
+    pub fn copy(&self, src: &Path, dst: &Path) -> io::Result<()> {
+        match self {
+            Self::Dir => fs::copy(src, dst).map(|_| ()),
+            Self::Tar { root, archive } => {
+                let file = File::open(src)?;
+                let metadata = file.metadata()?;
+                let name = entry_name(root, dst)?;
+                let mtime = mtime(metadata.modified()?);
+                lock(archive).append_file(&name, metadata.len(), mtime, file)
+            }
+        }
+    }
+
+    /// Ends an archive. Nothing is left to do for loose files.
+    pub fn finish(&self) -> io::Result<()> {
+        match self {
+            Self::Dir => Ok(()),
+            Self::Tar { archive, .. } => lock(archive).finish(),
+        }
+    }
+}
+
+impl SinkFile<'_> {
+    /// Flushes a file, or adds an entry to the archive.
+    pub fn finish(self) -> io::Result<()> {
+        match self {
+            Self::File(mut writer) => writer.flush(),
+            Self::Entry { archive, name, buf } => {
+                let mtime = mtime(SystemTime::now());
+                lock(archive).append_file(&name, buf.len() as u64, mtime, buf.as_slice())
+            }
+        }
+    }
+}
+
+impl Write for SinkFile<'_> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::File(writer) => writer.write(data),
+            Self::Entry { buf, .. } => buf.write(data),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::File(writer) => writer.flush(),
+            Self::Entry { .. } => Ok(()),
+        }
+    }
+}
+
+/// The path of `path` in an archive of `root`.
+fn entry_name(root: &Path, path: &Path) -> io::Result<String> {
+    path.strip_prefix(root)
+        .ok()
+        .and_then(Path::to_str)
+        .map(str::to_string)
+        .ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("{} can't be put in the archive", path.display()),
+            )
+        })
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().expect("An archive writer panicked")
+}
+
+fn mtime(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
@@ -0,0 +1,204 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::{
+    config::{Config, PageGranularity},
+    date::Date,
+    diary_content::Document,
+};
+
+/// Information about the whole archive which pages of single months need.
+#[derive(Clone, Debug, Default)]
+pub struct SiteInfo {
+    related: HashMap<Date, Vec<Date>>,
+    /// The anchors of the entries which don't use their day number.
+    slugs: HashMap<Date, String>,
+    /// The part numbers and the dates of the entries of each series, in part order.
+    series: HashMap<String, Vec<(u32, Date)>>,
+    /// The series each entry belongs to.
+    series_names: HashMap<Date, String>,
+}
+
+impl SiteInfo {
+    pub fn new<'a, T: Sized + Clone + 'a, I: Iterator<Item = (Date, &'a Document<T>)>>(
+        config: &Config,
+        docs: I,
+    ) -> Self {
+        let mut tags = BTreeMap::new();
+        let mut titles = BTreeMap::new();
+        let mut series: HashMap<String, Vec<(u32, Date)>> = HashMap::new();
+        let mut series_names = HashMap::new();
+        for (date, doc) in docs {
+            if let Some(title) = doc.title() {
+                titles.insert(date.clone(), title.to_string());
+            }
+            if let Some((name, part)) = doc.series() {
+                series.entry(name.to_string()).or_default().push((part, date.clone()));
+                series_names.insert(date.clone(), name.to_string());
+            }
+            tags.insert(date, doc.tags().into_iter().map(str::to_string).collect());
+        }
+        Self {
+            related: related_entries(&tags, config.related_entries()),
+            slugs: if config.slug_anchors() {
+                entry_slugs(&titles)
+            } else {
+                HashMap::new()
+            },
+            series: series
+                .into_iter()
+                .map(|(name, mut parts)| {
+                    parts.sort();
+                    (name, parts)
+                })
+                .collect(),
+            series_names,
+        }
+    }
+
+    pub fn related(&self, date: &Date) -> &[Date] {
+        self.related.get(date).map_or(&[], Vec::as_slice)
+    }
+
+    /// The name of the series of an entry, with every part of it in order.
+    pub fn series(&self, date: &Date) -> Option<(&str, &[(u32, Date)])> {
+        let name = self.series_names.get(date)?;
+        Some((name, self.series[name].as_slice()))
+    }
+
+    /// The `id` of the heading of an entry in its month page.
+    pub fn anchor(&self, date: &Date) -> String {
+        self.slugs
+            .get(date)
+            .cloned()
+            .unwrap_or_else(|| format!("{:02}", date.day()))
+    }
+
+    /// Like `Config::entry_path`, but with the anchor of the entry.
+    pub fn entry_path(&self, config: &Config, year: u32, month: u32, day: u32) -> String {
+        match Date::new(year, month, day) {
+            Some(date) if config.page_granularity() == PageGranularity::Month => {
+                format!("/{:04}/{:02}#{}", year, month, self.anchor(&date))
+            }
+            _ => config.entry_path(year, month, day),
+        }
+    }
+}
+
+/// Slugs of the titles which have one, unique within each month.
+fn entry_slugs(titles: &BTreeMap<Date, String>) -> HashMap<Date, String> {
+    let mut taken = HashSet::new();
+    let mut result = HashMap::new();
+    for (date, title) in titles {
+        let Some(mut slug) = slug(title) else { continue };
+        if !taken.insert((date.year(), date.month(), slug.clone())) {
+            slug = format!("{}-{:02}", slug, date.day());
+        }
+        result.insert(date.clone(), slug);
+    }
+    result
+}
+
+/// The ASCII letters and digits of `title` in lowercase, with runs of other
+/// characters in between replaced with `-`. Titles without letters have
+/// none, since a number would collide with the anchors of other days.
+fn slug(title: &str) -> Option<String> {
+    let mut slug = String::new();
+    for c in title.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    Some(slug.to_string()).filter(|slug| slug.chars().any(|c| c.is_ascii_alphabetic()))
+}
+
+/// For each entry, up to `limit` other entries sharing the most tags with it.
+/// Ties are broken by recency.
+fn related_entries(
+    tags: &BTreeMap<Date, Vec<String>>,
+    limit: usize,
+) -> HashMap<Date, Vec<Date>> {
+    let mut result = HashMap::new();
+    if limit == 0 {
+        return result;
+    }
+
+    let mut tag_to_dates: HashMap<&str, Vec<&Date>> = HashMap::new();
+    for (date, entry_tags) in tags {
+        for tag in entry_tags {
+            tag_to_dates.entry(tag).or_default().push(date);
+        }
+    }
+
+    for (date, entry_tags) in tags {
+        let mut scores: HashMap<&Date, usize> = HashMap::new();
+        for tag in entry_tags {
+            for &other in &tag_to_dates[tag.as_str()] {
+                if other != date {
+                    *scores.entry(other).or_default() += 1;
+                }
+            }
+        }
+        let mut scores = scores.into_iter().collect::<Vec<_>>();
+        scores.sort_by(|(a_date, a_score), (b_date, b_score)| {
+            b_score.cmp(a_score).then_with(|| b_date.cmp(a_date))
+        });
+        let related = scores
+            .into_iter()
+            .take(limit)
+            .map(|(date, _)| date.clone())
+            .collect::<Vec<_>>();
+        if !related.is_empty() {
+            result.insert(date.clone(), related);
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::{
+        date::Date,
+        site::{related_entries, slug},
+    };
+
+    fn tags(tags: &[&str]) -> Vec<String> {
+        tags.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn rank_related_entries() {
+        let base = Date::new(2023, 5, 1).unwrap();
+        let two_shared = Date::new(2023, 5, 2).unwrap();
+        let one_shared = Date::new(2023, 5, 3).unwrap();
+        let one_shared_old = Date::new(2023, 4, 3).unwrap();
+        let unrelated = Date::new(2023, 5, 4).unwrap();
+        let entries = BTreeMap::from([
+            (base.clone(), tags(&["trip", "food", "rain"])),
+            (two_shared.clone(), tags(&["trip", "food"])),
+            (one_shared.clone(), tags(&["rain"])),
+            (one_shared_old.clone(), tags(&["trip"])),
+            (unrelated, tags(&["work"])),
+        ]);
+
+        let related = related_entries(&entries, 3);
+        assert_eq!(related[&base], vec![two_shared, one_shared, one_shared_old]);
+
+        let related = related_entries(&entries, 1);
+        assert_eq!(related[&base].len(), 1);
+        assert!(related_entries(&entries, 0).is_empty());
+    }
+
+    #[test]
+    fn slugs_from_titles() {
+        assert_eq!(slug("Trip to Kyoto!").as_deref(), Some("trip-to-kyoto"));
+        assert_eq!(slug("  C++ & Rust ").as_deref(), Some("c-rust"));
+        assert_eq!(slug("京都 2023").as_deref(), None);
+        assert_eq!(slug("日記").as_deref(), None);
+    }
+}
@@ -0,0 +1,114 @@
+use std::{
+    fs::{self, metadata, DirEntry},
+    path::{Path, PathBuf},
+};
+
+use crate::{date::Date, report::BuildReport, Error, Result};
+
+/// The dates of every entry under `input_dir` with their files, sorted, found
+/// from the `YYYY/MM/DD.*` layout of the source tree alone. The files are not
+/// read, so this is a cheap pre-pass before parsing. Top-level entries which
+/// aren't numbered directories, such as `public`, are left out; months and
+/// days whose path isn't a valid date, such as `2023/02/30.lisp`, are skipped
+/// with a warning as they are misfiled, and other names inside a year are
+/// errors.
+pub fn collect_dates(
+    input_dir: &Path,
+    report: &mut BuildReport,
+) -> Result<Vec<(Date, PathBuf)>> {
+    let mut dates = Vec::new();
+    let year_list = fs::read_dir(input_dir).map_err(Error::IOError)?;
+    for year_dir in year_list.filter_map(|res| res.ok()) {
+        let metadata = metadata(year_dir.path()).map_err(Error::IOError)?;
+        if metadata.is_file() || year_dir.file_name() == "public" {
+            continue;
+        }
+        let Ok(year) = path_name_to_usize(&year_dir) else { continue };
+        let year = year as u32;
+
+        let month_list = fs::read_dir(year_dir.path()).map_err(Error::IOError)?;
+        for month_dir in month_list.filter_map(|res| res.ok()) {
+            let day_list = fs::read_dir(month_dir.path()).map_err(Error::IOError)?;
+            let month = path_name_to_usize(&month_dir)?;
+            if !(1..=12).contains(&month) {
+                report.warn(format!(
+                    "Skipping {}/{:02}, which is not a valid month",
+                    year, month
+                ));
+                continue;
+            }
+
+            for day in day_list.filter_map(|res| res.ok()) {
+                let day_num = path_name_to_usize(&day)?;
+                let Some(date) = Date::new(year, month as u32, day_num as u32) else {
+                    report.warn(format!(
+                        "Skipping {}/{:02}/{:02}, which is not a valid date",
+                        year, month, day_num
+                    ));
+                    continue;
+                };
+                dates.push((date, day.path()));
+            }
+        }
+    }
+    dates.sort();
+    Ok(dates)
+}
+
+/// The number a source file or directory is named by, without the extension.
+pub fn path_name_to_usize(entry: &DirEntry) -> Result<usize> {
+    Path::new(&entry.file_name())
+        .file_stem()
+        .ok_or_else(|| path_name_err(entry))
+        .and_then(|s| {
+            s.to_str()
+                .and_then(|s| s.parse::<usize>().ok())
+                .ok_or_else(|| path_name_err(entry))
+        })
+}
+
+fn path_name_err(day: &DirEntry) -> Error {
+    let path = day
+        .path()
+        .as_path()
+        .to_str()
+        .map_or("".to_string(), |s| s.to_string());
+    Error::PathNameError(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        date::Date, report::BuildReport, source_dates::collect_dates, test_util::TempDir, Error,
+    };
+
+    #[test]
+    fn collect_valid_dates() {
+        let dir = TempDir::new();
+        for path in [
+            "2023/05/06.lisp",
+            "2023/05/01.lisp",
+            "2022/12/31.lisp",
+            "2023/02/30.lisp",
+            "2023/13/01.lisp",
+            "public/2023/05/01.lisp",
+            "README.md",
+        ] {
+            dir.write(path, b"");
+        }
+        let mut report = BuildReport::new();
+        let dates = collect_dates(dir.path(), &mut report)
+            .unwrap()
+            .into_iter()
+            .map(|(date, _)| date)
+            .collect::<Vec<_>>();
+        let expected = [(2022, 12, 31), (2023, 5, 1), (2023, 5, 6)]
+            .map(|(y, m, d)| Date::new(y, m, d).unwrap());
+        assert_eq!(dates, expected);
+        assert_eq!(report.warnings.len(), 2);
+
+        dir.write("2023/05/notes.txt", b"");
+        let result = collect_dates(dir.path(), &mut BuildReport::new());
+        assert!(matches!(result, Err(Error::PathNameError(_))));
+    }
+}
@@ -1,18 +1,34 @@
-use std::io::{self, Bytes, Read};
+use std::io::{self, Bytes, Chain, Cursor, Read};
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
 
 pub struct StringReader<R: Read> {
-    bytes: Bytes<R>,
+    bytes: Bytes<Chain<Cursor<Vec<u8>>, R>>,
     chr: Option<u8>,
+    /// The 1-based line and column, in characters, of `chr`.
+    line: usize,
+    column: usize,
 }
 
 impl<R: Read> StringReader<R> {
-    pub fn new(raw_read: R) -> io::Result<Option<Self>> {
-        let mut bytes = raw_read.bytes();
+    /// Skips a leading UTF-8 byte order mark, if any.
+    pub fn new(mut raw_read: R) -> io::Result<Option<Self>> {
+        let mut head = Vec::with_capacity(UTF8_BOM.len());
+        raw_read
+            .by_ref()
+            .take(UTF8_BOM.len() as u64)
+            .read_to_end(&mut head)?;
+        if head == UTF8_BOM {
+            head.clear();
+        }
+        let mut bytes = Cursor::new(head).chain(raw_read).bytes();
         bytes.next().map_or(Ok(None), |chr| {
             let chr = chr?;
             Ok(Some(Self {
                 bytes,
                 chr: Some(chr),
+                line: 1,
+                column: 1,
             }))
         })
     }
@@ -21,7 +37,16 @@ impl<R: Read> StringReader<R> {
         self.chr
     }
 
+    /// The line and column of the current character.
+    pub const fn position(&self) -> (usize, usize) {
+        (self.line, self.column)
+    }
+
     pub fn seek(&mut self) -> io::Result<()> {
+        if self.chr == Some(b'\n') {
+            self.line += 1;
+            self.column = 0;
+        }
         self.chr = match self.bytes.next() {
             Some(res) => {
                 let chr = res?;
@@ -29,6 +54,28 @@ impl<R: Read> StringReader<R> {
             }
             None => None,
         };
+        // Continuation bytes belong to the character already counted.
+        if self.chr.is_some_and(|chr| chr & 0xC0 != 0x80) {
+            self.column += 1;
+        }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::string_reader::StringReader;
+
+    #[test]
+    fn track_position() {
+        let mut reader = StringReader::new("\u{feff}a\nあb".as_bytes()).unwrap().unwrap();
+        let mut positions = Vec::new();
+        while reader.chr().is_some() {
+            positions.push(reader.position());
+            reader.seek().unwrap();
+        }
+        let ascii = [(1, 1), (1, 2)];
+        let multibyte = [(2, 1), (2, 1), (2, 1)];
+        assert_eq!(positions, [&ascii[..], &multibyte, &[(2, 2)]].concat());
+    }
+}
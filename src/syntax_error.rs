@@ -1,9 +1,23 @@
+use crate::date::Date;
+
 #[derive(Debug)]
 pub enum Error {
     IllegalElement,
     MissingOperator,
     UnknownOperator(String),
     OperandMismatch,
+    DisallowedScheme(String),
+    /// `()` in place of an item; holds the line and column of its `(`.
+    EmptyForm(usize, usize),
+    /// A date which doesn't have exactly three components; holds how many it has.
+    DateComponents(usize),
+    InvalidDate(u32, u32, u32),
+    DuplicateEntry(Date),
+    InvalidLanguageTag(String),
+    /// A latitude outside ±90 or a longitude outside ±180 degrees.
+    CoordinatesOutOfRange(f64, f64),
+    /// A time of day which isn't `HH:MM` between `00:00` and `23:59`.
+    InvalidTime(String),
 }
 
 pub type ParseResult<T> = Result<T, Error>;
@@ -0,0 +1,176 @@
+use std::io::{self, ErrorKind, Read, Write};
+
+const BLOCK_SIZE: usize = 512;
+const NAME_LEN: usize = 100;
+const PREFIX_LEN: usize = 155;
+
+/// Writes an uncompressed ustar archive entry by entry, so that a site can be
+/// streamed into it as it is built.
+pub struct TarWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> TarWriter<W> {
+    pub const fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    /// Adds a directory at `path`, which is relative and has no trailing `/`.
+    pub fn append_dir(&mut self, path: &str, mtime: u64) -> io::Result<()> {
+        let path = format!("{}/", path);
+        self.writer.write_all(&header(&path, 0, mtime, b'5')?)
+    }
+
+    /// Adds a file at `path` with `len` bytes read from `content`.
+    pub fn append_file<R: Read>(
+        &mut self,
+        path: &str,
+        len: u64,
+        mtime: u64,
+        content: R,
+    ) -> io::Result<()> {
+        self.writer.write_all(&header(path, len, mtime, b'0')?)?;
+        // The header has promised `len` bytes, so a file which changes
+        // meanwhile would corrupt the rest of the archive.
+        let written = io::copy(&mut content.take(len), &mut self.writer)?;
+        if written < len {
+            return Err(io::Error::new(
+                ErrorKind::UnexpectedEof,
+                format!("{} shrank while being archived", path),
+            ));
+        }
+        let padding = (BLOCK_SIZE as u64 - len % BLOCK_SIZE as u64) % BLOCK_SIZE as u64;
+        self.writer.write_all(&vec![0; padding as usize])
+    }
+
+    /// Ends the archive with two zero-filled blocks and flushes it.
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.writer.write_all(&[0; BLOCK_SIZE * 2])?;
+        self.writer.flush()
+    }
+}
+
+fn header(path: &str, size: u64, mtime: u64, kind: u8) -> io::Result<[u8; BLOCK_SIZE]> {
+    let (prefix, name) = split_path(path).ok_or_else(|| {
+        io::Error::new(ErrorKind::InvalidInput, format!("Path too long for tar: {}", path))
+    })?;
+    let mut block = [0; BLOCK_SIZE];
+    block[..name.len()].copy_from_slice(name.as_bytes());
+    let mode: &[u8] = if kind == b'5' { b"0000755" } else { b"0000644" };
+    block[100..107].copy_from_slice(mode);
+    block[108..115].copy_from_slice(b"0000000");
+    block[116..123].copy_from_slice(b"0000000");
+    numeric_field(&mut block[124..136], size);
+    numeric_field(&mut block[136..148], mtime);
+    block[156] = kind;
+    block[257..263].copy_from_slice(b"ustar\0");
+    block[263..265].copy_from_slice(b"00");
+    block[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+    // The checksum is computed with its own field filled with spaces.
+    block[148..156].copy_from_slice(b"        ");
+    let checksum = block.iter().map(|&b| b as u32).sum::<u32>();
+    block[148..155].copy_from_slice(format!("{:06o}\0", checksum).as_bytes());
+    Ok(block)
+}
+
+/// Writes `value` as a NUL-terminated octal number, or in the base-256 form of
+/// GNU tar if it doesn't fit, such as the size of a file of 8 GiB or more.
+fn numeric_field(field: &mut [u8], value: u64) {
+    let digits = field.len() - 1;
+    if value < 1 << (3 * digits) {
+        field[..digits].copy_from_slice(format!("{:0width$o}", value, width = digits).as_bytes());
+        field[digits] = 0;
+    } else {
+        field.fill(0);
+        let bytes = value.to_be_bytes();
+        let len = field.len();
+        field[len - bytes.len()..].copy_from_slice(&bytes);
+        field[0] = 0x80;
+    }
+}
+
+/// Splits `path` into the prefix and the name fields of a header, at a `/`
+/// if it doesn't fit in the name alone.
+fn split_path(path: &str) -> Option<(&str, &str)> {
+    if path.len() <= NAME_LEN {
+        return Some(("", path));
+    }
+    path.trim_end_matches('/')
+        .match_indices('/')
+        .map(|(i, _)| (&path[..i], &path[i + 1..]))
+        .find(|(prefix, name)| prefix.len() <= PREFIX_LEN && name.len() <= NAME_LEN)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::io::ErrorKind;
+
+    use crate::{
+        tar::{header, split_path, TarWriter, BLOCK_SIZE},
+    };
+
+    /// The path and the content of each entry of an archive written by `TarWriter`.
+    pub fn entries(archive: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let field = |block: &[u8]| {
+            let end = block.iter().position(|&b| b == 0).unwrap_or(block.len());
+            String::from_utf8(block[..end].to_vec()).unwrap()
+        };
+        let mut result = Vec::new();
+        let mut blocks = archive.chunks(BLOCK_SIZE);
+        while let Some(block) = blocks.next().filter(|block| block.iter().any(|&b| b != 0)) {
+            let (prefix, name) = (field(&block[345..500]), field(&block[..100]));
+            let path = if prefix.is_empty() { name } else { format!("{}/{}", prefix, name) };
+            let size = usize::from_str_radix(&field(&block[124..136]), 8).unwrap();
+            let content = blocks
+                .by_ref()
+                .take(size.div_ceil(BLOCK_SIZE))
+                .flatten()
+                .take(size)
+                .copied()
+                .collect();
+            result.push((path, content));
+        }
+        result
+    }
+
+    #[test]
+    fn append_entries() {
+        let mut buf = Vec::new();
+        let mut tar = TarWriter::new(&mut buf);
+        tar.append_dir("a", 0).unwrap();
+        tar.append_file("a/c.txt", 600, 0, &[b'x'; 600][..]).unwrap();
+        tar.append_file("b.txt", 3, 0, &b"bee"[..]).unwrap();
+        tar.finish().unwrap();
+        assert_eq!(buf.len() % BLOCK_SIZE, 0);
+        assert_eq!(
+            entries(&buf),
+            [
+                ("a/".to_string(), vec![]),
+                ("a/c.txt".to_string(), vec![b'x'; 600]),
+                ("b.txt".to_string(), b"bee".to_vec()),
+            ]
+        );
+
+        let shrunk = TarWriter::new(Vec::new()).append_file("d.txt", 4, 0, &b"dee"[..]);
+        assert_eq!(shrunk.unwrap_err().kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn header_checksum() {
+        let block = header("index.html", 5, 0, b'0').unwrap();
+        assert_eq!(&block[148..156], b"007735\0 ");
+        assert_eq!(&block[124..136], b"00000000005\0");
+
+        // Sizes of 8 GiB and more don't fit in octal.
+        let block = header("index.html", 8 << 30, 0, b'0').unwrap();
+        assert_eq!(&block[124..136], &[0x80, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn split_long_paths() {
+        let long = format!("{}/{}", "d".repeat(60), "f".repeat(60));
+        assert_eq!(split_path("a/b.html"), Some(("", "a/b.html")));
+        assert_eq!(split_path(&long), Some((&long[..60], &long[61..])));
+        assert_eq!(split_path(&"f".repeat(101)), None);
+    }
+}
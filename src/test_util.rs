@@ -0,0 +1,66 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::config::Config;
+
+/// A lossless 1x1 WebP image.
+pub const TINY_WEBP: &[u8] = &[
+    0x52, 0x49, 0x46, 0x46, 0x1a, 0x00, 0x00, 0x00, 0x57, 0x45, 0x42, 0x50, 0x56, 0x50, 0x38,
+    0x4c, 0x0d, 0x00, 0x00, 0x00, 0x2f, 0x00, 0x00, 0x00, 0x10, 0x07, 0x10, 0x11, 0x11, 0x88,
+    0x88, 0xfe, 0x07, 0x00,
+];
+
+/// The default config with `settings` set as if by the config file.
+pub fn config_with(settings: &[(&str, &str)]) -> Config {
+    let map = settings.iter().map(|&(key, value)| (key.to_string(), value.to_string()));
+    Config::from_map(map.collect()).unwrap()
+}
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A directory under the system temp dir which is removed on drop.
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new() -> Self {
+        let mut path = env::temp_dir();
+        path.push(format!(
+            "shdiary-gen-test-{}-{}",
+            process::id(),
+            COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn join(&self, elem: &str) -> PathBuf {
+        self.path.join(elem)
+    }
+
+    /// Writes `content` to `elem`, creating parent directories as needed.
+    pub fn write(&self, elem: &str, content: &[u8]) -> PathBuf {
+        let path = self.join(elem);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).unwrap();
+        }
+        fs::write(&path, content).unwrap();
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
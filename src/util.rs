@@ -1,17 +1,151 @@
 use std::{
     fs::File,
-    io::{self, Read},
+    io::{self, Read, Write},
+    ops::Range,
     path::PathBuf,
 };
 
 use xxhash_rust::xxh3::Xxh3;
 
+use crate::config::LineEnding;
+
 pub fn push_path(origin: &PathBuf, elem: &str) -> PathBuf {
     let mut tmp = origin.clone();
     tmp.push(elem);
     tmp
 }
 
+/// The lowercased scheme of `url`, or `None` for relative URLs. Whitespace and
+/// control characters are ignored as browsers do.
+pub fn url_scheme(url: &str) -> Option<String> {
+    let url = url
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace() && !c.is_ascii_control())
+        .collect::<String>();
+    let (scheme, _) = url.split_once(':')?;
+    let mut chars = scheme.chars();
+    let is_scheme = chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    Some(scheme.to_ascii_lowercase()).filter(|_| is_scheme)
+}
+
+/// The host of an `http` or `https` URL, without the user info and the port.
+pub fn url_host(url: &str) -> Option<&str> {
+    if !matches!(url_scheme(url).as_deref(), Some("http" | "https")) {
+        return None;
+    }
+    let (_, rest) = url.split_once("://")?;
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+    let host = match host.find(']') {
+        // An IPv6 address, whose brackets are kept.
+        Some(end) if host.starts_with('[') => &host[..=end],
+        _ => host.split(':').next().unwrap_or_default(),
+    };
+    Some(host).filter(|host| !host.is_empty())
+}
+
+/// Percent-encodes the ASCII characters of `segment` which would end or
+/// alter a URL path segment, such as spaces, `#`, `?` and `/`. Other
+/// characters, including non-ASCII ones, are kept readable.
+pub fn encode_path_segment(segment: &str) -> String {
+    let mut result = String::with_capacity(segment.len());
+    for c in segment.chars() {
+        if !c.is_ascii()
+            || c.is_ascii_alphanumeric()
+            || matches!(c, '-' | '.' | '_' | '~' | '!' | '$' | '&' | '\'' | '(' | ')')
+            || matches!(c, '*' | '+' | ',' | ';' | '=' | ':' | '@')
+        {
+            result.push(c);
+        } else {
+            result.push_str(&format!("%{:02X}", c as u8));
+        }
+    }
+    result
+}
+
+/// The byte ranges of the bare `http` and `https` URLs in `text`. A URL ends
+/// at whitespace, a non-ASCII character or one of `<>"`, and trailing
+/// punctuation, as well as a `)` without its `(`, is left out of it.
+pub fn bare_urls(text: &str) -> Vec<Range<usize>> {
+    let mut result = Vec::new();
+    let mut from = 0;
+    while let Some(start) = ["http://", "https://"]
+        .iter()
+        .filter_map(|scheme| text[from..].find(scheme))
+        .min()
+        .map(|pos| from + pos)
+    {
+        let len = text[start..]
+            .find(|c: char| {
+                !c.is_ascii() || c.is_ascii_whitespace() || matches!(c, '<' | '>' | '"')
+            })
+            .unwrap_or(text.len() - start);
+        let mut url = &text[start..start + len];
+        loop {
+            let unbalanced =
+                url.ends_with(')') && url.matches('(').count() < url.matches(')').count();
+            if url.ends_with(['.', ',', ';', ':', '!', '?', '\'']) || unbalanced {
+                url = &url[..url.len() - 1];
+            } else {
+                break;
+            }
+        }
+        if !url.ends_with("//") {
+            result.push(start..start + url.len());
+        }
+        from = start + len.max(1);
+    }
+    result
+}
+
+/// Translates every `\n` written to `inner` into the given line ending,
+/// except those already preceded by `\r`.
+pub struct NewlineWriter<W: Write> {
+    inner: W,
+    line_ending: LineEnding,
+    /// Whether the last byte written was `\r`, which a write may split from
+    /// its `\n`.
+    after_cr: bool,
+}
+
+impl<W: Write> NewlineWriter<W> {
+    pub const fn new(inner: W, line_ending: LineEnding) -> Self {
+        Self {
+            inner,
+            line_ending,
+            after_cr: false,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for NewlineWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.line_ending == LineEnding::Lf {
+            return self.inner.write(buf);
+        }
+        for (i, line) in buf.split(|&b| b == b'\n').enumerate() {
+            if i > 0 {
+                self.inner.write_all(if self.after_cr { b"\n" } else { b"\r\n" })?;
+                self.after_cr = false;
+            }
+            self.inner.write_all(line)?;
+            if let Some(&last) = line.last() {
+                self.after_cr = last == b'\r';
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
 const BUFFER_SIZE: usize = 8192;
 
 pub fn calc_hash(src: &PathBuf) -> io::Result<u64> {
@@ -29,3 +163,72 @@ pub fn calc_hash(src: &PathBuf) -> io::Result<u64> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use crate::{
+        config::LineEnding,
+        util::{bare_urls, encode_path_segment, url_host, url_scheme, NewlineWriter},
+    };
+
+    #[test]
+    fn detect_url_scheme() {
+        assert_eq!(url_scheme("https://example.com").as_deref(), Some("https"));
+        assert_eq!(url_scheme(" JavaScript:alert(1)").as_deref(), Some("javascript"));
+        assert_eq!(url_scheme("java\tscript:alert(1)").as_deref(), Some("javascript"));
+        assert_eq!(url_scheme("/2023/05#05"), None);
+        assert_eq!(url_scheme("foo/bar:baz"), None);
+        assert_eq!(url_scheme("#top"), None);
+    }
+
+    #[test]
+    fn extract_url_host() {
+        assert_eq!(url_host("https://example.com/a?b#c"), Some("example.com"));
+        assert_eq!(url_host("http://user@example.com:8080"), Some("example.com"));
+        assert_eq!(url_host("HTTPS://Example.com"), Some("Example.com"));
+        assert_eq!(url_host("mailto:someone@example.com"), None);
+        assert_eq!(url_host("/2023/05#05"), None);
+        assert_eq!(url_host("https://[::1]:8080/"), Some("[::1]"));
+        assert_eq!(url_host("https:///path"), None);
+    }
+
+    #[test]
+    fn find_bare_urls() {
+        let urls = |text: &str| {
+            bare_urls(text).into_iter().map(|range| text[range].to_string()).collect::<Vec<_>>()
+        };
+        assert_eq!(urls("see https://example.com/a?b=1."), ["https://example.com/a?b=1"]);
+        assert_eq!(urls("(http://a.example/x_(y)) と https://b.example"), [
+            "http://a.example/x_(y)",
+            "https://b.example"
+        ]);
+        assert_eq!(urls("見てhttps://example.com。"), ["https://example.com"]);
+        assert!(urls("no links, only http:// and example.com").is_empty());
+    }
+
+    #[test]
+    fn encode_path_segments() {
+        assert_eq!(encode_path_segment("photo-1.webp"), "photo-1.webp");
+        assert_eq!(encode_path_segment("my photo#1?.webp"), "my%20photo%231%3F.webp");
+        assert_eq!(encode_path_segment("100%/a"), "100%25%2Fa");
+        assert_eq!(encode_path_segment("写真.webp"), "写真.webp");
+    }
+
+    #[test]
+    fn translate_newlines() {
+        let mut buf = Vec::new();
+        let mut writer = NewlineWriter::new(&mut buf, LineEnding::Crlf);
+        write!(writer, "a\nb").unwrap();
+        writer.write_all(b"\n\nc").unwrap();
+        assert_eq!(buf, b"a\r\nb\r\n\r\nc");
+
+        let mut buf = Vec::new();
+        let mut writer = NewlineWriter::new(&mut buf, LineEnding::Crlf);
+        writer.write_all(b"a\r\nb\r").unwrap();
+        writer.write_all(b"\nc\r").unwrap();
+        writer.write_all(b"d\n").unwrap();
+        assert_eq!(buf, b"a\r\nb\r\nc\rd\r\n");
+    }
+}